@@ -12,24 +12,61 @@ struct GlobalUniform{
     view_position: vec4<f32>,
     view_proj: mat4x4<f32>,
     ambient: vec4<f32>,
+    // Elapsed and delta seconds
+    time: vec4<f32>,
 }
 
-struct LightUniform{
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+struct Light{
     position: vec3<f32>,
-    color: vec3<f32>,
+    kind: u32,
     direction: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    inner_cos: f32,
+    outer_cos: f32,
+}
+
+struct Lights{
+    count: u32,
+    lights: array<Light>,
 }
 
 @group(0) @binding(0)
 var<uniform> global: GlobalUniform;
 
 @group(0) @binding(1)
-var<uniform> light: LightUniform;
+var<storage, read> lights: Lights;
+
+struct MaterialUniform{
+    base_color: vec4<f32>,
+    specular: vec3<f32>,
+    shininess: f32,
+    flags: u32,
+}
+
+const MATERIAL_TRANSPARENT: u32 = 1u;
+const MATERIAL_UNLIT: u32 = 2u;
+const MATERIAL_NO_SPECULAR: u32 = 4u;
+
+@group(1) @binding(0)
+var<uniform> material: MaterialUniform;
+@group(1) @binding(1)
+var t_diffuse: texture_2d<f32>;
+@group(1) @binding(2)
+var t_normal: texture_2d<f32>;
+@group(1) @binding(3)
+var t_specular: texture_2d<f32>;
 
 struct VertexInput{
     @location(0) position: vec3<f32>,
     @location(1) tex_coords: vec2<f32>,
     @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
 }
 
 struct VertexOutput {
@@ -37,6 +74,8 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) world_normal: vec3<f32>,
     @location(2) world_position: vec3<f32>,
+    @location(3) world_tangent: vec3<f32>,
+    @location(4) world_bitangent: vec3<f32>,
 };
 
 @vertex
@@ -63,22 +102,66 @@ fn vs_main(
     var world_position: vec4<f32> = world_matrix * vec4<f32>(model.position, 1.0);
     out.world_position = world_position.xyz;
     out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = normal_matrix * model.tangent;
+    out.world_bitangent = normal_matrix * model.bitangent;
     return out;
 }
 
-@group(1) @binding(0)
-var t_diffuse: texture_2d<f32>;
 @group(0) @binding(2)
 var s_diffuse: sampler;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Sampled before any branch, texture sampling needs uniform control flow.
     let tex_color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
-    let diffuse_strength = max(dot(in.world_normal, light.direction), 0.0);
-    let diffuse_color = light.color * diffuse_strength;
+    let tex_normal = textureSample(t_normal, s_diffuse, in.tex_coords).xyz * 2.0 - 1.0;
+    let tex_specular = textureSample(t_specular, s_diffuse, in.tex_coords).rgb;
 
-    let ambient_color = global.ambient;
+    let albedo = tex_color * material.base_color;
+    if (material.flags & MATERIAL_UNLIT) != 0u {
+        return albedo;
+    }
 
-    let result = (ambient_color + vec4<f32>(diffuse_color, 1.0)) * tex_color;
-    return result;
-}
\ No newline at end of file
+    let tbn = mat3x3<f32>(
+        normalize(in.world_tangent),
+        normalize(in.world_bitangent),
+        normalize(in.world_normal),
+    );
+    let normal = normalize(tbn * tex_normal);
+    let view_dir = normalize(global.view_position.xyz - in.world_position);
+    let has_specular = (material.flags & MATERIAL_NO_SPECULAR) == 0u;
+
+    var diffuse_color = vec3<f32>(0.0);
+    var specular_color = vec3<f32>(0.0);
+    let count = min(lights.count, arrayLength(&lights.lights));
+    for (var i = 0u; i < count; i++) {
+        let light = lights.lights[i];
+        var light_dir = -light.direction;
+        var attenuation = 1.0;
+        if light.kind != LIGHT_DIRECTIONAL {
+            let to_light = light.position - in.world_position;
+            let distance = length(to_light);
+            light_dir = to_light / max(distance, 0.0001);
+            // Inverse square, smoothly reaching zero at the range
+            let falloff = saturate(1.0 - pow(distance / light.range, 4.0));
+            attenuation = falloff * falloff / (distance * distance + 1.0);
+        }
+        if light.kind == LIGHT_SPOT {
+            attenuation *= smoothstep(light.outer_cos, light.inner_cos, dot(-light_dir, light.direction));
+        }
+
+        let radiance = light.color * attenuation;
+        let n_dot_l = max(dot(normal, light_dir), 0.0);
+        diffuse_color += radiance * n_dot_l;
+        if has_specular && n_dot_l > 0.0 {
+            // Blinn-Phong, the half vector between light and view
+            let half_dir = normalize(light_dir + view_dir);
+            specular_color += radiance * pow(max(dot(normal, half_dir), 0.0), material.shininess);
+        }
+    }
+    specular_color *= material.specular * tex_specular;
+
+    let ambient_color = global.ambient.rgb;
+    let result = (ambient_color + diffuse_color) * albedo.rgb + specular_color;
+    return vec4<f32>(result, albedo.a);
+}
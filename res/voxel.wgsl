@@ -0,0 +1,130 @@
+struct InstanceInput{
+    // Block position of the first block of the chunk, w unused
+    @location(5) chunk_origin: vec4<f32>,
+}
+
+struct GlobalUniform{
+    view_position: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    ambient: vec4<f32>,
+    // Elapsed and delta seconds
+    time: vec4<f32>,
+}
+
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+
+struct Light{
+    position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    range: f32,
+    color: vec3<f32>,
+    inner_cos: f32,
+    outer_cos: f32,
+}
+
+struct Lights{
+    count: u32,
+    lights: array<Light>,
+}
+
+@group(0) @binding(0)
+var<uniform> global: GlobalUniform;
+
+@group(0) @binding(1)
+var<storage, read> lights: Lights;
+
+@group(1) @binding(0)
+var t_blocks: texture_2d_array<f32>;
+@group(1) @binding(1)
+var s_blocks: sampler;
+
+// Light left in a corner surrounded by blocks
+const AO_MIN: f32 = 0.35;
+// Fragments of cutout blocks more transparent than this are discarded
+const ALPHA_CUTOFF: f32 = 0.5;
+
+struct VertexInput{
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) layer: u32,
+    @location(4) ao: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+    @location(3) @interpolate(flat) layer: u32,
+    @location(4) ao: f32,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let world_position = model.position + instance.chunk_origin.xyz;
+
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.clip_position = global.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.world_normal = model.normal;
+    out.layer = model.layer;
+    out.ao = model.ao;
+    return out;
+}
+
+fn shade(in: VertexOutput, albedo: vec4<f32>) -> vec4<f32> {
+    let normal = normalize(in.world_normal);
+    var diffuse_color = vec3<f32>(0.0);
+    let count = min(lights.count, arrayLength(&lights.lights));
+    for (var i = 0u; i < count; i++) {
+        let light = lights.lights[i];
+        var light_dir = -light.direction;
+        var attenuation = 1.0;
+        if light.kind != LIGHT_DIRECTIONAL {
+            let to_light = light.position - in.world_position;
+            let distance = length(to_light);
+            light_dir = to_light / max(distance, 0.0001);
+            // Inverse square, smoothly reaching zero at the range
+            let falloff = saturate(1.0 - pow(distance / light.range, 4.0));
+            attenuation = falloff * falloff / (distance * distance + 1.0);
+        }
+        if light.kind == LIGHT_SPOT {
+            attenuation *= smoothstep(light.outer_cos, light.inner_cos, dot(-light_dir, light.direction));
+        }
+        diffuse_color += light.color * attenuation * max(dot(normal, light_dir), 0.0);
+    }
+
+    // Ambient occlusion darkens all light reaching the corner, not only the ambient term
+    let occlusion = mix(AO_MIN, 1.0, in.ao);
+    let result = (global.ambient.rgb + diffuse_color) * occlusion * albedo.rgb;
+    return vec4<f32>(result, albedo.a);
+}
+
+fn sample_block(in: VertexOutput) -> vec4<f32> {
+    // Layers beyond the array, e.g. before block textures are loaded, use the last one
+    let layer = min(in.layer, textureNumLayers(t_blocks) - 1u);
+    return textureSample(t_blocks, s_blocks, in.tex_coords, layer);
+}
+
+// Opaque and translucent blocks, the latter blended by the pipeline.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shade(in, sample_block(in));
+}
+
+@fragment
+fn fs_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    let albedo = sample_block(in);
+    if albedo.a < ALPHA_CUTOFF {
+        discard;
+    }
+    return shade(in, vec4<f32>(albedo.rgb, 1.0));
+}
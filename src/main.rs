@@ -1,6 +1,39 @@
+use cgmath::{Point3, Vector3};
+use hecs::World;
 use terre_core::app::App;
+use terre_core::asset::{AssetPlugin, HotReloadPlugin};
+use terre_core::render::camera::{ActiveCamera, Projection};
+use terre_core::render::camera::bundle::CameraBundle;
+use terre_core::render::camera::controller::{FlyCamera, FlyCameraPlugin};
+use terre_core::render::pass::phong::PhongPlugin;
+use terre_core::render::pass::voxel::VoxelPlugin;
+use terre_core::schedule::Stage;
+use terre_core::transform::Transform;
+use terre_core::voxel::edit::BlockEditPlugin;
+use terre_core::voxel::registry::BlockRegistryPlugin;
+use terre_core::voxel::streaming::ChunkStreamingPlugin;
+use terre_core::voxel::textures::BlockTexturesPlugin;
+
+/// Where the world is saved, relative to the working directory.
+const SAVE_DIR: &str = "saves/world";
+
+fn spawn_camera(world: &mut World) {
+    let projection = Projection::Perspective { fovy: 70.0, znear: 0.1, zfar: 512.0 };
+    let transform = Transform::looking_at(Vector3::new(0.0, 48.0, 0.0), Point3::new(64.0, 16.0, 64.0), Vector3::unit_y());
+    world.spawn((ActiveCamera, FlyCamera::default(), CameraBundle::new(projection, transform)));
+}
 
 fn main() {
     App::new()
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(HotReloadPlugin)
+        .add_plugin(PhongPlugin::default())
+        .add_plugin(VoxelPlugin::default())
+        .add_plugin(BlockRegistryPlugin { save_dir: Some(SAVE_DIR.to_string()), ..Default::default() })
+        .add_plugin(BlockTexturesPlugin::default())
+        .add_plugin(ChunkStreamingPlugin { save_dir: Some(SAVE_DIR.to_string()), ..Default::default() })
+        .add_plugin(FlyCameraPlugin)
+        .add_plugin(BlockEditPlugin::default())
+        .add_system(Stage::Start, spawn_camera)
         .run();
 }
@@ -0,0 +1,18 @@
+//! Pack an asset directory into an archive read by `PackedAssetIo`.
+//! Place the output as `assets.pak` next to the release executable:
+//! `cargo run -p terre_core --example pack_assets -- res target/release/assets.pak`
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use terre_core::asset::io::PackedAssetIo;
+
+fn main() -> anyhow::Result<()> {
+    let args = std::env::args().collect::<Vec<_>>();
+    let [_, dir, out] = args.as_slice() else {
+        anyhow::bail!("Usage: pack_assets <asset dir> <archive>");
+    };
+    let mut writer = BufWriter::new(File::create(out)?);
+    PackedAssetIo::pack_dir(Path::new(dir), &mut writer)?;
+    println!("Packed {} into {}", dir, out);
+    Ok(())
+}
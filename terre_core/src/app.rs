@@ -1,24 +1,65 @@
-use winit::window::WindowBuilder;
+use winit::window::{CursorGrabMode, Window, WindowBuilder};
 use winit::event::{Event, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use pollster::block_on;
-use crate::ecs::resource::ResManager;
+use hecs::{Entity, EntityBuilder, World};
+use crate::asset::Asset;
+use crate::asset::assets::{AssetEvent, Assets, flush_asset_events, free_unused_assets};
+use crate::asset::gltf_loader::{GltfModelLoader, GltfSceneLoader};
+use crate::asset::loader::{ModelLoader, TextureLoader};
+use crate::asset::server::AssetServer;
+use crate::ecs::event::{clear_events, Events};
+use crate::ecs::removal::Removals;
+use crate::ecs::resource::{ResManager, Resource};
 use crate::ecs::system::IntoSystem;
-use crate::render::RenderState;
+use crate::input::{clear_frame_input, CursorGrab, handle_device_event, handle_window_event, KeyInput, MouseButtonInput, MouseMotion};
+use crate::render::{ClearColor, RenderDevice, RenderState};
+use crate::render::camera::{Camera, extract_cameras};
+use crate::render::extract::{extract_assets, extract_resource, ExtractSchedule, RenderWorld};
+use crate::render::light::{AmbientLight, extract_lights};
+use crate::render::graph::RenderGraph;
+use crate::render::RenderContext;
+use crate::render::work::extract_renderers;
 use crate::schedule::{GameSchedule, Stage};
+use crate::time::{Time, update_time};
+
+type RenderGraphSetup = Box<dyn FnOnce(&mut RenderGraph, &RenderContext) -> anyhow::Result<()>>;
 
 pub struct App {
     world: hecs::World,
     res_manager: ResManager,
     schedule: GameSchedule,
+    extract_schedule: ExtractSchedule,
+    render_world: RenderWorld,
+    render_graph_setups: Vec<RenderGraphSetup>,
 }
 
 impl App {
     pub fn new() -> Self {
+        let mut res_manager = ResManager::new();
+        res_manager.push_res(Removals::new()).unwrap();
+        res_manager.push_res(Time::new()).unwrap();
+        res_manager.push_res(KeyInput::new()).unwrap();
+        res_manager.push_res(MouseButtonInput::new()).unwrap();
+        res_manager.push_res(MouseMotion::default()).unwrap();
+        res_manager.push_res(CursorGrab::default()).unwrap();
+        let mut schedule = GameSchedule::new();
+        schedule.add_system(Stage::First, update_time);
+        let mut extract_schedule = ExtractSchedule::new();
+        extract_schedule.add_extract(extract_renderers);
+        extract_schedule.add_extract(extract_lights);
+        extract_schedule.add_extract(extract_cameras);
+        extract_schedule.add_extract(extract_resource::<Camera>);
+        extract_schedule.add_extract(extract_resource::<ClearColor>);
+        extract_schedule.add_extract(extract_resource::<AmbientLight>);
+        extract_schedule.add_extract(extract_resource::<Time>);
         App {
-            schedule: GameSchedule::new(),
+            schedule,
+            extract_schedule,
             world: hecs::World::new(),
-            res_manager: ResManager::new(),
+            res_manager,
+            render_world: RenderWorld::new(),
+            render_graph_setups: vec![],
         }
     }
 
@@ -26,6 +67,59 @@ impl App {
         self.schedule.add_system(stage, function);
         self
     }
+    /// Resources already added are kept, and a warning is logged.
+    pub fn add_resource<T>(mut self, resource: T) -> Self where T: Resource {
+        if let Err(err) = self.res_manager.push_res(resource) {
+            log::warn!("{}", err);
+        }
+        self
+    }
+
+    /// Add [`Events<T>`](Events), which are cleared at the start of each frame.
+    pub fn add_event<T>(self) -> Self where T: 'static {
+        if self.res_manager.contains_res::<Events<T>>() {
+            return self;
+        }
+        self.add_resource(Events::<T>::new())
+            .add_system(Stage::First, clear_events::<T>)
+    }
+
+    /// Add storage [`Assets<T>`](Assets) which unloads assets without strong handles each frame,
+    /// its [`AssetEvent<T>`](AssetEvent) stream, and its [`RenderAssets`](crate::render::extract::RenderAssets)
+    /// in the [`RenderWorld`].
+    pub fn add_asset<T>(self) -> Self where T: Asset {
+        if self.res_manager.contains_res::<Assets<T>>() {
+            return self;
+        }
+        self.add_resource(Assets::<T>::new())
+            .add_event::<AssetEvent<T>>()
+            .add_system(Stage::AssetUpload, flush_asset_events::<T>)
+            .add_system(Stage::PostUpdate, free_unused_assets::<T>)
+            .add_extract(extract_assets::<T>)
+    }
+
+    /// See [`GameSchedule#add_despawn_hook`](GameSchedule::add_despawn_hook).
+    pub fn add_despawn_hook(mut self, hook: impl FnMut(Entity, &EntityBuilder, &mut World, &mut ResManager) + 'static) -> Self {
+        self.schedule.add_despawn_hook(hook);
+        self
+    }
+    /// Copy what custom render nodes need from the game into the [`RenderWorld`] after every update,
+    /// e.g. [`extract_component`](crate::render::extract::extract_component) or [`extract_resource`].
+    pub fn add_extract(mut self, extract: impl FnMut(&World, &ResManager, &mut RenderWorld) + 'static) -> Self {
+        self.extract_schedule.add_extract(extract);
+        self
+    }
+
+    /// Add nodes to the [`RenderGraph`] once the device exists, in the order this is called.
+    /// Errors are logged and leave the nodes added so far.
+    pub fn configure_render_graph(
+        mut self,
+        setup: impl FnOnce(&mut RenderGraph, &RenderContext) -> anyhow::Result<()> + 'static,
+    ) -> Self {
+        self.render_graph_setups.push(Box::new(setup));
+        self
+    }
+
     pub fn add_plugin(self, plugin: impl Plugin + 'static) -> Self {
         plugin.build(self)
     }
@@ -42,8 +136,26 @@ impl App {
                 .unwrap(),
         ));
 
+        for setup in self.render_graph_setups.drain(..) {
+            if let Err(err) = setup(&mut state.render_graph, &state.render_context) {
+                log::error!("Failed to set up the render graph: {}", err);
+            }
+        }
+        let device = RenderDevice { device: state.render_context.device.clone(), queue: state.render_context.queue.clone() };
+        if let Err(err) = self.res_manager.push_res(device) {
+            log::error!("Failed to add the render device: {}", err);
+        }
+        if let Some(mut server) = self.res_manager.get_res_mut::<AssetServer>() {
+            let context = &state.render_context;
+            server.add_loader(ModelLoader::new(context.device.clone(), context.queue.clone()));
+            server.add_loader(TextureLoader::new(context.device.clone(), context.queue.clone()));
+            server.add_loader(GltfModelLoader::new(context.device.clone(), context.queue.clone()));
+            server.add_loader(GltfSceneLoader::new(context.device.clone(), context.queue.clone()));
+        }
+
         //run all starts system
         self.schedule.run_starts(&mut self.world, &mut self.res_manager);
+        let mut cursor_grabbed = false;
 
         event_loop.run(move |event, _, control_flow| {
             match event {
@@ -53,6 +165,7 @@ impl App {
                 } if window_id == state.window.id() => {
                     // let egui_renderer = runtime.res_manager.get_res_mut::<EguiRenderer>();
                     // egui_renderer.unwrap().handle_event(event);
+                    handle_window_event(&self.res_manager, event);
                     match event {
                         WindowEvent::KeyboardInput {
                             input,
@@ -64,31 +177,50 @@ impl App {
                             // state.input.cursor_position = vec2(position.x, position.y);
                         }
                         WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                        WindowEvent::Resized(physical_size) => {
-                            // runtime.resize(physical_size.clone())
-                        }
-                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                            // new_inner_size is &&mut so w have to dereference it twice
-                            // runtime.resize((*new_inner_size).clone())
-                        }
+                        WindowEvent::Resized(physical_size) => state.resize(*physical_size),
+                        WindowEvent::ScaleFactorChanged { new_inner_size, .. } => state.resize(**new_inner_size),
                         _ => {}
                     }
                 }
                 Event::RedrawRequested(window_id) if window_id == state.window.id() => {
                     // run logic
                     self.schedule.run_updates(&mut self.world, &mut self.res_manager);
-                    // run render todo split logic and render
-                    state.render_context.render_and_present(&mut self.world, &mut state.pass_queue);
+                    if let Some(grab) = self.res_manager.borrow_res::<CursorGrab>() {
+                        if grab.grabbed != cursor_grabbed {
+                            cursor_grabbed = grab.grabbed;
+                            apply_cursor_grab(&state.window, cursor_grabbed);
+                        }
+                    }
+                    clear_frame_input(&self.res_manager);
+                    // copy what is rendered, then prepare and render the copy
+                    self.extract_schedule.run(&self.world, &self.res_manager, &mut self.render_world);
+                    state.render_context.render_and_present(&self.render_world, &mut state.render_graph);
                 }
+                Event::DeviceEvent { ref event, .. } => handle_device_event(&self.res_manager, event),
                 Event::RedrawEventsCleared => {
                     state.window.request_redraw();
                 }
+                // The process exits right after, without dropping resources
+                Event::LoopDestroyed => self.schedule.run_exits(&mut self.world, &mut self.res_manager),
                 _ => {}
             }
         });
     }
 }
 
+fn apply_cursor_grab(window: &Window, grabbed: bool) {
+    let result = match grabbed {
+        // Not every platform can lock the cursor, confining it works for mouse look as motion is raw
+        true => window.set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined)),
+        false => window.set_cursor_grab(CursorGrabMode::None),
+    };
+    if let Err(err) = result {
+        log::warn!("Failed to change cursor grab: {}", err);
+    }
+    window.set_cursor_visible(!grabbed);
+}
+
 pub trait Plugin {
     fn build(&self, app: App) -> App;
 }
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+use crate::asset::Asset;
+use crate::asset::handle::{Handle, HandleId};
+use crate::ecs::event::Events;
+use crate::ecs::resource::{ResMut, Resource};
+
+/// Changes of [`Assets<T>`](Assets), flushed into [`Events`] in [`Stage::AssetUpload`](crate::schedule::Stage::AssetUpload).
+/// Handles in events are weak.
+pub enum AssetEvent<T> {
+    Created { handle: Handle<T> },
+    Modified { handle: Handle<T> },
+    Removed { handle: Handle<T> },
+}
+
+impl<T> AssetEvent<T> {
+    pub fn handle(&self) -> &Handle<T> {
+        match self {
+            AssetEvent::Created { handle } => handle,
+            AssetEvent::Modified { handle } => handle,
+            AssetEvent::Removed { handle } => handle,
+        }
+    }
+}
+
+impl<T> Clone for AssetEvent<T> {
+    fn clone(&self) -> Self {
+        match self {
+            AssetEvent::Created { handle } => AssetEvent::Created { handle: handle.clone() },
+            AssetEvent::Modified { handle } => AssetEvent::Modified { handle: handle.clone() },
+            AssetEvent::Removed { handle } => AssetEvent::Removed { handle: handle.clone() },
+        }
+    }
+}
+
+impl<T> PartialEq for AssetEvent<T> {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other) && self.handle() == other.handle()
+    }
+}
+
+impl<T> std::fmt::Debug for AssetEvent<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssetEvent::Created { handle } => f.debug_struct("Created").field("handle", handle).finish(),
+            AssetEvent::Modified { handle } => f.debug_struct("Modified").field("handle", handle).finish(),
+            AssetEvent::Removed { handle } => f.debug_struct("Removed").field("handle", handle).finish(),
+        }
+    }
+}
+
+struct Entry<T> {
+    /// Shared with [`RenderAssets`](crate::render::extract::RenderAssets) once extracted.
+    asset: Arc<T>,
+    strong: Weak<HandleId>,
+}
+
+/// Storage of all loaded assets of type `T`, as a resource.
+/// Assets without any strong [`Handle`] left are dropped by [`#free_unused`](Assets::free_unused).
+pub struct Assets<T> {
+    assets: HashMap<HandleId, Entry<T>>,
+    events: Vec<AssetEvent<T>>,
+}
+
+impl<T> Resource for Assets<T> where T: Asset {}
+
+impl<T> Assets<T> where T: Asset {
+    pub fn new() -> Self {
+        Self {
+            assets: HashMap::new(),
+            events: vec![],
+        }
+    }
+
+    pub fn add(&mut self, asset: T) -> Handle<T> {
+        let handle = Handle::new_strong();
+        self.insert(handle.id(), asset, handle.downgrade());
+        handle
+    }
+
+    /// Insert or replace the asset behind `handle`.
+    /// A weak handle can only replace an asset which is still alive, returns `false` otherwise.
+    pub fn set(&mut self, handle: &Handle<T>, asset: T) -> bool {
+        if handle.is_strong() {
+            self.insert(handle.id(), asset, handle.downgrade());
+            return true;
+        }
+        match self.assets.get_mut(&handle.id()) {
+            None => false,
+            Some(entry) => {
+                entry.asset = Arc::new(asset);
+                self.events.push(AssetEvent::Modified { handle: handle.weak() });
+                true
+            }
+        }
+    }
+
+    pub(crate) fn insert(&mut self, id: HandleId, asset: T, strong: Weak<HandleId>) {
+        let handle = Handle::weak_from_id(id);
+        match self.assets.insert(id, Entry { asset: Arc::new(asset), strong }) {
+            None => self.events.push(AssetEvent::Created { handle }),
+            Some(_) => self.events.push(AssetEvent::Modified { handle }),
+        }
+    }
+
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.get_by_id(handle.id())
+    }
+
+    pub fn get_by_id(&self, id: HandleId) -> Option<&T> {
+        self.assets.get(&id).map(|it| &*it.asset)
+    }
+
+    /// Mutable access is reported as [`AssetEvent::Modified`].
+    /// An asset still shared with the render world is copied first, assets which cannot be cloned are replaced
+    /// with [`#set`](Assets::set) instead.
+    pub fn get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T> where T: Clone {
+        let entry = self.assets.get_mut(&handle.id())?;
+        self.events.push(AssetEvent::Modified { handle: handle.weak() });
+        Some(Arc::make_mut(&mut entry.asset))
+    }
+
+    /// The asset of `id` without copying it, for the render world.
+    pub(crate) fn get_shared(&self, id: HandleId) -> Option<Arc<T>> {
+        self.assets.get(&id).map(|it| it.asset.clone())
+    }
+
+    pub fn contains(&self, id: HandleId) -> bool {
+        self.assets.contains_key(&id)
+    }
+
+    /// Get a new strong handle of a living asset.
+    pub fn get_strong(&self, id: HandleId) -> Option<Handle<T>> {
+        self.assets.get(&id)?.strong.upgrade().map(Handle::from_arc)
+    }
+
+    /// The removed asset may still be shared with the render world until the next extract.
+    pub fn remove(&mut self, handle: &Handle<T>) -> Option<Arc<T>> {
+        let entry = self.assets.remove(&handle.id())?;
+        self.events.push(AssetEvent::Removed { handle: handle.weak() });
+        Some(entry.asset)
+    }
+
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (HandleId, &T)> {
+        self.assets.iter().map(|(id, it)| (*id, &*it.asset))
+    }
+
+    /// Drop assets whose strong handles are all gone, returns their ids.
+    pub fn free_unused(&mut self) -> Vec<HandleId> {
+        let unused = self.assets.iter()
+            .filter(|(_, it)| it.strong.strong_count() == 0)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in unused.iter() {
+            self.assets.remove(id);
+            self.events.push(AssetEvent::Removed { handle: Handle::weak_from_id(*id) });
+        }
+        unused
+    }
+
+    pub fn drain_events(&mut self) -> impl Iterator<Item = AssetEvent<T>> + '_ {
+        self.events.drain(..)
+    }
+}
+
+impl<T> Default for Assets<T> where T: Asset {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) fn flush_asset_events<T: Asset>(mut assets: ResMut<Assets<T>>, mut events: ResMut<Events<AssetEvent<T>>>) {
+    events.send_batch(assets.drain_events());
+}
+
+pub(crate) fn free_unused_assets<T: Asset>(mut assets: ResMut<Assets<T>>) {
+    let freed = assets.free_unused();
+    if !freed.is_empty() {
+        log::debug!("Unloaded {} asset(s) of type {}", freed.len(), std::any::type_name::<T>());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::asset::assets::{AssetEvent, Assets};
+
+    #[test]
+    fn test_assets_free_after_last_strong_handle() {
+        let mut assets = Assets::<String>::new();
+        let handle = assets.add("stone".to_string());
+        let cloned = handle.clone();
+        let weak = handle.weak();
+
+        drop(handle);
+        assert!(assets.free_unused().is_empty());
+        assert_eq!(assets.get(&weak).unwrap(), "stone");
+
+        drop(cloned);
+        assert_eq!(assets.free_unused(), vec![weak.id()]);
+        assert!(assets.get(&weak).is_none());
+    }
+
+    #[test]
+    fn test_assets_weak_set_and_upgrade() {
+        let mut assets = Assets::<String>::new();
+        let handle = assets.add("dirt".to_string());
+
+        assert!(assets.set(&handle.weak(), "grass".to_string()));
+        assert_eq!(assets.get(&handle).unwrap(), "grass");
+        assert_eq!(assets.get_strong(handle.id()).unwrap().strong_count(), 2);
+    }
+
+    #[test]
+    fn test_assets_events() {
+        let mut assets = Assets::<String>::new();
+        let handle = assets.add("sand".to_string());
+        assets.set(&handle, "gravel".to_string());
+        let weak = handle.weak();
+        drop(handle);
+        assets.free_unused();
+
+        let events = assets.drain_events().collect::<Vec<_>>();
+        assert_eq!(events, vec![
+            AssetEvent::Created { handle: weak.clone() },
+            AssetEvent::Modified { handle: weak.clone() },
+            AssetEvent::Removed { handle: weak },
+        ]);
+        assert_eq!(assets.drain_events().count(), 0);
+    }
+}
@@ -0,0 +1,367 @@
+use std::sync::Arc;
+use base64::Engine;
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix, Vector3, Vector4};
+use gltf::material::AlphaMode;
+use gltf::mesh::Mode;
+use wgpu::{Device, Queue};
+use crate::asset::assets::Assets;
+use crate::asset::io::{AssetIo, relative_to};
+use crate::asset::scene::{Scene, SceneNode};
+use crate::asset::loader::upload_model;
+use crate::asset::server::AssetLoader;
+use crate::ecs::resource::ResManager;
+use crate::render::material::{Material, MaterialFlags};
+use crate::render::model::{MaterialData, MeshData, Model, ModelData, ModelVertex};
+use crate::render::texture::Texture;
+
+/// A node of the default glTF scene.
+pub struct GltfNode {
+    pub name: Option<String>,
+    /// Index into [`GltfData::nodes`], always lower than the index of this node.
+    pub parent: Option<usize>,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    /// Index into [`GltfData::meshes`].
+    pub mesh: Option<usize>,
+}
+
+impl GltfNode {
+    pub fn local_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// CPU side of a glTF file, decoded by [`load_gltf_data`].
+pub struct GltfData {
+    /// Triangle primitives of each glTF mesh, their materials index into [`#materials`](GltfData::materials).
+    pub meshes: Vec<Vec<MeshData>>,
+    pub materials: Vec<MaterialData>,
+    /// Nodes of the default scene, parents before their children.
+    pub nodes: Vec<GltfNode>,
+}
+
+impl GltfData {
+    pub fn world_matrices(&self) -> Vec<Matrix4<f32>> {
+        let mut matrices: Vec<Matrix4<f32>> = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter() {
+            let parent = node.parent.map_or(Matrix4::identity(), |it| matrices[it]);
+            matrices.push(parent * node.local_matrix());
+        }
+        matrices
+    }
+
+    /// One model with the mesh of every node baked into world space.
+    pub fn flatten(self) -> ModelData {
+        let mut meshes = vec![];
+        for (node, world) in self.nodes.iter().zip(self.world_matrices()) {
+            let Some(mesh) = node.mesh else { continue };
+            let linear = Matrix3::from_cols(world.x.truncate(), world.y.truncate(), world.z.truncate());
+            let normal_matrix = linear.invert().map_or(linear, |it| it.transpose());
+            for primitive in self.meshes[mesh].iter() {
+                let vertices = primitive.vertices.iter().map(|it| {
+                    let position = world * Vector4::new(it.position[0], it.position[1], it.position[2], 1.0);
+                    ModelVertex {
+                        position: position.truncate().into(),
+                        tex_coords: it.tex_coords,
+                        normal: (normal_matrix * Vector3::from(it.normal)).normalize().into(),
+                        tangent: (linear * Vector3::from(it.tangent)).normalize().into(),
+                        bitangent: (linear * Vector3::from(it.bitangent)).normalize().into(),
+                    }
+                }).collect();
+                meshes.push(MeshData {
+                    name: primitive.name.clone(),
+                    vertices,
+                    indices: primitive.indices.clone(),
+                    material: primitive.material,
+                });
+            }
+        }
+        ModelData { meshes, materials: self.materials }
+    }
+
+}
+
+/// Decode a `.gltf` or `.glb` file, with its buffers and images, without touching the GPU.
+/// # Explanation
+/// External buffers and images are resolved relative to the directory of `path`.
+/// Missing normals and tangents are computed, missing texture coordinates are zero,
+/// and primitives without material use an extra white one.
+pub fn load_gltf_data(path: &str, io: &dyn AssetIo) -> anyhow::Result<GltfData> {
+    let gltf = gltf::Gltf::from_slice(&io.read(path)?)?;
+    let document = &gltf.document;
+
+    let buffers = document.buffers().map(|buffer| match buffer.source() {
+        gltf::buffer::Source::Bin => gltf.blob.clone()
+            .ok_or_else(|| anyhow::Error::msg(format!("Missing binary chunk in '{}'", path))),
+        gltf::buffer::Source::Uri(uri) => read_uri(path, uri, io),
+    }).collect::<anyhow::Result<Vec<_>>>()?;
+
+    let images = document.images().map(|image| {
+        let bytes = match image.source() {
+            gltf::image::Source::View { view, .. } => {
+                let buffer = &buffers[view.buffer().index()];
+                buffer[view.offset()..view.offset() + view.length()].to_vec()
+            }
+            gltf::image::Source::Uri { uri, .. } => read_uri(path, uri, io)?,
+        };
+        Ok(image::load_from_memory(&bytes)?)
+    }).collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut materials = document.materials().map(|material| {
+        let pbr = material.pbr_metallic_roughness();
+        let roughness = pbr.roughness_factor().clamp(0.05, 1.0);
+        let mut flags = MaterialFlags::NONE;
+        if material.alpha_mode() == AlphaMode::Blend {
+            flags.insert(MaterialFlags::TRANSPARENT);
+        }
+        MaterialData {
+            name: material.name().map_or_else(|| format!("{} material {}", path, material.index().unwrap_or(0)), str::to_string),
+            base_color: pbr.base_color_factor(),
+            specular: [1.0 - roughness; 3],
+            // Usual mapping of roughness to a Blinn-Phong exponent.
+            shininess: 2.0 / roughness.powi(4) - 2.0,
+            flags,
+            diffuse_texture: pbr.base_color_texture().map(|it| images[it.texture().source().index()].clone()),
+            normal_texture: material.normal_texture().map(|it| images[it.texture().source().index()].clone()),
+            specular_texture: None,
+        }
+    }).collect::<Vec<_>>();
+    let default_material = materials.len();
+
+    let mut needs_default_material = false;
+    let meshes = document.meshes().map(|mesh| {
+        let mut primitives = vec![];
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                log::warn!("Skipping {:?} primitive of mesh {:?} in '{}'", primitive.mode(), mesh.name(), path);
+                continue;
+            }
+            let reader = primitive.reader(|it| buffers.get(it.index()).map(Vec::as_slice));
+            let Some(positions) = reader.read_positions() else {
+                log::warn!("Skipping primitive without positions of mesh {:?} in '{}'", mesh.name(), path);
+                continue;
+            };
+            let positions = positions.collect::<Vec<_>>();
+            let normals = reader.read_normals().map(|it| it.collect::<Vec<_>>());
+            let tangents = reader.read_tangents().map(|it| it.collect::<Vec<_>>());
+            let tex_coords = reader.read_tex_coords(0).map(|it| it.into_f32().collect::<Vec<_>>());
+            let indices = match reader.read_indices() {
+                Some(it) => it.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+
+            let vertices = positions.iter().enumerate().map(|(i, position)| ModelVertex {
+                position: *position,
+                tex_coords: tex_coords.as_ref().map_or([0.0; 2], |it| it[i]),
+                normal: normals.as_ref().map_or([0.0; 3], |it| it[i]),
+                tangent: [0.0; 3],
+                bitangent: [0.0; 3],
+            }).collect();
+            let material = primitive.material().index().unwrap_or_else(|| {
+                needs_default_material = true;
+                default_material
+            });
+
+            let mut data = MeshData {
+                name: mesh.name().map_or_else(|| format!("{} mesh {}", path, mesh.index()), str::to_string),
+                vertices,
+                indices,
+                material,
+            };
+            if normals.is_none() {
+                data.compute_normals();
+            }
+            match tangents {
+                // Only usable with the normals they were made for.
+                Some(tangents) if normals.is_some() => {
+                    for (vertex, tangent) in data.vertices.iter_mut().zip(tangents) {
+                        let normal = Vector3::from(vertex.normal);
+                        let direction = Vector3::new(tangent[0], tangent[1], tangent[2]);
+                        vertex.tangent = direction.into();
+                        vertex.bitangent = (normal.cross(direction) * tangent[3]).into();
+                    }
+                }
+                _ => data.compute_tangents(),
+            }
+            primitives.push(data);
+        }
+        primitives
+    }).collect::<Vec<_>>();
+    if needs_default_material {
+        materials.push(MaterialData::white(&format!("{} default material", path)));
+    }
+
+    let mut nodes = vec![];
+    let scene = document.default_scene().or_else(|| document.scenes().next());
+    let mut stack = scene.iter().flat_map(|it| it.nodes()).map(|it| (it, None)).collect::<Vec<_>>();
+    stack.reverse();
+    while let Some((node, parent)) = stack.pop() {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let index = nodes.len();
+        nodes.push(GltfNode {
+            name: node.name().map(str::to_string),
+            parent,
+            translation: translation.into(),
+            rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+            scale: scale.into(),
+            mesh: node.mesh().map(|it| it.index()),
+        });
+        let mut children = node.children().map(|it| (it, Some(index))).collect::<Vec<_>>();
+        children.reverse();
+        stack.extend(children);
+    }
+
+    Ok(GltfData { meshes, materials, nodes })
+}
+
+/// Bytes of an embedded `data:` uri, or of a file relative to the glTF file.
+fn read_uri(gltf_path: &str, uri: &str, io: &dyn AssetIo) -> anyhow::Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data.split_once(";base64,")
+            .ok_or_else(|| anyhow::Error::msg(format!("Unsupported data uri in '{}'", gltf_path)))?;
+        return Ok(base64::engine::general_purpose::STANDARD.decode(encoded)?);
+    }
+    io.read(&relative_to(gltf_path, &uri.replace("%20", " ")))
+}
+
+/// Loads a glTF file as a single [`Model`], with the node transforms baked into its meshes.
+pub struct GltfModelLoader {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+}
+
+impl GltfModelLoader {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+        Self { device, queue }
+    }
+}
+
+impl AssetLoader for GltfModelLoader {
+    type Asset = Model;
+    type Data = ModelData;
+
+    fn extensions(&self) -> &[&str] {
+        &["gltf", "glb"]
+    }
+
+    fn load(&self, path: &str, io: &dyn AssetIo) -> anyhow::Result<Self::Data> {
+        Ok(load_gltf_data(path, io)?.flatten())
+    }
+
+    fn finish_with(&self, data: Self::Data, res_manager: &ResManager) -> anyhow::Result<Self::Asset> {
+        upload_model(&data, &self.device, &self.queue, res_manager)
+    }
+}
+
+/// Loads a glTF file as a [`Scene`], adding one [`Model`] per glTF mesh to [`Assets<Model>`](Assets),
+/// all sharing the materials of the file.
+pub struct GltfSceneLoader {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+}
+
+impl GltfSceneLoader {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+        Self { device, queue }
+    }
+}
+
+impl AssetLoader for GltfSceneLoader {
+    type Asset = Scene;
+    type Data = GltfData;
+
+    fn extensions(&self) -> &[&str] {
+        &["gltf", "glb"]
+    }
+
+    fn load(&self, path: &str, io: &dyn AssetIo) -> anyhow::Result<Self::Data> {
+        load_gltf_data(path, io)
+    }
+
+    fn finish_with(&self, data: Self::Data, res_manager: &ResManager) -> anyhow::Result<Self::Asset> {
+        let materials = {
+            let mut textures = res_manager.borrow_res_mut::<Assets<Texture>>()
+                .ok_or_else(|| anyhow::Error::msg("Assets<Texture> is not added to the app!"))?;
+            let mut materials = res_manager.borrow_res_mut::<Assets<Material>>()
+                .ok_or_else(|| anyhow::Error::msg("Assets<Material> is not added to the app!"))?;
+            data.materials.iter()
+                .map(|it| Ok(materials.add(it.upload(&self.device, &self.queue, &mut textures)?)))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+        let mut models = res_manager.borrow_res_mut::<Assets<Model>>()
+            .ok_or_else(|| anyhow::Error::msg("Assets<Model> is not added to the app!"))?;
+        let handles = data.meshes.iter().map(|primitives| models.add(Model {
+            meshes: primitives.iter().map(|it| it.upload(&self.device)).collect(),
+            materials: materials.clone(),
+        })).collect::<Vec<_>>();
+
+        let nodes = data.nodes.into_iter().map(|node| SceneNode {
+            name: node.name,
+            parent: node.parent,
+            translation: node.translation,
+            rotation: node.rotation,
+            scale: node.scale,
+            model: node.mesh.map(|it| handles[it].clone()),
+        }).collect();
+        Ok(Scene { nodes })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base64::Engine;
+    use crate::asset::gltf_loader::load_gltf_data;
+    use crate::asset::io::MemoryAssetIo;
+
+    /// A triangle without normals and texture coordinates, used by a child node moved along x.
+    fn triangle_gltf() -> String {
+        let positions = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0];
+        let bytes = positions.iter().flat_map(|it| it.to_le_bytes()).collect::<Vec<_>>();
+        let uri = base64::engine::general_purpose::STANDARD.encode(&bytes);
+        format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [
+                {{ "name": "root", "children": [1] }},
+                {{ "name": "child", "mesh": 0, "translation": [2.0, 0.0, 0.0] }}
+            ],
+            "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }} }}] }}],
+            "accessors": [{{
+                "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                "min": [0.0, 0.0, -1.0], "max": [1.0, 0.0, 0.0]
+            }}],
+            "bufferViews": [{{ "buffer": 0, "byteLength": 36 }}],
+            "buffers": [{{ "byteLength": 36, "uri": "data:application/octet-stream;base64,{}" }}]
+        }}"#, uri)
+    }
+
+    #[test]
+    fn test_load_gltf_hierarchy_and_defaults() {
+        let io = MemoryAssetIo::new().with("models/triangle.gltf", triangle_gltf());
+        let data = load_gltf_data("models/triangle.gltf", &io).unwrap();
+
+        assert_eq!(data.nodes.len(), 2);
+        assert_eq!(data.nodes[1].name.as_deref(), Some("child"));
+        assert_eq!(data.nodes[1].parent, Some(0));
+        assert_eq!(data.nodes[1].mesh, Some(0));
+
+        let mesh = &data.meshes[0][0];
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.vertices[0].normal, [0.0, 1.0, 0.0]);
+        assert_eq!(mesh.vertices[2].tex_coords, [0.0, 0.0]);
+        let tangent = mesh.vertices[0].tangent;
+        assert_eq!(tangent[1], 0.0);
+        assert!((tangent[0] * tangent[0] + tangent[2] * tangent[2] - 1.0).abs() < 1e-5);
+        // Default white material added for the primitive without one.
+        assert_eq!(data.materials.len(), 1);
+        assert_eq!(mesh.material, 0);
+
+        let flat = data.flatten();
+        assert_eq!(flat.meshes.len(), 1);
+        assert_eq!(flat.meshes[0].vertices[1].position, [3.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::{Arc, Weak};
+use uuid::Uuid;
+
+#[derive(PartialEq, Eq, Copy, Clone, Hash, Debug)]
+pub struct HandleId(Uuid);
+
+impl HandleId {
+    pub fn new() -> Self {
+        HandleId(Uuid::new_v4())
+    }
+}
+
+impl Default for HandleId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reference to an asset stored in [`Assets`](crate::asset::assets::Assets).
+/// # Strong and weak
+/// Strong handles share a reference count, the asset is unloaded after the last one is dropped.
+/// Weak handles (see [`#weak`](Handle::weak)) only identify the asset and never keep it alive.
+pub struct Handle<T> {
+    id: HandleId,
+    strong: Option<Arc<HandleId>>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub(crate) fn new_strong() -> Self {
+        let id = HandleId::new();
+        Self::from_arc(Arc::new(id))
+    }
+
+    pub(crate) fn from_arc(strong: Arc<HandleId>) -> Self {
+        Self {
+            id: *strong,
+            strong: Some(strong),
+            marker: PhantomData,
+        }
+    }
+
+    pub fn weak_from_id(id: HandleId) -> Self {
+        Self {
+            id,
+            strong: None,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> HandleId {
+        self.id
+    }
+
+    pub fn is_strong(&self) -> bool {
+        self.strong.is_some()
+    }
+
+    pub fn weak(&self) -> Self {
+        Self::weak_from_id(self.id)
+    }
+
+    /// Count of strong handles sharing this asset, `0` for weak handles.
+    pub fn strong_count(&self) -> usize {
+        self.strong.as_ref().map_or(0, Arc::strong_count)
+    }
+
+    pub(crate) fn downgrade(&self) -> Weak<HandleId> {
+        self.strong.as_ref().map_or_else(Weak::new, Arc::downgrade)
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            strong: self.strong.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl<T> Debug for Handle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Handle")
+            .field("id", &self.id)
+            .field("strong", &self.is_strong())
+            .finish()
+    }
+}
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the asset root of [`FileAssetIo::from_env`].
+pub const ASSET_ROOT_ENV: &str = "TERRE_ASSET_ROOT";
+/// File name of the archive [`default_asset_io`] looks for next to the executable.
+pub const ASSET_PACK_NAME: &str = "assets.pak";
+
+const PACK_MAGIC: &[u8; 4] = b"TPAK";
+const PACK_VERSION: u32 = 1;
+const PACK_ENTRY_MIN_LEN: usize = 4 + 8 + 8;
+
+/// Source of asset files, addressed by paths relative to the asset root with `/` separators.
+/// # Explanation
+/// Loaders only read through this trait, so the same assets can come from a directory while developing,
+/// from memory in tests, or from a single [packed archive](PackedAssetIo) in release builds.
+pub trait AssetIo: Send + Sync + 'static {
+    fn read(&self, path: &str) -> anyhow::Result<Vec<u8>>;
+
+    fn read_to_string(&self, path: &str) -> anyhow::Result<String> {
+        Ok(String::from_utf8(self.read(path)?)?)
+    }
+
+    fn exists(&self, path: &str) -> bool;
+
+    /// Directory to watch for hot reload, `None` if not backed by the filesystem.
+    fn watch_root(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// Pick the asset source of a game:
+/// `$TERRE_ASSET_ROOT` if set, then an [`ASSET_PACK_NAME`] archive next to the executable,
+/// then a `res` directory as resolved by [`FileAssetIo::from_env`].
+pub fn default_asset_io() -> Box<dyn AssetIo> {
+    if std::env::var_os(ASSET_ROOT_ENV).is_none() {
+        if let Some(pack) = exe_dir().map(|it| it.join(ASSET_PACK_NAME)).filter(|it| it.is_file()) {
+            match PackedAssetIo::open(&pack) {
+                Ok(io) => return Box::new(io),
+                Err(err) => log::error!("Failed to open asset archive {:?}, using files instead: {}", pack, err),
+            }
+        }
+    }
+    Box::new(FileAssetIo::from_env())
+}
+
+fn exe_dir() -> Option<PathBuf> {
+    std::env::current_exe().ok()?.parent().map(Path::to_path_buf)
+}
+
+/// `./a\b.png` -> `a/b.png`
+pub(crate) fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/")
+        .split('/')
+        .filter(|it| !it.is_empty() && *it != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Path of `path` referenced from the file at `base`, e.g. a texture of a material library.
+/// `models/cube.obj` + `../textures/stone.png` -> `textures/stone.png`
+pub fn relative_to(base: &str, path: &str) -> String {
+    let base = normalize_path(base);
+    let mut parts = base.split('/').filter(|it| !it.is_empty()).collect::<Vec<_>>();
+    parts.pop();
+    let path = path.replace('\\', "/");
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => { parts.pop(); }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Reads assets from a directory.
+pub struct FileAssetIo {
+    root: PathBuf,
+}
+
+impl FileAssetIo {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Root from `$TERRE_ASSET_ROOT`, or `res` next to the executable, or `res` in the working directory.
+    pub fn from_env() -> Self {
+        if let Some(root) = std::env::var_os(ASSET_ROOT_ENV) {
+            return Self::new(root);
+        }
+        match exe_dir().map(|it| it.join("res")).filter(|it| it.is_dir()) {
+            Some(root) => Self::new(root),
+            None => Self::new("res"),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn full_path(&self, path: &str) -> PathBuf {
+        normalize_path(path).split('/').fold(self.root.clone(), |it, part| it.join(part))
+    }
+}
+
+impl AssetIo for FileAssetIo {
+    fn read(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let full_path = self.full_path(path);
+        fs::read(&full_path).map_err(|err| anyhow::Error::msg(format!("Failed to read asset {:?}: {}", full_path, err)))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.full_path(path).is_file()
+    }
+
+    fn watch_root(&self) -> Option<&Path> {
+        Some(&self.root)
+    }
+}
+
+/// Assets held in memory, mainly for tests.
+#[derive(Default)]
+pub struct MemoryAssetIo {
+    files: HashMap<String, Vec<u8>>,
+}
+
+impl MemoryAssetIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: &str, bytes: impl Into<Vec<u8>>) {
+        self.files.insert(normalize_path(path), bytes.into());
+    }
+
+    pub fn with(mut self, path: &str, bytes: impl Into<Vec<u8>>) -> Self {
+        self.insert(path, bytes);
+        self
+    }
+}
+
+impl AssetIo for MemoryAssetIo {
+    fn read(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        self.files.get(&normalize_path(path))
+            .cloned()
+            .ok_or_else(|| anyhow::Error::msg(format!("Asset '{}' is not in memory", path)))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files.contains_key(&normalize_path(path))
+    }
+}
+
+/// All assets packed into a single archive, written by [`#pack_dir`](PackedAssetIo::pack_dir).
+/// # Explanation
+/// Layout, little endian: `TPAK`, version `u32`, entry count `u32`,
+/// then per entry path length `u32`, path, offset `u64` and size `u64`, then the data of all entries.
+/// Offsets are relative to the start of the data. The whole archive is kept in memory.
+pub struct PackedAssetIo {
+    entries: HashMap<String, (usize, usize)>,
+    data: Vec<u8>,
+}
+
+impl PackedAssetIo {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::from_bytes(fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> anyhow::Result<Self> {
+        let mut reader = PackReader { bytes: &bytes, position: 0 };
+        if reader.take(4)? != PACK_MAGIC {
+            anyhow::bail!("Not an asset archive");
+        }
+        let version = reader.u32()?;
+        if version != PACK_VERSION {
+            anyhow::bail!("Unsupported asset archive version {}", version);
+        }
+
+        let count = reader.u32()? as usize;
+        // An entry takes at least its path length, offset and size, so a corrupt count can't reserve more than the archive holds
+        let mut entries = HashMap::with_capacity(count.min(reader.remaining() / PACK_ENTRY_MIN_LEN));
+        for _ in 0..count {
+            let path_len = reader.u32()? as usize;
+            let path = std::str::from_utf8(reader.take(path_len)?)?.to_string();
+            let offset = usize::try_from(reader.u64()?)?;
+            let size = usize::try_from(reader.u64()?)?;
+            entries.insert(path, (offset, size));
+        }
+
+        let data = bytes[reader.position..].to_vec();
+        let in_bounds = |(offset, size): &(usize, usize)| offset.checked_add(*size).is_some_and(|end| end <= data.len());
+        if let Some((path, _)) = entries.iter().find(|(_, range)| !in_bounds(range)) {
+            anyhow::bail!("Asset archive is truncated at '{}'", path);
+        }
+        Ok(Self { entries, data })
+    }
+
+    /// Pack every file under `dir` into an archive written to `out`.
+    pub fn pack_dir(dir: &Path, out: &mut impl Write) -> anyhow::Result<()> {
+        let mut files = vec![];
+        collect_files(dir, dir, &mut files)?;
+        files.sort();
+
+        let mut contents = Vec::with_capacity(files.len());
+        for (path, full_path) in files {
+            contents.push((path, fs::read(full_path)?));
+        }
+        Self::pack(&contents, out)
+    }
+
+    /// Write `(path, bytes)` entries as an archive.
+    pub fn pack(files: &[(String, Vec<u8>)], out: &mut impl Write) -> anyhow::Result<()> {
+        out.write_all(PACK_MAGIC)?;
+        out.write_all(&PACK_VERSION.to_le_bytes())?;
+        out.write_all(&(files.len() as u32).to_le_bytes())?;
+
+        let mut offset = 0u64;
+        for (path, bytes) in files {
+            let path = normalize_path(path);
+            out.write_all(&(path.len() as u32).to_le_bytes())?;
+            out.write_all(path.as_bytes())?;
+            out.write_all(&offset.to_le_bytes())?;
+            out.write_all(&(bytes.len() as u64).to_le_bytes())?;
+            offset += bytes.len() as u64;
+        }
+        for (_, bytes) in files {
+            out.write_all(bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl AssetIo for PackedAssetIo {
+    fn read(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let (offset, size) = self.entries.get(&normalize_path(path))
+            .ok_or_else(|| anyhow::Error::msg(format!("Asset '{}' is not in the archive", path)))?;
+        Ok(self.data[*offset..offset + size].to_vec())
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.entries.contains_key(&normalize_path(path))
+    }
+}
+
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+            continue;
+        }
+        let relative = path.strip_prefix(root)?
+            .components()
+            .map(|it| it.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push((relative, path));
+    }
+    Ok(())
+}
+
+struct PackReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PackReader<'a> {
+    fn take(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        let end = match self.position.checked_add(len) {
+            Some(end) if end <= self.bytes.len() => end,
+            _ => anyhow::bail!("Asset archive is truncated"),
+        };
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    fn u32(&mut self) -> anyhow::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::asset::io::{AssetIo, MemoryAssetIo, PackedAssetIo, relative_to};
+
+    #[test]
+    fn test_memory_io_normalizes_paths() {
+        let io = MemoryAssetIo::new().with("textures/stone.png", vec![1u8, 2]);
+
+        assert_eq!(io.read("./textures\\stone.png").unwrap(), vec![1, 2]);
+        assert!(io.exists("textures/stone.png"));
+        assert!(io.read("textures/dirt.png").is_err());
+    }
+
+    #[test]
+    fn test_relative_to() {
+        assert_eq!(relative_to("cube.obj", "cube.mtl"), "cube.mtl");
+        assert_eq!(relative_to("models/cube.obj", "./cube.mtl"), "models/cube.mtl");
+        assert_eq!(relative_to("models/cube/cube.obj", "..\\textures/stone.png"), "models/textures/stone.png");
+    }
+
+    #[test]
+    fn test_packed_io_round_trip() {
+        let files = vec![
+            ("shader.wgsl".to_string(), b"@vertex".to_vec()),
+            ("models/cube.obj".to_string(), b"v 0 0 0".to_vec()),
+            ("empty".to_string(), vec![]),
+        ];
+        let mut bytes = vec![];
+        PackedAssetIo::pack(&files, &mut bytes).unwrap();
+
+        let io = PackedAssetIo::from_bytes(bytes.clone()).unwrap();
+        assert_eq!(io.read_to_string("models/cube.obj").unwrap(), "v 0 0 0");
+        assert_eq!(io.read_to_string("shader.wgsl").unwrap(), "@vertex");
+        assert!(io.read("empty").unwrap().is_empty());
+        assert!(!io.exists("cube.obj"));
+
+        bytes.truncate(bytes.len() - 3);
+        assert!(PackedAssetIo::from_bytes(bytes).is_err());
+    }
+
+    #[test]
+    fn test_packed_io_corrupt_header() {
+        let files = vec![("a.txt".to_string(), b"abc".to_vec())];
+        let mut bytes = vec![];
+        PackedAssetIo::pack(&files, &mut bytes).unwrap();
+
+        let mut huge_count = bytes.clone();
+        huge_count[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(PackedAssetIo::from_bytes(huge_count).is_err());
+
+        // Entry: path length at 12, "a.txt" at 16, offset at 21, size at 29
+        let mut overflowing = bytes.clone();
+        overflowing[21..29].copy_from_slice(&u64::MAX.to_le_bytes());
+        overflowing[29..37].copy_from_slice(&2u64.to_le_bytes());
+        assert!(PackedAssetIo::from_bytes(overflowing).is_err());
+
+        let mut huge_path = bytes;
+        huge_path[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(PackedAssetIo::from_bytes(huge_path).is_err());
+    }
+}
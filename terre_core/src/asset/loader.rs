@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use wgpu::{Device, Queue};
+use crate::asset::assets::Assets;
+use crate::asset::io::AssetIo;
+use crate::asset::server::AssetLoader;
+use crate::render::{model, texture};
+use crate::ecs::resource::ResManager;
+use crate::render::material::Material;
+use crate::render::model::NormalMode;
+
+pub struct ModelLoader {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    normals: NormalMode,
+}
+
+impl ModelLoader {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+        Self { device, queue, normals: NormalMode::default() }
+    }
+
+    /// How normals are generated for meshes without them.
+    pub fn with_normals(mut self, normals: NormalMode) -> Self {
+        self.normals = normals;
+        self
+    }
+}
+
+impl AssetLoader for ModelLoader {
+    type Asset = model::Model;
+    type Data = model::ModelData;
+
+    fn extensions(&self) -> &[&str] {
+        &["obj"]
+    }
+
+    fn load(&self, path: &str, io: &dyn AssetIo) -> anyhow::Result<Self::Data> {
+        crate::asset::load_model_data(path, io, self.normals)
+    }
+
+    fn finish_with(&self, data: Self::Data, res_manager: &ResManager) -> anyhow::Result<Self::Asset> {
+        upload_model(&data, &self.device, &self.queue, res_manager)
+    }
+}
+
+pub struct TextureLoader {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+}
+
+impl TextureLoader {
+    pub fn new(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+        Self { device, queue }
+    }
+}
+
+impl AssetLoader for TextureLoader {
+    type Asset = texture::Texture;
+    type Data = (String, image::DynamicImage);
+
+    fn extensions(&self) -> &[&str] {
+        &["png", "jpg", "jpeg"]
+    }
+
+    fn load(&self, path: &str, io: &dyn AssetIo) -> anyhow::Result<Self::Data> {
+        let bytes = io.read(path)?;
+        Ok((path.to_string(), image::load_from_memory(&bytes)?))
+    }
+
+    fn finish(&self, data: Self::Data) -> anyhow::Result<Self::Asset> {
+        texture::Texture::from_image(&self.device, &self.queue, &data.1, Some(&data.0))
+    }
+}
+
+/// Upload `data` with its materials and textures added to their storages.
+pub(crate) fn upload_model(data: &model::ModelData, device: &Device, queue: &Queue, res_manager: &ResManager) -> anyhow::Result<model::Model> {
+    let mut textures = res_manager.borrow_res_mut::<Assets<texture::Texture>>()
+        .ok_or_else(|| anyhow::Error::msg("Assets<Texture> is not added to the app!"))?;
+    let mut materials = res_manager.borrow_res_mut::<Assets<Material>>()
+        .ok_or_else(|| anyhow::Error::msg("Assets<Material> is not added to the app!"))?;
+    data.upload(device, queue, &mut textures, &mut materials)
+}
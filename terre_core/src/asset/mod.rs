@@ -1,101 +1,283 @@
-use std::env;
 use std::io::{BufReader, Cursor};
+use std::sync::Arc;
 use anyhow::*;
 use wgpu::{Device, Queue};
-use wgpu::util::DeviceExt;
+use crate::app::{App, Plugin};
+use crate::asset::io::{AssetIo, default_asset_io, relative_to};
+use crate::asset::scene::{despawn_scene_nodes, Scene, spawn_scenes};
+use crate::asset::server::{AssetServer, free_unused_paths, upload_assets};
 use crate::render::{model, texture};
-use crate::render::model::Material;
+use crate::ecs::resource::ResMut;
+use crate::asset::assets::Assets;
+use crate::render::material::{Material, MaterialFlags};
+use crate::render::model::{MaterialData, MeshData, NormalMode};
+use crate::render::shader::{Shader, ShaderLoader};
+use crate::schedule::Stage;
 
+pub mod io;
+pub mod handle;
+pub mod assets;
+pub mod server;
+pub mod loader;
+pub mod gltf_loader;
+pub mod scene;
+pub mod watcher;
 
-pub async fn load_string(file_name: &str) -> Result<String> {
-    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("res").join(file_name);
-    let txt = std::fs::read_to_string(path)?;
-    Ok(txt)
+/// Types which can be stored in [`Assets`](assets::Assets) and referenced by [`Handle`](handle::Handle).
+pub trait Asset: Send + Sync + 'static {}
+
+impl Asset for String {}
+impl Asset for Vec<u8> {}
+impl Asset for model::Model {}
+impl Asset for texture::Texture {}
+
+/// Adds [`AssetServer`], storages of models, textures, materials, shaders and scenes, and spawns [`SpawnScene`](scene::SpawnScene)s.
+/// Loaders needing the GPU are registered by [`App::run`] once the device is created.
+/// # Usage
+/// [`AssetPlugin::default`] reads from [`default_asset_io`], [`#with_io`](AssetPlugin::with_io) from any [`AssetIo`].
+#[derive(Default)]
+pub struct AssetPlugin {
+    io: Option<Arc<dyn AssetIo>>,
+}
+
+impl AssetPlugin {
+    pub fn with_io(io: impl AssetIo) -> Self {
+        Self { io: Some(Arc::new(io)) }
+    }
+}
+
+impl Plugin for AssetPlugin {
+    fn build(&self, app: App) -> App {
+        let io = self.io.clone().unwrap_or_else(|| default_asset_io().into());
+        let mut server = AssetServer::with_io(io);
+        server.add_loader(ShaderLoader);
+
+        app.add_resource(server)
+            .add_system(Stage::AssetUpload, upload_assets)
+            .add_system(Stage::PostUpdate, free_unused_paths)
+            .add_asset::<model::Model>()
+            .add_asset::<texture::Texture>()
+            .add_asset::<Material>()
+            .add_asset::<Shader>()
+            .add_asset::<Scene>()
+            .add_system(Stage::PreUpdate, spawn_scenes)
+            .add_despawn_hook(despawn_scene_nodes)
+    }
 }
 
-pub async fn load_binary(file_name: &str) -> Result<Vec<u8>> {
-    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("res").join(file_name);
-    let data = std::fs::read(path)?;
-    Ok(data)
+/// Reloads assets when their files change, see [`AssetServer#watch_for_changes`](AssetServer::watch_for_changes).
+/// Needs [`AssetPlugin`] reading from the filesystem.
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: App) -> App {
+        app.add_system(Stage::Start, watch_for_changes)
+    }
+}
+
+fn watch_for_changes(mut server: ResMut<AssetServer>) {
+    if let Err(err) = server.watch_for_changes() {
+        log::error!("Failed to watch asset changes: {}", err);
+    } else {
+        log::info!("Watching asset changes in {:?}", server.io().watch_root().unwrap());
+    }
 }
 
-pub async fn load_texture(
+pub fn load_texture(
     file_name: &str,
+    io: &dyn AssetIo,
     device: &wgpu::Device,
     queue: &wgpu::Queue,
 ) -> anyhow::Result<texture::Texture> {
-    let data = load_binary(file_name).await?;
+    let data = io.read(file_name)?;
     texture::Texture::from_bytes(device, queue, &data, file_name)
 }
 
-pub async fn load_model(
+pub fn load_model(
     file_name: &str,
+    io: &dyn AssetIo,
     device: &Device,
     queue: &Queue,
+    textures: &mut Assets<texture::Texture>,
+    materials: &mut Assets<Material>,
 ) -> anyhow::Result<model::Model> {
-    let obj_text = load_string(file_name).await?;
+    load_model_data(file_name, io, NormalMode::default())?.upload(device, queue, textures, materials)
+}
+
+/// Decode an OBJ model and the textures of its materials without touching the GPU,
+/// so it can run on worker threads.
+/// # Explanation
+/// Only an unreadable OBJ is an error. Missing pieces are logged per mesh and replaced:
+/// texture coordinates by zero, normals by generated ones as `normals` says,
+/// and materials by a white one. Tangents are always computed.
+/// Materials take `Kd`, `Ks`, `Ns`, `d`, `illum` and the `map_Kd`, `map_Bump` and `map_Ks` textures.
+/// Material libraries and textures are resolved relative to the OBJ.
+pub fn load_model_data(file_name: &str, io: &dyn AssetIo, normals: NormalMode) -> anyhow::Result<model::ModelData> {
+    let obj_text = io.read_to_string(file_name)?;
     let obj_cursor = Cursor::new(obj_text);
     let mut obj_reader = BufReader::new(obj_cursor);
 
-    let (models, obj_materials) = tobj::load_obj_buf_async(
+    let (models, obj_materials) = tobj::load_obj_buf(
         &mut obj_reader,
         &tobj::LoadOptions {
             triangulate: true,
             single_index: true,
             ..Default::default()
         },
-        |p| async move {
-            let mat_text = load_string(&p).await.unwrap();
+        |p| {
+            let mtl_path = relative_to(file_name, &p.to_string_lossy());
+            let mat_text = io.read_to_string(&mtl_path).map_err(|err| {
+                log::warn!("Failed to read material library '{}': {}", mtl_path, err);
+                tobj::LoadError::OpenFileFailed
+            })?;
             tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
         },
-    ).await?;
+    )?;
+    let obj_materials = obj_materials.unwrap_or_else(|err| {
+        log::warn!("Loading '{}' without materials: {}", file_name, err);
+        vec![]
+    });
 
-    let mut materials: Vec<Material> = Vec::new();
-    for m in obj_materials? {
-        let diffuse_texture = load_texture(&m.diffuse_texture, device, queue).await?;
+    let mut materials: Vec<MaterialData> = Vec::new();
+    for m in obj_materials {
+        let load_map = |path: &str| match path.is_empty() {
+            true => None,
+            false => load_image(file_name, path, io),
+        };
+        let mut flags = MaterialFlags::NONE;
+        if m.dissolve < 1.0 {
+            flags.insert(MaterialFlags::TRANSPARENT);
+        }
+        match m.illumination_model {
+            Some(0) => flags.insert(MaterialFlags::UNLIT),
+            Some(1) => flags.insert(MaterialFlags::NO_SPECULAR),
+            _ => {}
+        }
 
-        materials.push(model::Material {
+        materials.push(MaterialData {
+            base_color: [m.diffuse[0], m.diffuse[1], m.diffuse[2], m.dissolve],
+            specular: m.specular,
+            shininess: m.shininess,
+            flags,
+            diffuse_texture: load_map(&m.diffuse_texture),
+            normal_texture: load_map(&m.normal_texture),
+            specular_texture: load_map(&m.specular_texture),
             name: m.name,
-            diffuse_texture,
         })
     }
 
+    let mut default_material = None;
     let meshes =
         models.into_iter().map(|m| {
-            let vertices = (0..m.mesh.positions.len() / 3)
+            let count = m.mesh.positions.len() / 3;
+            let has_tex_coords = m.mesh.texcoords.len() >= count * 2;
+            let has_normals = m.mesh.normals.len() >= count * 3;
+            if !has_tex_coords {
+                log::warn!("Mesh '{}' of '{}' has no texture coordinates", m.name, file_name);
+            }
+            if !has_normals {
+                log::warn!("Mesh '{}' of '{}' has no normals, generating {:?} ones", m.name, file_name, normals);
+            }
+
+            let vertices = (0..count)
                 .map(|i| model::ModelVertex{
                     position: [
                         m.mesh.positions[i * 3],
                         m.mesh.positions[i * 3 + 1],
                         m.mesh.positions[i * 3 + 2],
                     ],
-                    tex_coords: [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]],
-                    normal: [
-                        m.mesh.normals[i * 3],
-                        m.mesh.normals[i * 3 + 1],
-                        m.mesh.normals[i * 3 + 2],
-                    ],
+                    tex_coords: match has_tex_coords {
+                        true => [m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]],
+                        false => [0.0; 2],
+                    },
+                    normal: match has_normals {
+                        true => [m.mesh.normals[i * 3], m.mesh.normals[i * 3 + 1], m.mesh.normals[i * 3 + 2]],
+                        false => [0.0; 3],
+                    },
+                    tangent: [0.0; 3],
+                    bitangent: [0.0; 3],
                 }).collect::<Vec<_>>();
 
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
-                label: Some(&format!("{:?} Vertex Buffer", file_name)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{:?} Index Buffer", file_name)),
-                contents: bytemuck::cast_slice(&m.mesh.indices),
-                usage: wgpu::BufferUsages::INDEX,
-            });
-
-            model::Mesh{
-                name: file_name.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: m.mesh.indices.len() as u32,
-                material: m.mesh.material_id.unwrap_or(0),
+            let material = match m.mesh.material_id {
+                Some(id) if id < materials.len() => id,
+                _ => {
+                    log::warn!("Mesh '{}' of '{}' has no material, using white", m.name, file_name);
+                    *default_material.get_or_insert(materials.len())
+                }
+            };
+
+            let mut mesh = MeshData {
+                name: m.name,
+                vertices,
+                indices: m.mesh.indices,
+                material,
+            };
+            match (has_normals, normals) {
+                (true, _) => {}
+                (false, NormalMode::Smooth) => mesh.compute_normals(),
+                (false, NormalMode::Flat) => mesh.compute_flat_normals(),
             }
+            mesh.compute_tangents();
+            mesh
         }).collect::<Vec<_>>();
+    if default_material.is_some() {
+        materials.push(MaterialData::white(&format!("{} default material", file_name)));
+    }
+
+    Ok(model::ModelData{ meshes, materials })
+}
+
+/// Decode an image referenced by `file_name`, logging failures.
+fn load_image(file_name: &str, path: &str, io: &dyn AssetIo) -> Option<image::DynamicImage> {
+    let path = relative_to(file_name, path);
+    let image = io.read(&path).and_then(|it| Ok(image::load_from_memory(&it)?));
+    match image {
+        Result::Ok(it) => Some(it),
+        Err(err) => {
+            log::warn!("Failed to load texture '{}' of '{}': {}", path, file_name, err);
+            None
+        }
+    }
+}
 
-    Ok(model::Model{ meshes, materials})
-}
\ No newline at end of file
+#[cfg(test)]
+mod test {
+    use crate::asset::io::MemoryAssetIo;
+    use crate::asset::load_model_data;
+    use crate::render::material::MaterialFlags;
+    use crate::render::model::NormalMode;
+
+    /// A quad without texture coordinates or normals, using a material without diffuse map.
+    const QUAD_OBJ: &str = "mtllib quad.mtl\nv 0 0 0\nv 1 0 0\nv 1 0 -1\nv 0 0 -1\nusemtl plain\nf 1 2 3 4\n";
+    const QUAD_MTL: &str = "newmtl plain\nKd 0.5 0.5 0.5\nd 1.0\nillum 1\n";
+
+    #[test]
+    fn test_load_obj_with_missing_attributes() {
+        let io = MemoryAssetIo::new()
+            .with("models/quad.obj", QUAD_OBJ)
+            .with("models/quad.mtl", QUAD_MTL);
+
+        let smooth = load_model_data("models/quad.obj", &io, NormalMode::Smooth).unwrap();
+        let mesh = &smooth.meshes[0];
+        assert_eq!(mesh.vertices.len(), 4);
+        assert!(mesh.vertices.iter().all(|it| it.normal == [0.0, 1.0, 0.0] && it.tex_coords == [0.0, 0.0]));
+        assert!(mesh.vertices.iter().all(|it| it.tangent[1] == 0.0 && it.bitangent[1] == 0.0));
+        assert_eq!(smooth.materials.len(), 1);
+        assert_eq!(smooth.materials[0].base_color, [0.5, 0.5, 0.5, 1.0]);
+        assert!(smooth.materials[0].diffuse_texture.is_none());
+        assert_eq!(smooth.materials[0].flags, MaterialFlags::NO_SPECULAR);
+
+        let flat = load_model_data("models/quad.obj", &io, NormalMode::Flat).unwrap();
+        assert_eq!(flat.meshes[0].vertices.len(), 6);
+        assert_eq!(flat.meshes[0].indices, (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_load_obj_without_material_library() {
+        let io = MemoryAssetIo::new().with("quad.obj", QUAD_OBJ);
+
+        let data = load_model_data("quad.obj", &io, NormalMode::Smooth).unwrap();
+        assert_eq!(data.materials.len(), 1);
+        assert_eq!(data.meshes[0].material, 0);
+    }
+}
@@ -0,0 +1,162 @@
+use cgmath::{Matrix3, Matrix4, Quaternion, SquareMatrix, Vector3};
+use hecs::{Entity, EntityBuilder, World};
+use crate::asset::Asset;
+use crate::asset::assets::Assets;
+use crate::asset::handle::Handle;
+use crate::ecs::removal::{despawn, remove_one};
+use crate::ecs::resource::ResManager;
+use crate::render::model::Model;
+use crate::render::work::Renderer3D;
+use crate::transform::{GlobalTransform, Transform};
+
+pub struct SceneNode {
+    pub name: Option<String>,
+    /// Index into [`Scene::nodes`], always lower than the index of this node.
+    pub parent: Option<usize>,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    pub model: Option<Handle<Model>>,
+}
+
+/// A hierarchy of nodes which can be spawned as entities, e.g. loaded from a glTF file.
+/// Keeps the models of its nodes alive.
+pub struct Scene {
+    pub nodes: Vec<SceneNode>,
+}
+
+impl Asset for Scene {}
+
+impl Scene {
+    /// Spawn one entity per node with [`Transform`], [`GlobalTransform`], and [`Renderer3D`] if it has a model.
+    /// Root nodes are children of `parent`, returns the entities in the order of [`#nodes`](Scene::nodes).
+    pub fn spawn(&self, world: &mut World, parent: Option<Entity>) -> Vec<Entity> {
+        let parent_global = parent
+            .and_then(|it| world.get::<&GlobalTransform>(it).ok().map(|it| (it.0, it.1)))
+            .unwrap_or((Matrix4::identity(), Matrix3::identity()));
+
+        let mut entities: Vec<Entity> = Vec::with_capacity(self.nodes.len());
+        let mut globals: Vec<(Matrix4<f32>, Matrix3<f32>)> = Vec::with_capacity(self.nodes.len());
+        for node in self.nodes.iter() {
+            let (parent_matrix, parent_normal) = node.parent.map_or(parent_global, |it| globals[it]);
+            let local = Matrix4::from_translation(node.translation)
+                * Matrix4::from(node.rotation)
+                * Matrix4::from_nonuniform_scale(node.scale.x, node.scale.y, node.scale.z);
+            let global = (parent_matrix * local, parent_normal * Matrix3::from(node.rotation));
+
+            let transform = Transform {
+                parent: node.parent.map(|it| entities[it]).or(parent),
+                position: node.translation,
+                rotation: node.rotation,
+                scale: node.scale,
+            };
+            let entity = world.spawn((transform, GlobalTransform(global.0, global.1)));
+            if let Some(model) = &node.model {
+                world.insert_one(entity, Renderer3D { model: model.clone() }).unwrap();
+            }
+            entities.push(entity);
+            globals.push(global);
+        }
+        entities
+    }
+}
+
+/// Spawns the scene as children of this entity once it is loaded, then is replaced by [`SceneInstance`].
+pub struct SpawnScene(pub Handle<Scene>);
+
+/// Its spawned nodes are despawned with it through [`Removals`](crate::ecs::removal::Removals).
+pub struct SceneInstance {
+    pub scene: Handle<Scene>,
+    /// Spawned nodes in the order of [`Scene::nodes`].
+    pub entities: Vec<Entity>,
+}
+
+pub(crate) fn spawn_scenes(world: &mut World, res_manager: &mut ResManager) {
+    let Some(scenes) = res_manager.borrow_res::<Assets<Scene>>() else { return };
+    let ready = world.query::<&SpawnScene>().iter()
+        .filter(|(_, it)| scenes.get(&it.0).is_some())
+        .map(|(entity, it)| (entity, it.0.clone()))
+        .collect::<Vec<_>>();
+
+    for (entity, scene) in ready {
+        let entities = scenes.get(&scene).unwrap().spawn(world, Some(entity));
+        remove_one::<SpawnScene>(world, res_manager, entity).unwrap();
+        world.insert_one(entity, SceneInstance { scene, entities }).unwrap();
+    }
+}
+
+/// Despawn hook despawning the nodes of a despawned [`SceneInstance`], which may have been despawned already.
+pub(crate) fn despawn_scene_nodes(_: Entity, components: &EntityBuilder, world: &mut World, res_manager: &mut ResManager) {
+    let Some(instance) = components.get::<&SceneInstance>() else { return };
+    for entity in instance.entities.iter() {
+        let _ = despawn(world, res_manager, *entity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{Quaternion, Vector3};
+    use hecs::World;
+    use crate::asset::assets::Assets;
+    use crate::asset::scene::{despawn_scene_nodes, Scene, SceneInstance, SceneNode, SpawnScene, spawn_scenes};
+    use crate::ecs::removal::{despawn, Removals};
+    use crate::ecs::resource::ResManager;
+    use crate::schedule::{GameSchedule, Stage};
+    use crate::transform::{GlobalTransform, Transform};
+
+    fn node(parent: Option<usize>, x: f32) -> SceneNode {
+        SceneNode {
+            name: None,
+            parent,
+            translation: Vector3::new(x, 0.0, 0.0),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            model: None,
+        }
+    }
+
+    #[test]
+    fn test_spawn_scene_when_loaded() {
+        let mut world = World::new();
+        let mut res_manager = ResManager::new();
+        let mut scenes = Assets::<Scene>::new();
+        let scene = scenes.add(Scene { nodes: vec![node(None, 1.0), node(Some(0), 2.0)] });
+        res_manager.push_res(scenes).unwrap();
+        let root = world.spawn((SpawnScene(scene.clone()),));
+
+        let mut schedule = GameSchedule::new();
+        schedule.add_system(Stage::PreUpdate, spawn_scenes);
+        schedule.run_updates(&mut world, &mut res_manager);
+
+        let instance = world.get::<&SceneInstance>(root).unwrap();
+        assert_eq!(instance.scene, scene);
+        assert!(world.get::<&SpawnScene>(root).is_err());
+
+        let child = instance.entities[1];
+        assert_eq!(world.get::<&Transform>(child).unwrap().parent, Some(instance.entities[0]));
+        assert_eq!(world.get::<&Transform>(instance.entities[0]).unwrap().parent, Some(root));
+        assert_eq!(world.get::<&GlobalTransform>(child).unwrap().0.w.x, 3.0);
+    }
+
+    #[test]
+    fn test_despawn_scene_nodes_with_instance() {
+        let mut world = World::new();
+        let mut res_manager = ResManager::new();
+        let mut scenes = Assets::<Scene>::new();
+        let scene = scenes.add(Scene { nodes: vec![node(None, 1.0), node(Some(0), 2.0)] });
+        res_manager.push_res(scenes).unwrap();
+        res_manager.push_res(Removals::new()).unwrap();
+        let root = world.spawn((SpawnScene(scene),));
+
+        let mut schedule = GameSchedule::new();
+        schedule.add_system(Stage::PreUpdate, spawn_scenes);
+        schedule.add_system(Stage::Update, move |world: &mut World, res_manager: &mut ResManager| {
+            let _ = despawn(world, res_manager, root);
+        });
+        schedule.add_despawn_hook(despawn_scene_nodes);
+        schedule.run_updates(&mut world, &mut res_manager);
+
+        assert!(!world.contains(root));
+        assert_eq!(world.len(), 0);
+    }
+}
@@ -0,0 +1,404 @@
+use std::any::{type_name, Any, TypeId};
+use std::collections::{HashMap, HashSet};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{Arc, Weak};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use terre_core_macros::Resource;
+use crate::asset::Asset;
+use crate::asset::assets::Assets;
+use crate::asset::handle::{Handle, HandleId};
+use crate::asset::io::{AssetIo, default_asset_io, normalize_path};
+use crate::asset::watcher::AssetWatcher;
+use crate::ecs::resource::{ResManager, ResMut};
+use crate::task::TaskPool;
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum LoadState {
+    NotLoaded,
+    Loading,
+    Loaded,
+    Failed,
+}
+
+/// Turns a file read through [`AssetIo`] into an asset.
+/// # Explanation
+/// [`#load`](AssetLoader::load) runs on a worker thread and should do the IO and decoding,
+/// [`#finish`](AssetLoader::finish) runs on the main thread in [`Stage::AssetUpload`](crate::schedule::Stage::AssetUpload)
+/// and should only upload the decoded data to the GPU.
+pub trait AssetLoader: Send + Sync + 'static {
+    type Asset: Asset;
+    /// CPU side data handed from the worker to the main thread.
+    type Data: Send + 'static;
+    /// File extensions without dot, e.g. `["png", "jpg"]`.
+    fn extensions(&self) -> &[&str];
+    fn load(&self, path: &str, io: &dyn AssetIo) -> anyhow::Result<Self::Data>;
+    /// Loaders override either this or [`#finish_with`](AssetLoader::finish_with).
+    fn finish(&self, _data: Self::Data) -> anyhow::Result<Self::Asset> {
+        anyhow::bail!("{} needs resources to finish", type_name::<Self>())
+    }
+    /// [`#finish`](AssetLoader::finish) with access to resources, e.g. to add sub-assets to their storages.
+    fn finish_with(&self, data: Self::Data, _res_manager: &ResManager) -> anyhow::Result<Self::Asset> {
+        self.finish(data)
+    }
+}
+
+trait ErasedLoader: Send + Sync {
+    fn asset_type(&self) -> TypeId;
+    fn extensions(&self) -> &[&str];
+    fn load(&self, path: &str, io: &dyn AssetIo) -> anyhow::Result<Box<dyn Any + Send>>;
+    fn finish(&self, data: Box<dyn Any + Send>, id: HandleId, strong: Weak<HandleId>, res_manager: &ResManager) -> anyhow::Result<()>;
+}
+
+struct Erased<L>(L);
+
+impl<L> ErasedLoader for Erased<L> where L: AssetLoader {
+    fn asset_type(&self) -> TypeId {
+        TypeId::of::<L::Asset>()
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.0.extensions()
+    }
+
+    fn load(&self, path: &str, io: &dyn AssetIo) -> anyhow::Result<Box<dyn Any + Send>> {
+        Ok(Box::new(self.0.load(path, io)?))
+    }
+
+    fn finish(&self, data: Box<dyn Any + Send>, id: HandleId, strong: Weak<HandleId>, res_manager: &ResManager) -> anyhow::Result<()> {
+        let data = data.downcast::<L::Data>().unwrap();
+        let asset = self.0.finish_with(*data, res_manager)?;
+        let mut assets = res_manager.borrow_res_mut::<Assets<L::Asset>>()
+            .ok_or_else(|| anyhow::Error::msg(format!("Asset type [{}] is not added to the app!", type_name::<L::Asset>())))?;
+        assets.insert(id, asset, strong);
+        Ok(())
+    }
+}
+
+/// Sent back from the worker thread.
+struct LoadedData {
+    path: String,
+    id: HandleId,
+    strong: Weak<HandleId>,
+    loader: Arc<dyn ErasedLoader>,
+    data: anyhow::Result<Box<dyn Any + Send>>,
+    reload: bool,
+}
+
+struct PathEntry {
+    id: HandleId,
+    strong: Weak<HandleId>,
+}
+
+/// Loads assets by path into their [`Assets`] storage.
+/// # Usage
+/// [`#load`](AssetServer::load) returns a strong [`Handle`] at once, and the same handle for the same path
+/// as long as it is alive. Files are read and decoded on worker threads, then finished in
+/// [`Stage::AssetUpload`](crate::schedule::Stage::AssetUpload); poll [`#load_state`](AssetServer::load_state)
+/// or read [`AssetEvent`](crate::asset::assets::AssetEvent)s to know when it is ready.
+#[derive(Resource)]
+pub struct AssetServer {
+    io: Arc<dyn AssetIo>,
+    loaders: Vec<Arc<dyn ErasedLoader>>,
+    paths: HashMap<(String, TypeId), PathEntry>,
+    states: HashMap<HandleId, LoadState>,
+    pool: TaskPool,
+    sender: Sender<LoadedData>,
+    receiver: Receiver<LoadedData>,
+    watcher: Option<AssetWatcher>,
+    /// Files changed on disk noticed by the last update.
+    changed: HashSet<String>,
+}
+
+impl AssetServer {
+    /// Server reading from [`default_asset_io`].
+    pub fn new() -> Self {
+        Self::with_io(default_asset_io().into())
+    }
+
+    pub fn with_io(io: Arc<dyn AssetIo>) -> Self {
+        Self::with_io_and_pool(io, TaskPool::with_default_threads("Asset Worker"))
+    }
+
+    pub fn with_io_and_pool(io: Arc<dyn AssetIo>, pool: TaskPool) -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            io,
+            loaders: vec![],
+            paths: HashMap::new(),
+            states: HashMap::new(),
+            pool,
+            sender,
+            receiver,
+            watcher: None,
+            changed: HashSet::new(),
+        }
+    }
+
+    pub fn io(&self) -> &dyn AssetIo {
+        self.io.as_ref()
+    }
+
+    /// Reload assets in place, behind their handles, when their files under the asset root change.
+    /// Only works for an [`AssetIo`] backed by the filesystem.
+    pub fn watch_for_changes(&mut self) -> anyhow::Result<()> {
+        if self.watcher.is_none() {
+            let root = self.io.watch_root()
+                .ok_or_else(|| anyhow::Error::msg("Asset io does not support watching changes"))?;
+            self.watcher = Some(AssetWatcher::new(root)?);
+        }
+        Ok(())
+    }
+
+    pub fn add_loader(&mut self, loader: impl AssetLoader) {
+        self.loaders.push(Arc::new(Erased(loader)));
+    }
+
+    /// Load the asset at `path` on a worker, or get the handle of the asset already loaded from it.
+    /// Paths are normalized like [`AssetIo`] paths, so `./a\b.png` and `a/b.png` are the same asset.
+    pub fn load<T>(&mut self, path: &str) -> Handle<T> where T: Asset {
+        let path = &normalize_path(path);
+        let key = (path.to_string(), TypeId::of::<T>());
+        if let Some(strong) = self.paths.get(&key).and_then(|it| it.strong.upgrade()) {
+            return Handle::from_arc(strong);
+        }
+
+        let handle = Handle::<T>::new_strong();
+        self.paths.insert(key, PathEntry { id: handle.id(), strong: handle.downgrade() });
+
+        let Some(loader) = self.find_loader(path, TypeId::of::<T>()) else {
+            log::error!("No asset loader for '{}'", path);
+            self.states.insert(handle.id(), LoadState::Failed);
+            return handle;
+        };
+        self.states.insert(handle.id(), LoadState::Loading);
+        self.spawn_load(loader, path, handle.id(), handle.downgrade(), false);
+        handle
+    }
+
+    fn find_loader(&self, path: &str, asset_type: TypeId) -> Option<Arc<dyn ErasedLoader>> {
+        let extension = Path::new(path).extension()
+            .and_then(|it| it.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        self.loaders.iter()
+            .find(|it| it.asset_type() == asset_type && it.extensions().contains(&extension.as_str()))
+            .cloned()
+    }
+
+    fn spawn_load(&self, loader: Arc<dyn ErasedLoader>, path: &str, id: HandleId, strong: Weak<HandleId>, reload: bool) {
+        let sender = self.sender.clone();
+        let io = self.io.clone();
+        let path = path.to_string();
+        self.pool.spawn(move || {
+            // All handles may be dropped before the worker picks it up.
+            let data = match strong.strong_count() {
+                0 => Err(anyhow::Error::msg("Unused")),
+                // A panicking loader still reports back, so the asset does not stay loading
+                _ => catch_unwind(AssertUnwindSafe(|| loader.load(&path, io.as_ref())))
+                    .unwrap_or_else(|_| Err(anyhow::Error::msg("Loader panicked"))),
+            };
+            let _ = sender.send(LoadedData { path, id, strong, loader, data, reload });
+        });
+    }
+
+    /// Load the changed files again for every asset type they were loaded as.
+    fn reload_changed(&mut self) {
+        let Some(watcher) = &self.watcher else { return };
+        self.changed = watcher.changed_paths().iter().map(|it| normalize_path(it)).collect();
+        if self.changed.is_empty() {
+            return;
+        }
+        let to_reload = self.paths.iter()
+            .filter(|((path, _), entry)| self.changed.contains(path) && entry.strong.strong_count() > 0)
+            .filter_map(|((path, asset_type), entry)| {
+                let loader = self.find_loader(path, *asset_type)?;
+                Some((loader, path.clone(), entry.id, entry.strong.clone()))
+            })
+            .collect::<Vec<_>>();
+        for (loader, path, id, strong) in to_reload {
+            log::info!("Reloading asset '{}'", path);
+            self.spawn_load(loader, &path, id, strong, true);
+        }
+    }
+
+    /// Files created or modified on disk since the previous update, relative to the asset root.
+    /// Lets data read directly through [`#io`](AssetServer::io) reload itself, empty when not watching.
+    pub fn changed_paths(&self) -> &HashSet<String> {
+        &self.changed
+    }
+
+    /// Get a strong handle of an asset loaded from `path`, if it is still alive.
+    pub fn get_handle<T>(&self, path: &str) -> Option<Handle<T>> where T: Asset {
+        let entry = self.paths.get(&(normalize_path(path), TypeId::of::<T>()))?;
+        entry.strong.upgrade().map(Handle::from_arc)
+    }
+
+    pub fn load_state<T>(&self, handle: &Handle<T>) -> LoadState {
+        self.load_state_by_id(handle.id())
+    }
+
+    pub fn load_state_by_id(&self, id: HandleId) -> LoadState {
+        self.states.get(&id).copied().unwrap_or(LoadState::NotLoaded)
+    }
+
+    /// Finish assets decoded by workers since the last call and insert them into their storages,
+    /// then start reloading files changed on disk if watching.
+    pub fn update(&mut self, res_manager: &ResManager) {
+        for LoadedData { path, id, strong, loader, data, reload } in self.receiver.try_iter().collect::<Vec<_>>() {
+            if strong.strong_count() == 0 {
+                self.states.remove(&id);
+                continue;
+            }
+            let result = data.and_then(|data| loader.finish(data, id, strong, res_manager));
+            let state = match result {
+                Ok(_) => LoadState::Loaded,
+                Err(err) if reload && self.load_state_by_id(id) == LoadState::Loaded => {
+                    log::error!("Failed to reload asset '{}', keeping the previous version: {}", path, err);
+                    LoadState::Loaded
+                }
+                Err(err) => {
+                    log::error!("Failed to load asset '{}': {}", path, err);
+                    LoadState::Failed
+                }
+            };
+            self.states.insert(id, state);
+        }
+        self.reload_changed();
+    }
+
+    /// Forget paths and states of assets without strong handles.
+    pub fn free_unused(&mut self) {
+        self.paths.retain(|_, it| it.strong.strong_count() > 0);
+        let alive = self.paths.values().map(|it| it.id).collect::<HashSet<_>>();
+        self.states.retain(|id, _| alive.contains(id));
+    }
+}
+
+impl Default for AssetServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) fn upload_assets(res_manager: &ResManager) {
+    let Some(mut server) = res_manager.borrow_res_mut::<AssetServer>() else { return };
+    server.update(res_manager);
+}
+
+pub(crate) fn free_unused_paths(mut server: ResMut<AssetServer>) {
+    server.free_unused();
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use crate::asset::assets::Assets;
+    use crate::asset::handle::Handle;
+    use crate::asset::io::{AssetIo, MemoryAssetIo};
+    use crate::asset::server::{AssetLoader, AssetServer, LoadState};
+    use crate::ecs::resource::ResManager;
+    use crate::task::TaskPool;
+
+    struct EchoLoader;
+
+    impl AssetLoader for EchoLoader {
+        type Asset = String;
+        type Data = String;
+
+        fn extensions(&self) -> &[&str] {
+            &["txt"]
+        }
+
+        fn load(&self, path: &str, io: &dyn AssetIo) -> anyhow::Result<Self::Data> {
+            Ok(io.read_to_string(path)?.to_uppercase())
+        }
+
+        fn finish(&self, data: Self::Data) -> anyhow::Result<Self::Asset> {
+            Ok(data)
+        }
+    }
+
+    struct PanicLoader;
+
+    impl AssetLoader for PanicLoader {
+        type Asset = String;
+        type Data = String;
+
+        fn extensions(&self) -> &[&str] {
+            &["bad"]
+        }
+
+        fn load(&self, _path: &str, _io: &dyn AssetIo) -> anyhow::Result<Self::Data> {
+            panic!("Corrupt file")
+        }
+
+        fn finish(&self, data: Self::Data) -> anyhow::Result<Self::Asset> {
+            Ok(data)
+        }
+    }
+
+    fn new_server() -> (AssetServer, ResManager) {
+        let mut res_manager = ResManager::new();
+        res_manager.push_res(Assets::<String>::new()).unwrap();
+        let io = MemoryAssetIo::new().with("a.txt", "stone");
+        let mut server = AssetServer::with_io_and_pool(Arc::new(io), TaskPool::new("Test Asset Worker", 2));
+        server.add_loader(EchoLoader);
+        (server, res_manager)
+    }
+
+    fn wait_loaded(server: &mut AssetServer, res_manager: &ResManager, handle: &Handle<String>) -> LoadState {
+        let start = Instant::now();
+        while server.load_state(handle) == LoadState::Loading && start.elapsed() < Duration::from_secs(5) {
+            server.update(res_manager);
+            std::thread::yield_now();
+        }
+        server.load_state(handle)
+    }
+
+    #[test]
+    fn test_asset_server_dedup_and_load_state() {
+        let (mut server, res_manager) = new_server();
+
+        let a = server.load::<String>("a.txt");
+        let again = server.load::<String>("./a.txt");
+        let missing = server.load::<String>("missing.txt");
+        let unknown = server.load::<String>("a.png");
+        assert_eq!(a, again);
+        assert_eq!(server.get_handle::<String>(".\\a.txt"), Some(a.clone()));
+        assert_eq!(server.load_state(&unknown), LoadState::Failed);
+
+        assert_eq!(wait_loaded(&mut server, &res_manager, &a), LoadState::Loaded);
+        assert_eq!(wait_loaded(&mut server, &res_manager, &missing), LoadState::Failed);
+        assert_eq!(res_manager.borrow_res::<Assets<String>>().unwrap().get(&a).unwrap(), "STONE");
+    }
+
+    #[test]
+    fn test_asset_server_loader_panic_fails() {
+        let (mut server, res_manager) = new_server();
+        server.add_loader(PanicLoader);
+
+        let bad = server.load::<String>("a.bad");
+        assert_eq!(wait_loaded(&mut server, &res_manager, &bad), LoadState::Failed);
+        // The workers keep loading after the panic
+        let a = server.load::<String>("a.txt");
+        assert_eq!(wait_loaded(&mut server, &res_manager, &a), LoadState::Loaded);
+    }
+
+    #[test]
+    fn test_asset_server_unload_after_drop() {
+        let (mut server, mut res_manager) = new_server();
+
+        let a = server.load::<String>("a.txt");
+        let id = a.id();
+        wait_loaded(&mut server, &res_manager, &a);
+        drop(a);
+
+        server.free_unused();
+        res_manager.get_res_mut::<Assets<String>>().unwrap().free_unused();
+        assert_eq!(server.load_state_by_id(id), LoadState::NotLoaded);
+        assert!(server.get_handle::<String>("a.txt").is_none());
+        assert!(!res_manager.get_res::<Assets<String>>().unwrap().contains(id));
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+use notify::{Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Watches the asset root and reports changed files as paths relative to it.
+/// # Explanation
+/// Uses the native watcher of the platform, and falls back to polling every second if it is unavailable.
+pub struct AssetWatcher {
+    root: PathBuf,
+    // Dropping the watcher stops watching.
+    _watcher: Box<dyn Watcher + Send>,
+    receiver: Receiver<notify::Result<Event>>,
+}
+
+impl AssetWatcher {
+    pub fn new(root: &Path) -> anyhow::Result<Self> {
+        let root = root.canonicalize()?;
+        let (sender, receiver) = channel();
+
+        let mut watcher: Box<dyn Watcher + Send> = match RecommendedWatcher::new(sender.clone(), Config::default()) {
+            Ok(it) => Box::new(it),
+            Err(err) => {
+                log::warn!("Native file watcher unavailable ({}), polling asset changes instead", err);
+                Box::new(PollWatcher::new(sender, Config::default().with_poll_interval(Duration::from_secs(1)))?)
+            }
+        };
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            root,
+            _watcher: watcher,
+            receiver,
+        })
+    }
+
+    /// Files created or modified since the last call, deduplicated.
+    pub fn changed_paths(&self) -> HashSet<String> {
+        let mut changed = HashSet::new();
+        for event in self.receiver.try_iter() {
+            let event = match event {
+                Ok(it) => it,
+                Err(err) => {
+                    log::warn!("Asset watcher error: {}", err);
+                    continue;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for path in event.paths {
+                if let Some(relative) = relative_asset_path(&self.root, &path) {
+                    changed.insert(relative);
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Path of `path` relative to `root` with `/` separators, as used by [`AssetServer#load`](crate::asset::server::AssetServer::load).
+fn relative_asset_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let parts = relative.components()
+        .map(|it| it.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use crate::asset::watcher::relative_asset_path;
+
+    #[test]
+    fn test_relative_asset_path() {
+        let root = Path::new("/game/res");
+        assert_eq!(relative_asset_path(root, &root.join("textures").join("stone.png")).unwrap(), "textures/stone.png");
+        assert_eq!(relative_asset_path(root, &root.join("shader.wgsl")).unwrap(), "shader.wgsl");
+        assert!(relative_asset_path(root, Path::new("/elsewhere/shader.wgsl")).is_none());
+    }
+}
@@ -0,0 +1,53 @@
+use crate::ecs::resource::{ResMut, Resource};
+
+/// Events sent during the current frame, as a resource.
+/// # Usage
+/// Add with [`App#add_event`](crate::app::App::add_event), send with `ResMut<Events<T>>` and read with `Res<Events<T>>`.
+/// # Explanation
+/// Events are cleared in [`Stage::First`](crate::schedule::Stage::First), so systems see events sent earlier in the same frame,
+/// events sent after a system ran are missed by it.
+pub struct Events<T> {
+    events: Vec<T>,
+}
+
+impl<T> Resource for Events<T> where T: 'static {}
+
+impl<T> Events<T> {
+    pub fn new() -> Self {
+        Self { events: vec![] }
+    }
+
+    pub fn send(&mut self, event: T) {
+        self.events.push(event);
+    }
+
+    pub fn send_batch(&mut self, events: impl IntoIterator<Item = T>) {
+        self.events.extend(events);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.events.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) fn clear_events<T: 'static>(mut events: ResMut<Events<T>>) {
+    events.clear();
+}
@@ -1,2 +1,4 @@
 pub mod system;
 pub mod resource;
+pub mod removal;
+pub mod event;
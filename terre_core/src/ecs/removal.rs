@@ -0,0 +1,291 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use hecs::{Component, ComponentError, Entity, EntityBuilder, NoSuchEntity, World};
+use terre_core_macros::Resource;
+use crate::ecs::resource::{Res, ResManager};
+use crate::ecs::system::SharedSystemParam;
+
+/// When a removal happened or a system runs: the frame, then the stage and the system within the frame.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+struct Tick {
+    frame: u64,
+    stage: u8,
+    system: usize,
+}
+
+/// Records components removed from entities and entities despawned, for one frame.
+/// # Usage
+/// Components have to be tracked with [`#track`](Removals::track) first, which
+/// [`RemovedComponents`] does by itself when its system is initialized.
+/// Despawn entities and remove tracked components with [`#despawn`](Removals::despawn)
+/// and [`#remove_one`](Removals::remove_one), or [`despawn`] and [`remove_one`] from systems,
+/// instead of the methods of [`World`], which are not seen.
+/// # Explanation
+/// hecs has no removal callbacks, so removals are recorded where they happen, with the system running at the time.
+/// [`GameSchedule`](crate::schedule::GameSchedule) tells which system runs, and a system reads the removals since
+/// it ran last frame, so it sees each of them once, also those of later stages of the previous frame.
+/// Records older than a frame are dropped by [`#next_frame`](Removals::next_frame).
+///
+/// Despawned entities keep their components until the despawn hooks of the schedule ran after the stage.
+#[derive(Resource)]
+pub struct Removals {
+    trackers: HashMap<TypeId, Vec<(Entity, Tick)>>,
+    despawned: Vec<(Entity, Tick)>,
+    /// Despawned entities the despawn hooks did not see yet, with their components.
+    unhooked: Vec<(Entity, EntityBuilder)>,
+    now: Tick,
+}
+
+impl Removals {
+    pub fn new() -> Self {
+        Self {
+            trackers: HashMap::new(),
+            despawned: vec![],
+            unhooked: vec![],
+            now: Tick::default(),
+        }
+    }
+
+    pub fn track<T>(&mut self) where T: Component {
+        self.trackers.entry(TypeId::of::<T>()).or_default();
+    }
+
+    pub fn is_tracked<T>(&self) -> bool where T: Component {
+        self.trackers.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Despawn `entity`, recording it and its tracked components.
+    pub fn despawn(&mut self, world: &mut World, entity: Entity) -> Result<(), NoSuchEntity> {
+        let mut components = EntityBuilder::new();
+        components.add_bundle(world.take(entity)?);
+        for it in components.component_types() {
+            if let Some(removed) = self.trackers.get_mut(&it) {
+                removed.push((entity, self.now));
+            }
+        }
+        self.despawned.push((entity, self.now));
+        self.unhooked.push((entity, components));
+        Ok(())
+    }
+
+    /// Remove component `T` of `entity`, recording it if `T` is tracked.
+    pub fn remove_one<T>(&mut self, world: &mut World, entity: Entity) -> Result<T, ComponentError> where T: Component {
+        let component = world.remove_one::<T>(entity)?;
+        if let Some(removed) = self.trackers.get_mut(&TypeId::of::<T>()) {
+            removed.push((entity, self.now));
+        }
+        Ok(component)
+    }
+
+    /// Entities despawned since the last call with the components they had, for despawn hooks.
+    pub fn take_unhooked(&mut self) -> Vec<(Entity, EntityBuilder)> {
+        std::mem::take(&mut self.unhooked)
+    }
+
+    /// Start the next frame, dropping records older than a frame.
+    pub fn next_frame(&mut self) {
+        self.now = Tick { frame: self.now.frame + 1, stage: 0, system: 0 };
+        let frame = self.now.frame;
+        let recent = |(_, tick): &(Entity, Tick)| tick.frame + 1 >= frame;
+        self.trackers.values_mut().for_each(|it| it.retain(recent));
+        self.despawned.retain(recent);
+    }
+
+    /// Record what follows as done by system `system` of the stage at `stage` in the frame.
+    pub(crate) fn enter_system(&mut self, stage: u8, system: usize) {
+        self.now = Tick { stage, system, ..self.now };
+    }
+
+    /// Whether a removal at `tick` happened since the running system ran last frame.
+    fn unseen(&self, tick: Tick) -> bool {
+        let since = match self.now.frame.checked_sub(1) {
+            Some(frame) => Tick { frame, ..self.now },
+            None => Tick::default(),
+        };
+        since <= tick && tick < self.now
+    }
+
+    /// Entities which lost component `T` since the running system ran last frame, including despawned ones.
+    pub fn removed<T>(&self) -> impl Iterator<Item = Entity> + '_ where T: Component {
+        self.trackers.get(&TypeId::of::<T>())
+            .into_iter()
+            .flatten()
+            .filter(|(_, tick)| self.unseen(*tick))
+            .map(|(entity, _)| *entity)
+    }
+
+    /// Entities despawned since the running system ran last frame.
+    pub fn despawned(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.despawned.iter()
+            .filter(|(_, tick)| self.unseen(*tick))
+            .map(|(entity, _)| *entity)
+    }
+}
+
+impl Default for Removals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// [`Removals::despawn`] with the [`Removals`] resource, despawning without recording if there is none.
+pub fn despawn(world: &mut World, res_manager: &ResManager, entity: Entity) -> Result<(), NoSuchEntity> {
+    match res_manager.borrow_res_mut::<Removals>() {
+        Some(mut removals) => removals.despawn(world, entity),
+        None => world.despawn(entity),
+    }
+}
+
+/// [`Removals::remove_one`] with the [`Removals`] resource, removing without recording if there is none.
+pub fn remove_one<T>(world: &mut World, res_manager: &ResManager, entity: Entity) -> Result<T, ComponentError> where T: Component {
+    match res_manager.borrow_res_mut::<Removals>() {
+        Some(mut removals) => removals.remove_one(world, entity),
+        None => world.remove_one(entity),
+    }
+}
+
+/// System parameter iterating entities which lost component `T` since its system ran last frame.
+pub struct RemovedComponents<'w, T> {
+    removals: Res<'w, Removals>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'w, T> RemovedComponents<'w, T> where T: Component {
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.removals.removed::<T>()
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> SharedSystemParam for RemovedComponents<'_, T> where T: Component {
+    type Item<'world> = RemovedComponents<'world, T>;
+
+    fn init(_world: &mut World, res_manager: &mut ResManager) {
+        if !res_manager.contains_res::<Removals>() {
+            res_manager.push_res(Removals::new()).unwrap();
+        }
+        res_manager.get_res_mut::<Removals>().unwrap().track::<T>();
+    }
+
+    fn get_shared<'w>(_world: &'w World, res_manager: &'w ResManager) -> Self::Item<'w> {
+        RemovedComponents {
+            removals: res_manager.borrow_res::<Removals>().unwrap(),
+            marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hecs::{Entity, World};
+    use terre_core_macros::Resource;
+    use crate::ecs::removal::{despawn, Removals, RemovedComponents};
+    use crate::ecs::resource::{ResManager, ResMut};
+    use crate::schedule::{GameSchedule, Stage};
+
+    #[test]
+    fn test_removals_track_remove_and_despawn() {
+        let mut world = World::new();
+        let a = world.spawn((1i32, 1u32));
+        let b = world.spawn((2i32,));
+        let mut removals = Removals::new();
+        removals.track::<i32>();
+
+        // Removed then inserted again is still a removal
+        assert_eq!(removals.remove_one::<i32>(&mut world, a), Ok(1));
+        world.insert_one(a, 3i32).unwrap();
+        removals.despawn(&mut world, b).unwrap();
+        assert!(removals.despawn(&mut world, b).is_err());
+
+        // Hooks get the components of despawned entities
+        let unhooked = removals.take_unhooked();
+        assert_eq!(unhooked.len(), 1);
+        assert_eq!((unhooked[0].0, unhooked[0].1.get::<&i32>().as_deref()), (b, Some(&2)));
+        assert!(removals.take_unhooked().is_empty());
+
+        // Seen during the next frame, then dropped
+        removals.next_frame();
+        assert_eq!(removals.removed::<i32>().collect::<Vec<_>>(), vec![a, b]);
+        assert_eq!(removals.despawned().collect::<Vec<_>>(), vec![b]);
+        assert_eq!(removals.removed::<u32>().count(), 0);
+        removals.next_frame();
+        assert_eq!(removals.removed::<i32>().count(), 0);
+        assert_eq!(removals.despawned().count(), 0);
+    }
+
+    #[derive(Resource)]
+    struct Seen(Vec<Entity>);
+
+    #[test]
+    fn test_removed_components_param() {
+        fn despawn_all(world: &mut World, res_manager: &mut ResManager) {
+            let all = world.iter().map(|it| it.entity()).collect::<Vec<_>>();
+            all.into_iter().for_each(|it| despawn(world, res_manager, it).unwrap());
+        }
+        fn collect(removed: RemovedComponents<i32>, mut seen: ResMut<Seen>) {
+            seen.0.extend(removed.iter());
+        }
+
+        let mut world = World::new();
+        let mut res_manager = ResManager::new();
+        res_manager.push_res(Removals::new()).unwrap();
+        res_manager.push_res(Seen(vec![])).unwrap();
+        let entity = world.spawn((3i32,));
+
+        let mut schedule = GameSchedule::new();
+        schedule.add_system(Stage::Start, collect);
+        schedule.add_system(Stage::Update, despawn_all);
+        schedule.add_system(Stage::PostUpdate, collect);
+
+        schedule.run_starts(&mut world, &mut res_manager);
+        schedule.run_updates(&mut world, &mut res_manager);
+
+        assert_eq!(res_manager.get_res::<Seen>().unwrap().0, vec![entity]);
+        assert_eq!(res_manager.get_res::<Removals>().unwrap().despawned().collect::<Vec<_>>(), vec![entity]);
+    }
+
+    #[test]
+    fn test_removed_in_later_stage_seen_next_frame() {
+        fn despawn_all(world: &mut World, res_manager: &mut ResManager) {
+            let all = world.iter().map(|it| it.entity()).collect::<Vec<_>>();
+            all.into_iter().for_each(|it| despawn(world, res_manager, it).unwrap());
+        }
+        fn collect(removed: RemovedComponents<i32>, mut seen: ResMut<Seen>) {
+            seen.0.extend(removed.iter());
+        }
+
+        let mut world = World::new();
+        let mut res_manager = ResManager::new();
+        res_manager.push_res(Removals::new()).unwrap();
+        res_manager.push_res(Seen(vec![])).unwrap();
+        let mut schedule = GameSchedule::new();
+        schedule.add_system(Stage::Update, collect);
+        schedule.add_system(Stage::PostUpdate, despawn_all);
+
+        // Despawned in PostUpdate, read once by Update of the next frame
+        let entity = world.spawn((3i32,));
+        schedule.run_updates(&mut world, &mut res_manager);
+        assert!(res_manager.get_res::<Seen>().unwrap().0.is_empty());
+        schedule.run_updates(&mut world, &mut res_manager);
+        schedule.run_updates(&mut world, &mut res_manager);
+        assert_eq!(res_manager.get_res::<Seen>().unwrap().0, vec![entity]);
+
+        // Despawned in Start, read by the first Update
+        let mut schedule = GameSchedule::new();
+        schedule.add_system(Stage::Start, despawn_all);
+        schedule.add_system(Stage::Update, collect);
+        res_manager.get_res_mut::<Seen>().unwrap().0.clear();
+        let started = world.spawn((4i32,));
+        schedule.run_starts(&mut world, &mut res_manager);
+        schedule.run_updates(&mut world, &mut res_manager);
+        assert_eq!(res_manager.get_res::<Seen>().unwrap().0, vec![started]);
+    }
+}
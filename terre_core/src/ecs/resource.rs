@@ -1,4 +1,5 @@
-use std::any::{Any, type_name, TypeId};
+use std::any::{type_name, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
 use std::collections::HashMap;
 use std::ops::{Deref, DerefMut};
 use anyhow::Error;
@@ -7,21 +8,24 @@ use terre_core_macros::Resource;
 
 pub struct Res<'a, T> {
     value: &'a T,
+    // Keeps the cell borrowed while `value` is alive, `None` when borrowed through `&mut ResManager`.
+    _guard: Option<Ref<'a, Box<dyn Resource>>>,
 }
 
 impl<'a, T> Res<'a, T> {
     fn new(value: &'a T) -> Self {
-        Self { value }
+        Self { value, _guard: None }
     }
 }
 
 pub struct ResMut<'a, T> {
     value: &'a mut T,
+    _guard: Option<RefMut<'a, Box<dyn Resource>>>,
 }
 
 impl<'a, T> ResMut<'a, T> {
     fn new(content: &'a mut T) -> Self {
-        Self { value: content }
+        Self { value: content, _guard: None }
     }
 }
 
@@ -51,8 +55,12 @@ impl<'a, T> DerefMut for ResMut<'a, T> where T: Resource {
 pub trait Resource: Downcast {}
 impl_downcast!(Resource);
 
+/// Storage of all resources, one per type.
+/// Resources live in [`RefCell`]s, so systems can borrow several of them at once through
+/// [`#borrow_res`](ResManager::borrow_res) and [`#borrow_res_mut`](ResManager::borrow_res_mut);
+/// conflicting borrows inside one system panic.
 pub struct ResManager {
-    resources: HashMap<TypeId, Box<dyn Resource>>,
+    resources: HashMap<TypeId, RefCell<Box<dyn Resource>>>,
 }
 
 impl ResManager {
@@ -62,22 +70,47 @@ impl ResManager {
         }
     }
     pub fn push_res<T>(&mut self, it: T) -> anyhow::Result<()> where T: Resource {
-        if !self.resources.contains_key(&it.type_id()) {
-            self.resources.entry(it.type_id()).or_insert(Box::new(it));
+        if !self.resources.contains_key(&TypeId::of::<T>()) {
+            self.resources.entry(TypeId::of::<T>()).or_insert(RefCell::new(Box::new(it)));
             Ok(())
         } else {
             Err(Error::msg(format!("Resource 'type:[{}]' already exist!", type_name::<T>())))
         }
     }
 
+    pub fn contains_res<T>(&self) -> bool where T: Resource {
+        self.resources.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn remove_res<T>(&mut self) -> Option<T> where T: Resource {
+        let a = self.resources.remove(&TypeId::of::<T>())?;
+        a.into_inner().downcast::<T>().ok().map(|it| *it)
+    }
+
     pub fn get_res_mut<T>(&mut self) -> Option<ResMut<T>> where T: Resource {
         let a = self.resources.get_mut(&TypeId::of::<T>())?;
-        Some(ResMut::new(a.downcast_mut::<T>().unwrap()))
+        Some(ResMut::new(a.get_mut().downcast_mut::<T>().unwrap()))
     }
 
     pub fn get_res<T>(&mut self) -> Option<Res<T>> where T: Resource {
-        let a = self.resources.get(&TypeId::of::<T>())?;
-        Some(Res::new(a.downcast_ref::<T>().unwrap()))
+        self.borrow_res()
+    }
+
+    /// Borrow a resource through a shared `ResManager`, panics if it is mutably borrowed.
+    pub fn borrow_res<T>(&self) -> Option<Res<'_, T>> where T: Resource {
+        let guard = self.resources.get(&TypeId::of::<T>())?.borrow();
+        let value = guard.downcast_ref::<T>().unwrap() as *const T;
+        // SAFETY: `value` points into the boxed resource, which the guard keeps borrowed
+        // and alive for as long as the returned `Res` exists.
+        Some(Res { value: unsafe { &*value }, _guard: Some(guard) })
+    }
+
+    /// Mutably borrow a resource through a shared `ResManager`, panics if it is already borrowed.
+    pub fn borrow_res_mut<T>(&self) -> Option<ResMut<'_, T>> where T: Resource {
+        let mut guard = self.resources.get(&TypeId::of::<T>())?.borrow_mut();
+        let value = guard.downcast_mut::<T>().unwrap() as *mut T;
+        // SAFETY: see `borrow_res`, the guard is exclusive here.
+        Some(ResMut { value: unsafe { &mut *value }, _guard: Some(guard) })
     }
 }
 
@@ -1,8 +1,9 @@
-use hecs::{Query, QueryMut, World};
+use hecs::{Query, QueryBorrow, QueryMut, World};
 use winit::event::KeyboardInput;
+use std::any::type_name;
 use std::marker::PhantomData;
 use crate::render::RenderState;
-use crate::ecs::resource::ResManager;
+use crate::ecs::resource::{Res, ResManager, ResMut, Resource};
 
 pub trait System {
     fn run(&mut self, world: &mut World, res_manager: &mut ResManager);
@@ -24,7 +25,31 @@ impl<F> KeyHandleSystem for F where F: Fn(&mut RenderState, &KeyboardInput) -> (
 /// `impl System for FunctionSystem` use [`#get_param`](SystemParam::get_param) to get parameter from world.
 pub trait SystemParam {
     type Item<'world>;
-    fn get_param<'w>(world: &'w mut World, res_manager: &mut ResManager) -> Self::Item<'w>;
+    /// Invoked once before the first run of the system owning this parameter.
+    fn init(_world: &mut World, _res_manager: &mut ResManager) {}
+    fn get_param<'w>(world: &'w mut World, res_manager: &'w mut ResManager) -> Self::Item<'w>;
+}
+
+/// # Usage
+/// System parameters which only need shared access to the world and resources.
+/// A function system can take several of them, e.g. `fn(QueryBorrow<&mut A>, Res<B>, RemovedComponents<C>)`.
+/// # Explanation
+/// Borrows are checked at runtime by hecs and [`ResManager`], so conflicting parameters in one system panic.
+/// Exclusive parameters such as `&mut World` and [`QueryMut`] can only be used alone.
+pub trait SharedSystemParam {
+    type Item<'world>;
+    fn init(_world: &mut World, _res_manager: &mut ResManager) {}
+    fn get_shared<'w>(world: &'w World, res_manager: &'w ResManager) -> Self::Item<'w>;
+}
+
+impl<P> SystemParam for P where P: SharedSystemParam {
+    type Item<'world> = <P as SharedSystemParam>::Item<'world>;
+    fn init(world: &mut World, res_manager: &mut ResManager) {
+        <P as SharedSystemParam>::init(world, res_manager)
+    }
+    fn get_param<'w>(world: &'w mut World, res_manager: &'w mut ResManager) -> Self::Item<'w> {
+        P::get_shared(world, res_manager)
+    }
 }
 
 pub trait IntoSystem<Params> {
@@ -34,6 +59,7 @@ pub trait IntoSystem<Params> {
 
 pub struct FunctionSystem<F, Marker> {
     system: F,
+    initialized: bool,
     marker: PhantomData<Marker>,
 }
 
@@ -52,22 +78,114 @@ pub trait SystemParamFunction<Marker>: 'static {
 //todo implement more and test
 impl SystemParam for () {
     type Item<'world> = ();
-    fn get_param<'w>(world: &'w mut World, res_manager: &mut ResManager) -> Self::Item<'w> {}
+    fn get_param<'w>(world: &'w mut World, res_manager: &'w mut ResManager) -> Self::Item<'w> {}
 }
 impl<P1> SystemParam for (P1, ) where P1: SystemParam {
     type Item<'world> = (P1::Item<'world>, );
-    fn get_param<'w>(world: &'w mut World, res_manager: &mut ResManager) -> Self::Item<'w> {
+    fn init(world: &mut World, res_manager: &mut ResManager) {
+        P1::init(world, res_manager)
+    }
+    fn get_param<'w>(world: &'w mut World, res_manager: &'w mut ResManager) -> Self::Item<'w> {
         (P1::get_param(world, res_manager), )
     }
 }
 
+macro_rules! impl_shared_param_tuple {
+    ($($param: ident),*) => {
+        impl<$($param),*> SharedSystemParam for ($($param,)*) where $($param: SharedSystemParam),* {
+            type Item<'world> = ($(<$param as SharedSystemParam>::Item<'world>,)*);
+            fn init(world: &mut World, res_manager: &mut ResManager) {
+                $(<$param as SharedSystemParam>::init(world, res_manager);)*
+            }
+            fn get_shared<'w>(world: &'w World, res_manager: &'w ResManager) -> Self::Item<'w> {
+                ($(<$param as SharedSystemParam>::get_shared(world, res_manager),)*)
+            }
+        }
+    };
+}
+
+impl_shared_param_tuple!(P1, P2);
+impl_shared_param_tuple!(P1, P2, P3);
+impl_shared_param_tuple!(P1, P2, P3, P4);
+impl_shared_param_tuple!(P1, P2, P3, P4, P5);
+impl_shared_param_tuple!(P1, P2, P3, P4, P5, P6);
+
 impl SystemParam for &mut World{
     type Item<'world> = &'world mut World;
-    fn get_param<'w>(world: &'w mut World, res_manager: &mut ResManager) -> Self::Item<'w> {
+    fn get_param<'w>(world: &'w mut World, res_manager: &'w mut ResManager) -> Self::Item<'w> {
+        world
+    }
+}
+
+impl SharedSystemParam for &World {
+    type Item<'world> = &'world World;
+    fn get_shared<'w>(world: &'w World, _res_manager: &'w ResManager) -> Self::Item<'w> {
         world
     }
 }
 
+impl SharedSystemParam for &ResManager {
+    type Item<'world> = &'world ResManager;
+    fn get_shared<'w>(_world: &'w World, res_manager: &'w ResManager) -> Self::Item<'w> {
+        res_manager
+    }
+}
+
+impl<Qy> SharedSystemParam for QueryBorrow<'_, Qy> where Qy: Query {
+    type Item<'world> = QueryBorrow<'world, Qy>;
+    fn get_shared<'w>(world: &'w World, _res_manager: &'w ResManager) -> Self::Item<'w> {
+        world.query::<Qy>()
+    }
+}
+
+impl<T> SharedSystemParam for Res<'_, T> where T: Resource {
+    type Item<'world> = Res<'world, T>;
+    fn get_shared<'w>(_world: &'w World, res_manager: &'w ResManager) -> Self::Item<'w> {
+        res_manager.borrow_res::<T>()
+            .unwrap_or_else(|| panic!("Resource 'type:[{}]' does not exist!", type_name::<T>()))
+    }
+}
+
+impl<T> SharedSystemParam for ResMut<'_, T> where T: Resource {
+    type Item<'world> = ResMut<'world, T>;
+    fn get_shared<'w>(_world: &'w World, res_manager: &'w ResManager) -> Self::Item<'w> {
+        res_manager.borrow_res_mut::<T>()
+            .unwrap_or_else(|| panic!("Resource 'type:[{}]' does not exist!", type_name::<T>()))
+    }
+}
+
+impl<T> SharedSystemParam for Option<Res<'_, T>> where T: Resource {
+    type Item<'world> = Option<Res<'world, T>>;
+    fn get_shared<'w>(_world: &'w World, res_manager: &'w ResManager) -> Self::Item<'w> {
+        res_manager.borrow_res::<T>()
+    }
+}
+
+impl<T> SharedSystemParam for Option<ResMut<'_, T>> where T: Resource {
+    type Item<'world> = Option<ResMut<'world, T>>;
+    fn get_shared<'w>(_world: &'w World, res_manager: &'w ResManager) -> Self::Item<'w> {
+        res_manager.borrow_res_mut::<T>()
+    }
+}
+
+/// # Usage
+/// Exclusive systems `fn(&mut World, &mut ResManager)` get full access to both, e.g. to spawn entities
+/// from resources. Like other exclusive parameters they can not be combined with anything else.
+impl SystemParam for (&mut World, &mut ResManager) {
+    type Item<'world> = (&'world mut World, &'world mut ResManager);
+    fn get_param<'w>(world: &'w mut World, res_manager: &'w mut ResManager) -> Self::Item<'w> {
+        (world, res_manager)
+    }
+}
+
+impl<Func> SystemParamFunction<fn(&mut World, &mut ResManager)> for Func
+    where Func: FnMut(&mut World, &mut ResManager) + 'static {
+    type Params = (&'static mut World, &'static mut ResManager);
+    fn run<'w>(&mut self, param: <Self::Params as SystemParam>::Item<'w>) {
+        self(param.0, param.1)
+    }
+}
+
 impl<Func> SystemParamFunction<fn() -> ()> for Func where Func: FnMut() -> () + 'static {
     type Params = ();
     fn run<'w>(&mut self, param: <Self::Params as SystemParam>::Item<'w>) {
@@ -84,6 +202,27 @@ impl<Func: 'static, P1> SystemParamFunction<fn(P1,) -> ()> for Func
     }
 }
 
+macro_rules! impl_system_param_function {
+    ($($param: ident),*) => {
+        impl<Func: 'static, $($param),*> SystemParamFunction<fn($($param,)*)> for Func
+            where Func: FnMut($($param,)*) + FnMut($(<$param as SharedSystemParam>::Item<'_>,)*),
+                  $($param: SharedSystemParam),* {
+            type Params = ($($param,)*);
+            #[allow(non_snake_case)]
+            fn run<'w>(&mut self, param: <($($param,)*) as SystemParam>::Item<'w>) {
+                let ($($param,)*) = param;
+                self($($param,)*)
+            }
+        }
+    };
+}
+
+impl_system_param_function!(P1, P2);
+impl_system_param_function!(P1, P2, P3);
+impl_system_param_function!(P1, P2, P3, P4);
+impl_system_param_function!(P1, P2, P3, P4, P5);
+impl_system_param_function!(P1, P2, P3, P4, P5, P6);
+
 impl<F, Marker> IntoSystem<Marker> for F
     where
         Marker: 'static,
@@ -93,6 +232,7 @@ impl<F, Marker> IntoSystem<Marker> for F
     fn into_system(self) -> Self::Output {
         FunctionSystem {
             system: self,
+            initialized: false,
             marker: PhantomData,
         }
     }
@@ -101,13 +241,17 @@ impl<F, Marker> IntoSystem<Marker> for F
 impl<F, Marker> System for FunctionSystem<F, Marker>
     where F: SystemParamFunction<Marker> + 'static {
     fn run(&mut self, world: &mut World, res_manager: &mut ResManager) {
+        if !self.initialized {
+            F::Params::init(world, res_manager);
+            self.initialized = true;
+        }
         self.system.run(F::Params::get_param(world, res_manager));
     }
 }
 
 impl<Qy> SystemParam for QueryMut<'_, Qy> where Qy: Query{
     type Item<'world> = QueryMut<'world, Qy>;
-    fn get_param<'w>(world: &'w mut World, res_manager: &mut ResManager) -> Self::Item<'w> {
+    fn get_param<'w>(world: &'w mut World, res_manager: &'w mut ResManager) -> Self::Item<'w> {
         world.query_mut::<Qy>()
     }
 }
@@ -1,4 +1,9 @@
+use std::collections::HashSet;
+use std::hash::Hash;
 use cgmath::{Vector2, Zero};
+use terre_core_macros::Resource;
+use winit::event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+use crate::ecs::resource::{ResManager, Resource};
 
 pub struct CursorInput {
     pub cursor_position: Vector2<f64>,
@@ -12,4 +17,154 @@ impl CursorInput {
             last_cursor_position: Vector2::<f64>::zero(),
         }
     }
-}
\ No newline at end of file
+}
+
+/// Pixels scrolled by a touchpad per line of a mouse wheel.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// State of buttons, e.g. [`KeyInput`] and [`MouseButtonInput`].
+/// # Explanation
+/// `just_pressed` and `just_released` hold what changed since the last frame and are cleared after it.
+pub struct ButtonInput<T> {
+    pressed: HashSet<T>,
+    just_pressed: HashSet<T>,
+    just_released: HashSet<T>,
+}
+
+impl<T> Resource for ButtonInput<T> where T: 'static {}
+
+pub type KeyInput = ButtonInput<VirtualKeyCode>;
+pub type MouseButtonInput = ButtonInput<MouseButton>;
+
+impl<T> ButtonInput<T> where T: Copy + Eq + Hash {
+    pub fn new() -> Self {
+        Self {
+            pressed: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+        }
+    }
+
+    pub fn press(&mut self, button: T) {
+        if self.pressed.insert(button) {
+            self.just_pressed.insert(button);
+        }
+    }
+
+    pub fn release(&mut self, button: T) {
+        if self.pressed.remove(&button) {
+            self.just_released.insert(button);
+        }
+    }
+
+    pub fn pressed(&self, button: T) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    pub fn just_pressed(&self, button: T) -> bool {
+        self.just_pressed.contains(&button)
+    }
+
+    pub fn just_released(&self, button: T) -> bool {
+        self.just_released.contains(&button)
+    }
+
+    pub fn clear(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+impl<T> Default for ButtonInput<T> where T: Copy + Eq + Hash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Mouse movement since the last frame.
+#[derive(Resource, Debug)]
+pub struct MouseMotion {
+    /// Raw motion in pixels, also reported while the cursor is grabbed.
+    pub delta: Vector2<f32>,
+    /// Lines scrolled, positive away from the user.
+    pub scroll: f32,
+}
+
+impl Default for MouseMotion {
+    fn default() -> Self {
+        Self { delta: Vector2::zero(), scroll: 0.0 }
+    }
+}
+
+/// Whether the cursor is hidden and locked to the window, applied to the window after each frame.
+#[derive(Resource, Debug, Default)]
+pub struct CursorGrab {
+    pub grabbed: bool,
+}
+
+pub(crate) fn handle_window_event(res_manager: &ResManager, event: &WindowEvent) {
+    match event {
+        WindowEvent::KeyboardInput { input, .. } => {
+            let (Some(key), Some(mut keys)) = (input.virtual_keycode, res_manager.borrow_res_mut::<KeyInput>()) else { return };
+            match input.state {
+                ElementState::Pressed => keys.press(key),
+                ElementState::Released => keys.release(key),
+            }
+        }
+        WindowEvent::MouseInput { state, button, .. } => {
+            let Some(mut buttons) = res_manager.borrow_res_mut::<MouseButtonInput>() else { return };
+            match state {
+                ElementState::Pressed => buttons.press(*button),
+                ElementState::Released => buttons.release(*button),
+            }
+        }
+        WindowEvent::MouseWheel { delta, .. } => {
+            let Some(mut motion) = res_manager.borrow_res_mut::<MouseMotion>() else { return };
+            motion.scroll += match delta {
+                MouseScrollDelta::LineDelta(_, y) => *y,
+                MouseScrollDelta::PixelDelta(it) => it.y as f32 / PIXELS_PER_LINE,
+            };
+        }
+        _ => {}
+    }
+}
+
+pub(crate) fn handle_device_event(res_manager: &ResManager, event: &DeviceEvent) {
+    if let DeviceEvent::MouseMotion { delta } = event {
+        let Some(mut motion) = res_manager.borrow_res_mut::<MouseMotion>() else { return };
+        motion.delta += Vector2::new(delta.0 as f32, delta.1 as f32);
+    }
+}
+
+/// Forget what happened during the frame, after all systems saw it.
+pub(crate) fn clear_frame_input(res_manager: &ResManager) {
+    if let Some(mut keys) = res_manager.borrow_res_mut::<KeyInput>() {
+        keys.clear();
+    }
+    if let Some(mut buttons) = res_manager.borrow_res_mut::<MouseButtonInput>() {
+        buttons.clear();
+    }
+    if let Some(mut motion) = res_manager.borrow_res_mut::<MouseMotion>() {
+        **motion = MouseMotion::default();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use winit::event::VirtualKeyCode;
+    use crate::input::KeyInput;
+
+    #[test]
+    fn test_button_input() {
+        let mut keys = KeyInput::new();
+        keys.press(VirtualKeyCode::W);
+        keys.press(VirtualKeyCode::W);
+        assert!(keys.pressed(VirtualKeyCode::W) && keys.just_pressed(VirtualKeyCode::W));
+
+        keys.clear();
+        assert!(keys.pressed(VirtualKeyCode::W) && !keys.just_pressed(VirtualKeyCode::W));
+
+        keys.release(VirtualKeyCode::W);
+        assert!(!keys.pressed(VirtualKeyCode::W) && keys.just_released(VirtualKeyCode::W));
+    }
+}
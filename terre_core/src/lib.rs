@@ -4,4 +4,7 @@ pub mod ecs;
 pub mod render;
 pub mod input;
 pub mod schedule;
-pub mod asset;
\ No newline at end of file
+pub mod asset;
+pub mod task;
+pub mod time;
+pub mod voxel;
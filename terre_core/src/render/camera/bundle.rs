@@ -1,58 +1,19 @@
-use wgpu::util::DeviceExt;
-use super::*;
+use hecs::Bundle;
+use crate::render::camera::{Camera3d, Projection};
+use crate::transform::Transform;
 
+/// Components of a camera entity, add [`ActiveCamera`](super::ActiveCamera) to render from it.
+#[derive(Bundle)]
 pub struct CameraBundle {
-    pub camera: Camera,
-    pub camera_controller: CameraController,
-    pub camera_uniform: CameraUniform,
-    pub camera_buffer: wgpu::Buffer,
-    pub camera_bind_group: wgpu::BindGroup,
-    pub camera_bind_group_layout: wgpu::BindGroupLayout,
+    pub camera: Camera3d,
+    pub transform: Transform,
 }
 
 impl CameraBundle {
-    pub fn new(config: &wgpu::SurfaceConfiguration, device: &wgpu::Device) -> Self {
-        let camera = Camera::new(config.width as f32 / config.height as f32);
-        let camera_controller = CameraController::new(0.2);
-
-        let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update(&camera);
-
-        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Camera Buffer"),
-            contents: bytemuck::cast_slice(&[camera_uniform]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-            label: Some("camera_bind_group_layout"),
-        });
-
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
-        });
-
+    pub fn new(projection: Projection, transform: Transform) -> Self {
         Self {
-            camera,
-            camera_bind_group,
-            camera_uniform,
-            camera_controller,
-            camera_buffer,
-            camera_bind_group_layout,
+            camera: Camera3d::new(projection),
+            transform,
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,235 @@
+use cgmath::{InnerSpace, Point3, Quaternion, Rad, Rotation3, Vector2, Vector3, Zero};
+use hecs::QueryBorrow;
+use winit::event::{MouseButton, VirtualKeyCode};
+use crate::app::{App, Plugin};
+use crate::ecs::resource::{Res, ResMut};
+use crate::input::{CursorGrab, KeyInput, MouseButtonInput, MouseMotion};
+use crate::schedule::Stage;
+use crate::time::Time;
+use crate::transform::Transform;
+
+/// Pitch stays just short of straight up or down, where yaw is undefined.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+fn yaw_pitch_rotation(yaw: f32, pitch: f32) -> Quaternion<f32> {
+    Quaternion::from_angle_y(Rad(yaw)) * Quaternion::from_angle_x(Rad(pitch))
+}
+
+/// First person controls for a camera [`Transform`], added by [`FlyCameraPlugin`].
+/// # Usage
+/// `WASD` or arrows to move, `Space` and `LShift` to rise and sink, the mouse to look around.
+/// Clicking into the window grabs the cursor, `Escape` releases it.
+/// # Explanation
+/// Yaw and pitch are read back from the forward axis of the transform each frame,
+/// so the camera can also be moved by other systems; roll is dropped.
+#[derive(Copy, Clone, Debug)]
+pub struct FlyCamera {
+    /// Units per second.
+    pub speed: f32,
+    /// Radians per pixel of mouse motion.
+    pub sensitivity: f32,
+    /// Grab the cursor on click, otherwise the mouse only looks around while the cursor is grabbed elsewhere.
+    pub grab_cursor: bool,
+}
+
+impl Default for FlyCamera {
+    fn default() -> Self {
+        Self {
+            speed: 5.0,
+            sensitivity: 0.002,
+            grab_cursor: true,
+        }
+    }
+}
+
+impl FlyCamera {
+    /// Move by the pressed `keys` for `delta_seconds` and look around by `look` pixels.
+    pub fn update(&self, transform: &mut Transform, keys: &KeyInput, look: Vector2<f32>, delta_seconds: f32) {
+        let forward = transform.forward();
+        let mut yaw = (-forward.x).atan2(-forward.z);
+        let mut pitch = forward.y.clamp(-1.0, 1.0).asin();
+        yaw -= look.x * self.sensitivity;
+        pitch = (pitch - look.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        transform.rotation = yaw_pitch_rotation(yaw, pitch);
+
+        // Move on the ground plane, regardless of pitch
+        let forward = Vector3::new(-yaw.sin(), 0.0, -yaw.cos());
+        let right = Vector3::new(yaw.cos(), 0.0, -yaw.sin());
+        let axis = |positive: &[VirtualKeyCode], negative: &[VirtualKeyCode]| {
+            let pressed = |keys_of: &[VirtualKeyCode]| keys_of.iter().any(|it| keys.pressed(*it)) as i32 as f32;
+            pressed(positive) - pressed(negative)
+        };
+        let direction = forward * axis(&[VirtualKeyCode::W, VirtualKeyCode::Up], &[VirtualKeyCode::S, VirtualKeyCode::Down])
+            + right * axis(&[VirtualKeyCode::D, VirtualKeyCode::Right], &[VirtualKeyCode::A, VirtualKeyCode::Left])
+            + Vector3::unit_y() * axis(&[VirtualKeyCode::Space], &[VirtualKeyCode::LShift]);
+        if direction.magnitude2() > 0.0 {
+            transform.position += direction.normalize() * self.speed * delta_seconds;
+        }
+    }
+}
+
+/// Controls for looking at a point from around it, added by [`OrbitCameraPlugin`].
+/// # Usage
+/// Drag with the left mouse button to orbit, with the right or middle button to pan, scroll to zoom.
+#[derive(Copy, Clone, Debug)]
+pub struct OrbitCamera {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    /// Radians around the `Y` axis, zero looks along `-Z`.
+    pub yaw: f32,
+    /// Radians above the horizon, positive looks up at the target.
+    pub pitch: f32,
+    /// Radians per pixel of mouse motion.
+    pub orbit_sensitivity: f32,
+    /// Fraction of the distance panned per pixel of mouse motion.
+    pub pan_sensitivity: f32,
+    /// Fraction of the distance zoomed per scrolled line.
+    pub zoom_sensitivity: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+}
+
+impl Default for OrbitCamera {
+    fn default() -> Self {
+        Self {
+            target: Point3::new(0.0, 0.0, 0.0),
+            distance: 10.0,
+            yaw: 0.0,
+            pitch: -0.4,
+            orbit_sensitivity: 0.005,
+            pan_sensitivity: 0.001,
+            zoom_sensitivity: 0.1,
+            min_distance: 0.5,
+            max_distance: 500.0,
+        }
+    }
+}
+
+impl OrbitCamera {
+    pub fn new(target: Point3<f32>, distance: f32) -> Self {
+        Self { target, distance, ..Default::default() }
+    }
+
+    pub fn orbit(&mut self, delta: Vector2<f32>) {
+        self.yaw -= delta.x * self.orbit_sensitivity;
+        self.pitch = (self.pitch - delta.y * self.orbit_sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Move the target within the view plane, following the cursor.
+    pub fn pan(&mut self, delta: Vector2<f32>) {
+        let rotation = yaw_pitch_rotation(self.yaw, self.pitch);
+        let offset = rotation * Vector3::new(-delta.x, delta.y, 0.0);
+        self.target += offset * self.pan_sensitivity * self.distance;
+    }
+
+    /// Positive `lines` move closer, scaling the distance so zooming feels the same at any range.
+    pub fn zoom(&mut self, lines: f32) {
+        let factor = (1.0 - self.zoom_sensitivity).powf(lines);
+        self.distance = (self.distance * factor).clamp(self.min_distance, self.max_distance);
+    }
+
+    /// Place `transform` at `distance` from the target, looking at it.
+    pub fn apply(&self, transform: &mut Transform) {
+        transform.rotation = yaw_pitch_rotation(self.yaw, self.pitch);
+        transform.position = self.target.to_homogeneous().truncate() - transform.forward() * self.distance;
+    }
+}
+
+pub struct FlyCameraPlugin;
+
+impl Plugin for FlyCameraPlugin {
+    fn build(&self, app: App) -> App {
+        app.add_system(Stage::Update, fly_camera)
+    }
+}
+
+pub struct OrbitCameraPlugin;
+
+impl Plugin for OrbitCameraPlugin {
+    fn build(&self, app: App) -> App {
+        app.add_system(Stage::Update, orbit_camera)
+    }
+}
+
+fn fly_camera(
+    mut query: QueryBorrow<(&FlyCamera, &mut Transform)>,
+    keys: Res<KeyInput>,
+    buttons: Res<MouseButtonInput>,
+    motion: Res<MouseMotion>,
+    mut grab: ResMut<CursorGrab>,
+    time: Res<Time>,
+) {
+    for (_, (camera, transform)) in query.iter() {
+        if camera.grab_cursor {
+            if buttons.just_pressed(MouseButton::Left) {
+                grab.grabbed = true;
+            }
+            if keys.just_pressed(VirtualKeyCode::Escape) {
+                grab.grabbed = false;
+            }
+        }
+        let look = match grab.grabbed {
+            true => motion.delta,
+            false => Vector2::zero(),
+        };
+        camera.update(transform, &keys, look, time.delta_seconds());
+    }
+}
+
+fn orbit_camera(
+    mut query: QueryBorrow<(&mut OrbitCamera, &mut Transform)>,
+    buttons: Res<MouseButtonInput>,
+    motion: Res<MouseMotion>,
+) {
+    for (_, (camera, transform)) in query.iter() {
+        if buttons.pressed(MouseButton::Left) {
+            camera.orbit(motion.delta);
+        }
+        if buttons.pressed(MouseButton::Right) || buttons.pressed(MouseButton::Middle) {
+            camera.pan(motion.delta);
+        }
+        if motion.scroll != 0.0 {
+            camera.zoom(motion.scroll);
+        }
+        camera.apply(transform);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{InnerSpace, MetricSpace, Point3, Vector2, Vector3};
+    use winit::event::VirtualKeyCode;
+    use crate::input::KeyInput;
+    use crate::render::camera::controller::{FlyCamera, MAX_PITCH, OrbitCamera};
+    use crate::transform::Transform;
+
+    #[test]
+    fn test_fly_camera_moves_with_delta_time() {
+        let mut transform = Transform::from_position(Vector3::new(0.0, 0.0, 0.0));
+        let mut keys = KeyInput::new();
+        keys.press(VirtualKeyCode::W);
+
+        let camera = FlyCamera { speed: 4.0, ..Default::default() };
+        camera.update(&mut transform, &keys, Vector2::new(0.0, 0.0), 0.5);
+        assert!((transform.position - Vector3::new(0.0, 0.0, -2.0)).magnitude() < 1e-5);
+
+        // Looking far up stops short of straight up
+        camera.update(&mut transform, &KeyInput::new(), Vector2::new(0.0, -1.0e5), 0.5);
+        assert!((transform.forward().y.asin() - MAX_PITCH).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_orbit_camera_keeps_distance() {
+        let mut camera = OrbitCamera::new(Point3::new(1.0, 2.0, 3.0), 10.0);
+        let mut transform = Transform::from_position(Vector3::new(0.0, 0.0, 0.0));
+        camera.orbit(Vector2::new(200.0, -50.0));
+        camera.apply(&mut transform);
+
+        let position = Point3::new(transform.position.x, transform.position.y, transform.position.z);
+        assert!((position.distance(camera.target) - 10.0).abs() < 1e-4);
+        assert!((transform.forward() - (camera.target - position).normalize()).magnitude() < 1e-4);
+
+        camera.zoom(1000.0);
+        assert_eq!(camera.distance, camera.min_distance);
+    }
+}
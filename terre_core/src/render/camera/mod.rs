@@ -1,13 +1,235 @@
 pub mod bundle;
+pub mod controller;
 
 use std::ops::Add;
-use cgmath::{InnerSpace, Quaternion, SquareMatrix, Vector3, Zero};
+use cgmath::{Deg, InnerSpace, Matrix4, Quaternion, SquareMatrix, Vector3, Vector4, Zero};
 use bytemuck::Zeroable;
+use hecs::{Entity, World};
 use winit::event::{ElementState, VirtualKeyCode};
 use terre_core_macros::Resource;
+use crate::asset::handle::Handle;
+use crate::ecs::resource::ResManager;
+use crate::render::Target;
+use crate::render::extract::{RenderAssets, RenderWorld};
+use crate::render::texture::Texture;
+use crate::transform::Transform;
+
+/// How a [`Camera3d`] maps its view onto the screen.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Perspective {
+        /// Vertical field of view in degrees.
+        fovy: f32,
+        znear: f32,
+        zfar: f32,
+    },
+    Orthographic {
+        /// Height of the view in world units, the width follows the aspect ratio.
+        height: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective { fovy: 45.0, znear: 0.1, zfar: 100.0 }
+    }
+}
+
+impl Projection {
+    /// Projection in OpenGL clip space for a target of `aspect` width over height.
+    pub fn matrix(&self, aspect: f32) -> Matrix4<f32> {
+        match *self {
+            Projection::Perspective { fovy, znear, zfar } => cgmath::perspective(Deg(fovy), aspect, znear, zfar),
+            Projection::Orthographic { height, znear, zfar } => {
+                let (half_width, half_height) = (height * aspect / 2.0, height / 2.0);
+                cgmath::ortho(-half_width, half_width, -half_height, half_height, znear, zfar)
+            }
+        }
+    }
+}
+
+/// Where a [`Camera3d`] draws to.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum RenderTarget {
+    #[default]
+    Window,
+    /// A texture made by [`Texture::create_render_target`], skipped until it is in [`Assets<Texture>`].
+    Texture(Handle<Texture>),
+}
+
+/// Part of a [`RenderTarget`] a camera draws to, in fractions of its size from the top left corner.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub const FULL: Viewport = Viewport { x: 0.0, y: 0.0, width: 1.0, height: 1.0 };
+
+    /// `[x, y, width, height]` in pixels of a target of `width` by `height`, at least one pixel large.
+    pub fn to_pixels(&self, width: u32, height: u32) -> [f32; 4] {
+        let (width, height) = (width as f32, height as f32);
+        let x = (self.x * width).clamp(0.0, width - 1.0).floor();
+        let y = (self.y * height).clamp(0.0, height - 1.0).floor();
+        [
+            x,
+            y,
+            (self.width * width).round().clamp(1.0, width - x),
+            (self.height * height).round().clamp(1.0, height - y),
+        ]
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self::FULL
+    }
+}
+
+/// Camera looking along the forward (`-Z`) axis of the [`Transform`] of its entity.
+/// # Usage
+/// Spawn with [`CameraBundle`](bundle::CameraBundle), and mark cameras to render from with [`ActiveCamera`].
+/// Several cameras can share a target, e.g. a minimap over the main view or split screen, drawn in order of `priority`.
+/// # Explanation
+/// The view is built from the position and rotation of the [`Transform`] itself, parents are ignored.
+/// The first camera drawing to a target clears it with [`ClearColor`](crate::render::ClearColor),
+/// later ones draw over it and only clear depth.
+#[derive(Clone, Debug, Default)]
+pub struct Camera3d {
+    pub projection: Projection,
+    pub target: RenderTarget,
+    pub viewport: Viewport,
+    /// Cameras are drawn from low to high priority.
+    pub priority: i32,
+}
+
+impl Camera3d {
+    pub fn new(projection: Projection) -> Self {
+        Self { projection, ..Default::default() }
+    }
+
+    pub fn with_target(mut self, target: RenderTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn with_viewport(mut self, viewport: Viewport) -> Self {
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn view_matrix(transform: &Transform) -> Matrix4<f32> {
+        let world = Matrix4::from_translation(transform.position) * Matrix4::from(transform.rotation);
+        world.invert().unwrap_or_else(Matrix4::identity)
+    }
+
+    /// View projection in wgpu clip space for a target of `aspect` width over height.
+    pub fn view_projection(&self, transform: &Transform, aspect: f32) -> Matrix4<f32> {
+        crate::render::OPENGL_TO_WGPU_MATRIX * self.projection.matrix(aspect) * Self::view_matrix(transform)
+    }
+
+    /// Ray through `cursor`, in pixels from the top left of a target of `size`, see [`Camera::screen_ray`].
+    pub fn screen_ray(&self, transform: &Transform, cursor: [f32; 2], size: [u32; 2]) -> Option<Ray> {
+        let aspect = size[0].max(1) as f32 / size[1].max(1) as f32;
+        Ray::unproject(self.view_projection(transform, aspect), cursor, size, 0.0)
+    }
+}
+
+/// Marks [`Camera3d`]s the scene is rendered from.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ActiveCamera;
+
+/// Entities with [`ActiveCamera`], [`Camera3d`] and [`Transform`], ordered by [`Camera3d::priority`].
+pub fn active_cameras(world: &World) -> Vec<Entity> {
+    let mut cameras = world.query::<(&ActiveCamera, &Camera3d, &Transform)>().iter()
+        .map(|(entity, (_, camera, _))| (camera.priority, entity))
+        .collect::<Vec<_>>();
+    cameras.sort_by_key(|(priority, _)| *priority);
+    cameras.into_iter().map(|(_, entity)| entity).collect()
+}
+
+/// The active camera with the lowest priority.
+pub fn active_camera(world: &World) -> Option<Entity> {
+    active_cameras(world).first().copied()
+}
+
+/// Copy active cameras into the [`RenderWorld`].
+pub fn extract_cameras(world: &World, _: &ResManager, render_world: &mut RenderWorld) {
+    for (entity, (active, camera, transform)) in world.query::<(&ActiveCamera, &Camera3d, &Transform)>().iter() {
+        render_world.insert(entity, (*active, camera.clone(), *transform));
+    }
+}
+
+/// One camera to draw this frame, see [`FrameContext#cameras`](crate::render::FrameContext::cameras).
+pub struct CameraView {
+    /// `None` for the fallback view of the window, when there is no active camera.
+    pub entity: Option<Entity>,
+    /// Offscreen target, `None` for the window.
+    pub target: Option<Target>,
+    /// `[x, y, width, height]` in pixels.
+    pub viewport: [f32; 4],
+    pub uniform: CameraUniform,
+    /// Whether this is the first view drawing to its target.
+    pub clear: bool,
+}
+
+/// Views of all active cameras whose target exists, or a view of the window from the [`Camera`] resource
+/// when there is none, like before cameras were entities.
+/// Cameras and their target textures are read from the [`RenderWorld`].
+pub fn collect_camera_views(render_world: &RenderWorld, window: &Target) -> Vec<CameraView> {
+    let world = &render_world.world;
+    let textures = render_world.res_manager.borrow_res::<RenderAssets<Texture>>();
+    let mut cleared: Vec<RenderTarget> = vec![];
+    let mut views = vec![];
+    for entity in active_cameras(world) {
+        let mut query = world.query_one::<(&Camera3d, &Transform)>(entity).unwrap();
+        let (camera, transform) = query.get().unwrap();
+        let target = match &camera.target {
+            RenderTarget::Window => None,
+            RenderTarget::Texture(handle) => {
+                let Some(texture) = textures.as_ref().and_then(|it| it.get(handle)) else { continue };
+                Some(Target {
+                    view: texture.texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                    format: texture.texture.format(),
+                    size: texture.texture.size(),
+                })
+            }
+        };
+        let size = target.as_ref().unwrap_or(window).size;
+        let viewport = camera.viewport.to_pixels(size.width, size.height);
+        let mut uniform = CameraUniform::new();
+        uniform.update_3d(camera, transform, viewport[2] / viewport[3]);
+
+        let clear = !cleared.contains(&camera.target);
+        if clear {
+            cleared.push(camera.target.clone());
+        }
+        views.push(CameraView { entity: Some(entity), target, viewport, uniform, clear });
+    }
+
+    if views.is_empty() {
+        let mut uniform = CameraUniform::new();
+        if let Some(camera) = render_world.res_manager.borrow_res::<Camera>() {
+            uniform.update(&camera);
+        }
+        let viewport = Viewport::FULL.to_pixels(window.size.width, window.size.height);
+        views.push(CameraView { entity: None, target: None, viewport, uniform, clear: true });
+    }
+    views
+}
 
 
-#[derive(Resource)]
+#[derive(Resource, Clone)]
 pub struct Camera {
     pub eye: cgmath::Point3<f32>,
     pub target: cgmath::Point3<f32>,
@@ -49,6 +271,46 @@ impl Camera {
         let a = quaternion * offset;
         self.target = self.eye.add(a);
     }
+
+    /// Ray through `cursor`, in pixels from the top left of the window of `size`, for mouse picking.
+    /// `None` if the camera is degenerate, e.g. its eye is its target.
+    pub fn screen_ray(&self, cursor: [f32; 2], size: [u32; 2]) -> Option<Ray> {
+        // OpenGL clip space, before OPENGL_TO_WGPU_MATRIX, puts the near plane at a depth of -1
+        Ray::unproject(self.build_view_projection_matrix(), cursor, size, -1.0)
+    }
+}
+
+/// Half line from `origin` along `direction`, which is normalized.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self { origin, direction: direction.normalize() }
+    }
+
+    /// Point `distance` along the ray.
+    pub fn at(&self, distance: f32) -> Vector3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// Ray from the near to the far plane of `view_projection` through `cursor`, in pixels from the top left of `size`,
+    /// with the near plane at `near_depth` in clip space and the far plane at `1`.
+    fn unproject(view_projection: Matrix4<f32>, cursor: [f32; 2], size: [u32; 2], near_depth: f32) -> Option<Self> {
+        let inverse = view_projection.invert()?;
+        let x = cursor[0] / size[0].max(1) as f32 * 2.0 - 1.0;
+        let y = 1.0 - cursor[1] / size[1].max(1) as f32 * 2.0;
+        let point = |depth: f32| {
+            let world = inverse * Vector4::new(x, y, depth, 1.0);
+            world.truncate() / world.w
+        };
+        let (near, far) = (point(near_depth), point(1.0));
+        let direction = far - near;
+        (direction.magnitude2() > f32::EPSILON && direction.x.is_finite()).then(|| Self::new(near, direction))
+    }
 }
 
 #[repr(C)]
@@ -72,7 +334,16 @@ impl CameraUniform {
 
     pub fn update(&mut self, camera: &Camera) {
         self.view_proj = (crate::render::OPENGL_TO_WGPU_MATRIX * camera.build_view_projection_matrix()).into();
-        self.view_position = [camera.eye.x, camera.eye.y, camera.eye.y, 1f32];
+        self.view_position = [camera.eye.x, camera.eye.y, camera.eye.z, 1f32];
+    }
+
+    pub fn view_position(&self) -> cgmath::Vector3<f32> {
+        cgmath::Vector4::from(self.view_position).truncate()
+    }
+
+    pub fn update_3d(&mut self, camera: &Camera3d, transform: &Transform, aspect: f32) {
+        self.view_proj = camera.view_projection(transform, aspect).into();
+        self.view_position = transform.position.extend(1.0).into();
     }
 }
 
@@ -168,3 +439,66 @@ impl CameraController {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use cgmath::{InnerSpace, Point3, Vector3, Vector4};
+    use hecs::World;
+    use crate::render::camera::{active_camera, active_cameras, ActiveCamera, Camera, Camera3d, Projection, Viewport};
+    use crate::transform::Transform;
+
+    #[test]
+    fn test_camera_looks_at_target() {
+        let transform = Transform::looking_at(Vector3::new(0.0, 5.0, 10.0), Point3::new(0.0, 0.0, 0.0), Vector3::unit_y());
+        for projection in [Projection::default(), Projection::Orthographic { height: 10.0, znear: 0.1, zfar: 100.0 }] {
+            let clip = Camera3d::new(projection).view_projection(&transform, 16.0 / 9.0) * Vector4::new(0.0, 0.0, 0.0, 1.0);
+            let ndc = clip.truncate() / clip.w;
+            assert!(ndc.x.abs() < 1e-5 && ndc.y.abs() < 1e-5, "{:?}", ndc);
+            assert!(ndc.z > 0.0 && ndc.z < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_active_camera() {
+        let mut world = World::new();
+        let transform = || Transform::looking_at(Vector3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), Vector3::unit_y());
+        world.spawn((Camera3d::default(), transform()));
+        assert_eq!(active_camera(&world), None);
+
+        let active = world.spawn((Camera3d::default().with_priority(1), transform(), ActiveCamera));
+        assert_eq!(active_camera(&world), Some(active));
+
+        let minimap = world.spawn((Camera3d::default().with_priority(2), transform(), ActiveCamera));
+        let main = world.spawn((Camera3d::default().with_priority(-1), transform(), ActiveCamera));
+        assert_eq!(active_cameras(&world), vec![main, active, minimap]);
+    }
+
+    #[test]
+    fn test_screen_rays() {
+        let camera = Camera { eye: Point3::new(0.0, 5.0, 10.0), target: Point3::new(0.0, 5.0, 0.0), ..Camera::new(2.0) };
+        let center = camera.screen_ray([400.0, 200.0], [800, 400]).unwrap();
+        assert!((center.direction - Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-4, "{:?}", center);
+        assert!((center.origin - Vector3::new(0.0, 5.0, 10.0 - camera.znear)).magnitude() < 1e-3, "{:?}", center);
+        // The top left corner is up and to the left
+        let corner = camera.screen_ray([0.0, 0.0], [800, 400]).unwrap();
+        assert!(corner.direction.x < 0.0 && corner.direction.y > 0.0 && corner.direction.z < 0.0);
+        assert_eq!(Camera { target: camera.eye, ..camera.clone() }.screen_ray([0.0, 0.0], [800, 400]), None);
+
+        // Rays of the active camera hit what is drawn at the cursor
+        let transform = Transform::looking_at(Vector3::new(0.0, 5.0, 10.0), Point3::new(0.0, 0.0, 0.0), Vector3::unit_y());
+        let camera = Camera3d::default();
+        let clip = camera.view_projection(&transform, 2.0) * Vector4::new(1.0, 2.0, 0.0, 1.0);
+        let ndc = clip.truncate() / clip.w;
+        let cursor = [(ndc.x + 1.0) / 2.0 * 800.0, (1.0 - ndc.y) / 2.0 * 400.0];
+        let ray = camera.screen_ray(&transform, cursor, [800, 400]).unwrap();
+        let closest = ray.at((Vector3::new(1.0, 2.0, 0.0) - ray.origin).dot(ray.direction));
+        assert!((closest - Vector3::new(1.0, 2.0, 0.0)).magnitude() < 1e-3, "{:?}", closest);
+    }
+
+    #[test]
+    fn test_viewport_to_pixels() {
+        let right_half = Viewport { x: 0.5, y: 0.0, width: 0.5, height: 1.0 };
+        assert_eq!(right_half.to_pixels(800, 600), [400.0, 0.0, 400.0, 600.0]);
+        assert_eq!(Viewport { x: 1.0, y: 1.0, width: 0.0, height: 0.0 }.to_pixels(800, 600), [799.0, 599.0, 1.0, 1.0]);
+    }
+}
@@ -0,0 +1,260 @@
+//! Copies of what rendering needs from the game world, taken once per frame.
+//! # Explanation
+//! A frame renders in three phases:
+//! - *extract*: after all updates, [`ExtractSchedule`] copies render relevant components and resources
+//!   (transforms, renderers, lights, cameras) into the [`RenderWorld`];
+//! - *prepare*: every [`Node#prepare`](crate::render::graph::Node::prepare) uploads what it draws from the copy;
+//! - *render*: every [`Node#run`](crate::render::graph::Node::run) records its commands.
+//!
+//! Only extract touches the game world, so the next frame can be simulated while this one renders.
+//! Assets live on the GPU already and are shared with [`RenderAssets`] rather than copied.
+use std::collections::HashMap;
+use std::sync::Arc;
+use hecs::{Component, DynamicBundle, Entity, World};
+use crate::asset::Asset;
+use crate::asset::assets::{AssetEvent, Assets};
+use crate::asset::handle::{Handle, HandleId};
+use crate::ecs::event::Events;
+use crate::ecs::resource::{ResManager, Resource};
+
+/// Entities and resources extracted for rendering this frame.
+/// # Explanation
+/// Entities keep their ids from the game world, so data cached per entity stays valid across frames.
+pub struct RenderWorld {
+    pub world: World,
+    pub res_manager: ResManager,
+}
+
+impl RenderWorld {
+    pub fn new() -> Self {
+        Self {
+            world: World::new(),
+            res_manager: ResManager::new(),
+        }
+    }
+
+    /// Add `components` to `entity` of the game world, spawning it under the same id if needed.
+    pub fn insert(&mut self, entity: Entity, components: impl DynamicBundle) {
+        if self.world.contains(entity) {
+            self.world.insert(entity, components).unwrap();
+        } else {
+            self.world.spawn_at(entity, components);
+        }
+    }
+
+    /// Insert or replace a resource.
+    pub fn insert_res<T>(&mut self, resource: T) where T: Resource {
+        if let Some(mut it) = self.res_manager.get_res_mut::<T>() {
+            **it = resource;
+            return;
+        }
+        self.res_manager.push_res(resource).unwrap();
+    }
+}
+
+impl Default for RenderWorld {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub type ExtractFn = Box<dyn FnMut(&World, &ResManager, &mut RenderWorld)>;
+
+/// Functions run in the extract phase, see [`App#add_extract`](crate::app::App::add_extract).
+pub struct ExtractSchedule {
+    extracts: Vec<ExtractFn>,
+}
+
+impl ExtractSchedule {
+    pub fn new() -> Self {
+        Self { extracts: vec![] }
+    }
+
+    pub fn add_extract(&mut self, extract: impl FnMut(&World, &ResManager, &mut RenderWorld) + 'static) {
+        self.extracts.push(Box::new(extract));
+    }
+
+    /// Replace the entities of `render_world` with fresh copies, resources are overwritten by their extracts.
+    pub fn run(&mut self, world: &World, res_manager: &ResManager, render_world: &mut RenderWorld) {
+        render_world.world.clear();
+        self.extracts.iter_mut().for_each(|extract| extract(world, res_manager, render_world));
+    }
+}
+
+impl Default for ExtractSchedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Copy component `T` of every entity having it.
+pub fn extract_component<T>(world: &World, _: &ResManager, render_world: &mut RenderWorld) where T: Component + Clone {
+    for (entity, component) in world.query::<&T>().iter() {
+        render_world.insert(entity, (component.clone(),));
+    }
+}
+
+/// Copy resource `T`, if the game has it.
+pub fn extract_resource<T>(_: &World, res_manager: &ResManager, render_world: &mut RenderWorld) where T: Resource + Clone {
+    let Some(resource) = res_manager.borrow_res::<T>().map(|it| (**it).clone()) else { return };
+    render_world.insert_res(resource);
+}
+
+/// Assets of type `T` as the render world sees them, kept in sync with [`Assets<T>`](Assets) by [`extract_assets`].
+/// # Explanation
+/// Entries share the assets of the game, so extracting only updates the entries of assets which changed this frame.
+pub struct RenderAssets<T> {
+    assets: HashMap<HandleId, Arc<T>>,
+    events: Vec<AssetEvent<T>>,
+}
+
+impl<T> Resource for RenderAssets<T> where T: Asset {}
+
+impl<T> RenderAssets<T> where T: Asset {
+    pub fn new() -> Self {
+        Self {
+            assets: HashMap::new(),
+            events: vec![],
+        }
+    }
+
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.get_by_id(handle.id())
+    }
+
+    pub fn get_by_id(&self, id: HandleId) -> Option<&T> {
+        self.assets.get(&id).map(|it| &**it)
+    }
+
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// Changes of the assets since the previous extract, for render nodes caching data of assets.
+    pub fn events(&self) -> &[AssetEvent<T>] {
+        &self.events
+    }
+
+    /// Copy the assets named by `events` from `assets`, or all of them when `all`.
+    fn sync<'a>(&mut self, assets: &Assets<T>, events: impl Iterator<Item = &'a AssetEvent<T>>, all: bool) {
+        if all {
+            self.assets = assets.iter().filter_map(|(id, _)| Some((id, assets.get_shared(id)?))).collect();
+        }
+        self.events.clear();
+        for event in events {
+            let id = event.handle().id();
+            match assets.get_shared(id) {
+                Some(asset) => self.assets.insert(id, asset),
+                None => self.assets.remove(&id),
+            };
+            self.events.push(event.clone());
+        }
+    }
+}
+
+impl<T> Default for RenderAssets<T> where T: Asset {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Update [`RenderAssets<T>`](RenderAssets) with the assets which changed this frame,
+/// added for every asset type by [`App#add_asset`](crate::app::App::add_asset).
+pub fn extract_assets<T>(_: &World, res_manager: &ResManager, render_world: &mut RenderWorld) where T: Asset {
+    let Some(assets) = res_manager.borrow_res::<Assets<T>>() else { return };
+    let events = res_manager.borrow_res::<Events<AssetEvent<T>>>();
+    let all = !render_world.res_manager.contains_res::<RenderAssets<T>>();
+    if all {
+        render_world.insert_res(RenderAssets::<T>::new());
+    }
+    let mut render_assets = render_world.res_manager.get_res_mut::<RenderAssets<T>>().unwrap();
+    render_assets.sync(&assets, events.iter().flat_map(|it| it.iter()), all);
+}
+
+#[cfg(test)]
+mod test {
+    use hecs::World;
+    use crate::asset::assets::{AssetEvent, Assets};
+    use crate::ecs::event::Events;
+    use crate::ecs::resource::ResManager;
+    use crate::render::ClearColor;
+    use crate::render::extract::{extract_assets, extract_component, extract_resource, ExtractSchedule, RenderAssets, RenderWorld};
+
+    #[test]
+    fn test_extract() {
+        let mut world = World::new();
+        let mut res_manager = ResManager::new();
+        res_manager.push_res(ClearColor(wgpu::Color::RED)).unwrap();
+        let entity = world.spawn((1u32, "not rendered"));
+
+        let mut schedule = ExtractSchedule::new();
+        schedule.add_extract(extract_component::<u32>);
+        schedule.add_extract(extract_resource::<ClearColor>);
+        let mut render_world = RenderWorld::new();
+        schedule.run(&world, &res_manager, &mut render_world);
+
+        assert_eq!(*render_world.world.get::<&u32>(entity).unwrap(), 1);
+        assert!(render_world.world.get::<&&str>(entity).is_err());
+        assert_eq!(render_world.res_manager.borrow_res::<ClearColor>().unwrap().0, wgpu::Color::RED);
+
+        // Changes reach the copy on the next extract, despawned entities leave it
+        res_manager.get_res_mut::<ClearColor>().unwrap().0 = wgpu::Color::BLUE;
+        world.despawn(entity).unwrap();
+        schedule.run(&world, &res_manager, &mut render_world);
+        assert!(!render_world.world.contains(entity));
+        assert_eq!(render_world.res_manager.borrow_res::<ClearColor>().unwrap().0, wgpu::Color::BLUE);
+    }
+
+    #[test]
+    fn test_extract_assets() {
+        fn flush(res_manager: &mut ResManager) {
+            let events = res_manager.get_res_mut::<Assets<String>>().unwrap().drain_events().collect::<Vec<_>>();
+            let mut sent = res_manager.get_res_mut::<Events<AssetEvent<String>>>().unwrap();
+            sent.clear();
+            sent.send_batch(events);
+        }
+        let world = World::new();
+        let mut res_manager = ResManager::new();
+        res_manager.push_res(Assets::<String>::new()).unwrap();
+        res_manager.push_res(Events::<AssetEvent<String>>::new()).unwrap();
+        let stone = res_manager.get_res_mut::<Assets<String>>().unwrap().add("stone".to_string());
+        let mut render_world = RenderWorld::new();
+
+        // Assets added before the first extract are copied all at once
+        flush(&mut res_manager);
+        extract_assets::<String>(&world, &res_manager, &mut render_world);
+        let render_assets = render_world.res_manager.borrow_res::<RenderAssets<String>>().unwrap();
+        assert_eq!(render_assets.get(&stone).unwrap(), "stone");
+        drop(render_assets);
+
+        // Later ones follow the events of the frame, which nodes can read until the next extract
+        let dirt = res_manager.get_res_mut::<Assets<String>>().unwrap().add("dirt".to_string());
+        res_manager.get_res_mut::<Assets<String>>().unwrap().set(&stone, "cobblestone".to_string());
+        flush(&mut res_manager);
+        extract_assets::<String>(&world, &res_manager, &mut render_world);
+        let render_assets = render_world.res_manager.borrow_res::<RenderAssets<String>>().unwrap();
+        assert_eq!(render_assets.get(&stone).unwrap(), "cobblestone");
+        assert_eq!(render_assets.get(&dirt).unwrap(), "dirt");
+        assert_eq!(render_assets.events().len(), 2);
+        drop(render_assets);
+
+        // Changing an extracted asset leaves the copy of the render world alone
+        *res_manager.get_res_mut::<Assets<String>>().unwrap().get_mut(&dirt).unwrap() = "mud".to_string();
+        let render_assets = render_world.res_manager.borrow_res::<RenderAssets<String>>().unwrap();
+        assert_eq!(render_assets.get(&dirt).unwrap(), "dirt");
+        drop(render_assets);
+
+        let weak = dirt.weak();
+        drop(dirt);
+        res_manager.get_res_mut::<Assets<String>>().unwrap().free_unused();
+        flush(&mut res_manager);
+        extract_assets::<String>(&world, &res_manager, &mut render_world);
+        let render_assets = render_world.res_manager.borrow_res::<RenderAssets<String>>().unwrap();
+        assert!(render_assets.get(&weak).is_none());
+        assert_eq!(render_assets.len(), 1);
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::anyhow;
+use crate::render::{FrameContext, RenderContext, Target};
+use crate::render::extract::RenderWorld;
+use crate::render::texture::Texture;
+
+/// Anchors every [`RenderGraph`] starts with, run in this order.
+/// Nodes are placed relative to them with [`RenderGraph#add_node_after`](RenderGraph::add_node_after)
+/// and [`RenderGraph#add_node_before`](RenderGraph::add_node_before).
+pub mod anchor {
+    pub const SHADOW: &str = "shadow";
+    pub const OPAQUE: &str = "opaque";
+    pub const TRANSPARENT: &str = "transparent";
+    pub const POST: &str = "post";
+    pub const UI: &str = "ui";
+
+    pub const ALL: [&str; 5] = [SHADOW, OPAQUE, TRANSPARENT, POST, UI];
+}
+
+/// Depth of every camera view, allocated by the graph with [`AttachmentDesc::depth`].
+/// Nodes drawing the views in turn pass it on, the first one clearing it.
+pub const DEPTH_ATTACHMENT: &str = "depth";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SlotType {
+    TextureView,
+    /// A texture view per camera view, see [`SlotValue::CameraTextures`].
+    CameraTextures,
+    Buffer,
+}
+
+#[derive(Clone)]
+pub enum SlotValue {
+    TextureView(Arc<wgpu::TextureView>),
+    /// A texture view per camera view, in the order of [`FrameContext::cameras`].
+    CameraTextures(Vec<Arc<wgpu::TextureView>>),
+    Buffer(Arc<wgpu::Buffer>),
+}
+
+impl SlotValue {
+    pub fn slot_type(&self) -> SlotType {
+        match self {
+            SlotValue::TextureView(_) => SlotType::TextureView,
+            SlotValue::CameraTextures(_) => SlotType::CameraTextures,
+            SlotValue::Buffer(_) => SlotType::Buffer,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub name: &'static str,
+    pub slot_type: SlotType,
+}
+
+impl SlotInfo {
+    pub const fn new(name: &'static str, slot_type: SlotType) -> Self {
+        Self { name, slot_type }
+    }
+}
+
+/// Inputs of a running [`Node`], and where it puts its outputs.
+pub struct NodeContext {
+    inputs: HashMap<&'static str, SlotValue>,
+    outputs: HashMap<&'static str, SlotValue>,
+}
+
+impl NodeContext {
+    pub fn input(&self, name: &str) -> anyhow::Result<&SlotValue> {
+        self.inputs.get(name).ok_or_else(|| anyhow!("Input slot '{}' is not connected", name))
+    }
+
+    pub fn input_texture(&self, name: &str) -> anyhow::Result<&wgpu::TextureView> {
+        match self.input(name)? {
+            SlotValue::TextureView(it) => Ok(it),
+            _ => Err(anyhow!("Input slot '{}' is not a texture view", name)),
+        }
+    }
+
+    /// Texture views of the camera views, indexed like [`FrameContext::cameras`].
+    pub fn input_camera_textures(&self, name: &str) -> anyhow::Result<&[Arc<wgpu::TextureView>]> {
+        match self.input(name)? {
+            SlotValue::CameraTextures(it) => Ok(it),
+            _ => Err(anyhow!("Input slot '{}' is not a texture view per camera", name)),
+        }
+    }
+
+    pub fn input_buffer(&self, name: &str) -> anyhow::Result<&wgpu::Buffer> {
+        match self.input(name)? {
+            SlotValue::Buffer(it) => Ok(it),
+            _ => Err(anyhow!("Input slot '{}' is not a buffer", name)),
+        }
+    }
+
+    pub fn set_output(&mut self, name: &'static str, value: SlotValue) {
+        self.outputs.insert(name, value);
+    }
+}
+
+/// A step of rendering in a [`RenderGraph`].
+pub trait Node {
+    fn inputs(&self) -> Vec<SlotInfo> {
+        vec![]
+    }
+
+    fn outputs(&self) -> Vec<SlotInfo> {
+        vec![]
+    }
+
+    /// Upload what this frame draws, before any node runs.
+    /// Entities, resources and [`RenderAssets`](crate::render::extract::RenderAssets) are read from `world`,
+    /// extracted from the game. A failing node is skipped this frame.
+    fn prepare(
+        &mut self,
+        _world: &RenderWorld,
+        _context: &mut RenderContext,
+        _frame_context: &FrameContext,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Record commands into `frame_context`, reading connected inputs from `graph` and setting outputs on it.
+    fn run(
+        &mut self,
+        graph: &mut NodeContext,
+        world: &RenderWorld,
+        context: &mut RenderContext,
+        frame_context: &mut FrameContext,
+    ) -> anyhow::Result<()>;
+}
+
+/// Node running nothing, marking a place in the graph.
+struct AnchorNode;
+
+impl Node for AnchorNode {
+    fn run(&mut self, _: &mut NodeContext, _: &RenderWorld, _: &mut RenderContext, _: &mut FrameContext) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttachmentSize {
+    /// Follows the size of the window.
+    Window,
+    Fixed(u32, u32),
+    /// One texture per camera view, each following the size of the target of its view.
+    Camera,
+}
+
+/// Texture owned by the graph, reallocated when its size changes.
+#[derive(Copy, Clone, Debug)]
+pub struct AttachmentDesc {
+    pub format: wgpu::TextureFormat,
+    pub size: AttachmentSize,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl AttachmentDesc {
+    /// Depth buffer of every camera view, see [`DEPTH_ATTACHMENT`].
+    pub fn depth() -> Self {
+        Self {
+            format: Texture::DEPTH_FORMAT,
+            size: AttachmentSize::Camera,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+
+    /// Type of the inputs the attachment can be connected to.
+    pub fn slot_type(&self) -> SlotType {
+        match self.size {
+            AttachmentSize::Camera => SlotType::CameraTextures,
+            AttachmentSize::Window | AttachmentSize::Fixed(..) => SlotType::TextureView,
+        }
+    }
+}
+
+struct Attachment {
+    desc: AttachmentDesc,
+    /// Textures with their sizes, one per camera view for [`AttachmentSize::Camera`], otherwise one.
+    allocated: Vec<((u32, u32), Arc<wgpu::TextureView>)>,
+}
+
+impl Attachment {
+    fn value(&self) -> Option<SlotValue> {
+        let views = self.allocated.iter().map(|(_, it)| it.clone());
+        match self.desc.size {
+            AttachmentSize::Camera => Some(SlotValue::CameraTextures(views.collect())),
+            AttachmentSize::Window | AttachmentSize::Fixed(..) => views.map(SlotValue::TextureView).next(),
+        }
+    }
+}
+
+/// Where an input slot takes its value from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SlotSource {
+    Node { node: String, slot: &'static str },
+    Attachment(String),
+}
+
+struct NodeState {
+    name: String,
+    node: Box<dyn Node>,
+    inputs: HashMap<&'static str, SlotSource>,
+    /// Whether [`Node#prepare`](Node::prepare) succeeded this frame.
+    prepared: bool,
+}
+
+/// Nodes of rendering, run once per frame in an order satisfying their dependencies.
+/// # Usage
+/// Plugins add nodes relative to the [`anchor`]s through [`App#configure_render_graph`](crate::app::App::configure_render_graph),
+/// since nodes are created once the device exists.
+/// Inputs are connected to outputs of other nodes with [`#add_slot_edge`](RenderGraph::add_slot_edge),
+/// or to textures allocated by the graph with [`#add_attachment_edge`](RenderGraph::add_attachment_edge).
+/// # Explanation
+/// A node runs after the nodes it takes inputs from and after those ordered before it with
+/// [`#add_node_edge`](RenderGraph::add_node_edge); otherwise nodes run in the order they were added.
+pub struct RenderGraph {
+    nodes: Vec<NodeState>,
+    /// `(before, after)` pairs of node indices, besides slot edges.
+    node_edges: Vec<(usize, usize)>,
+    attachments: HashMap<String, Attachment>,
+    order: Option<Vec<usize>>,
+}
+
+impl RenderGraph {
+    /// A graph with the [`anchor`]s and a [`DEPTH_ATTACHMENT`].
+    pub fn new() -> Self {
+        let mut graph = Self::empty();
+        for (i, anchor) in anchor::ALL.iter().enumerate() {
+            graph.add_node(anchor, AnchorNode).unwrap();
+            if i > 0 {
+                graph.add_node_edge(anchor::ALL[i - 1], anchor).unwrap();
+            }
+        }
+        graph.add_attachment(DEPTH_ATTACHMENT, AttachmentDesc::depth());
+        graph
+    }
+
+    /// A graph without anchors or attachments.
+    pub fn empty() -> Self {
+        Self {
+            nodes: vec![],
+            node_edges: vec![],
+            attachments: HashMap::new(),
+            order: None,
+        }
+    }
+
+    fn index(&self, name: &str) -> anyhow::Result<usize> {
+        self.nodes.iter().position(|it| it.name == name).ok_or_else(|| anyhow!("No render node '{}'", name))
+    }
+
+    pub fn contains_node(&self, name: &str) -> bool {
+        self.index(name).is_ok()
+    }
+
+    pub fn add_node(&mut self, name: &str, node: impl Node + 'static) -> anyhow::Result<()> {
+        if self.contains_node(name) {
+            anyhow::bail!("Render node '{}' already exists", name);
+        }
+        self.nodes.push(NodeState { name: name.to_string(), node: Box::new(node), inputs: HashMap::new(), prepared: false });
+        self.order = None;
+        Ok(())
+    }
+
+    /// Add a node running after `anchor`, and before the next anchor if there is one.
+    pub fn add_node_after(&mut self, anchor: &str, name: &str, node: impl Node + 'static) -> anyhow::Result<()> {
+        self.add_node(name, node)?;
+        self.add_node_edge(anchor, name)?;
+        if let Some(next) = anchor::ALL.iter().position(|it| *it == anchor).and_then(|it| anchor::ALL.get(it + 1)) {
+            if self.contains_node(next) {
+                self.add_node_edge(name, next)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a node running before `anchor`, and after the previous anchor if there is one.
+    pub fn add_node_before(&mut self, anchor: &str, name: &str, node: impl Node + 'static) -> anyhow::Result<()> {
+        self.add_node(name, node)?;
+        self.add_node_edge(name, anchor)?;
+        if let Some(previous) = anchor::ALL.iter().position(|it| *it == anchor).filter(|it| *it > 0).map(|it| anchor::ALL[it - 1]) {
+            if self.contains_node(previous) {
+                self.add_node_edge(previous, name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `after` after `before`.
+    pub fn add_node_edge(&mut self, before: &str, after: &str) -> anyhow::Result<()> {
+        let edge = (self.index(before)?, self.index(after)?);
+        self.node_edges.push(edge);
+        self.order = None;
+        Ok(())
+    }
+
+    fn input_slot(&self, node: usize, slot: &str) -> anyhow::Result<SlotInfo> {
+        self.nodes[node].node.inputs().into_iter().find(|it| it.name == slot)
+            .ok_or_else(|| anyhow!("Render node '{}' has no input '{}'", self.nodes[node].name, slot))
+    }
+
+    /// Feed the output `from_slot` of `from` into the input `to_slot` of `to`, which then runs after `from`.
+    pub fn add_slot_edge(&mut self, from: &str, from_slot: &str, to: &str, to_slot: &str) -> anyhow::Result<()> {
+        let (from_index, to_index) = (self.index(from)?, self.index(to)?);
+        let output = self.nodes[from_index].node.outputs().into_iter().find(|it| it.name == from_slot)
+            .ok_or_else(|| anyhow!("Render node '{}' has no output '{}'", from, from_slot))?;
+        let input = self.input_slot(to_index, to_slot)?;
+        if output.slot_type != input.slot_type {
+            anyhow::bail!("Can not connect {:?} '{}.{}' to {:?} '{}.{}'", output.slot_type, from, from_slot, input.slot_type, to, to_slot);
+        }
+        self.nodes[to_index].inputs.insert(input.name, SlotSource::Node { node: from.to_string(), slot: output.name });
+        self.order = None;
+        Ok(())
+    }
+
+    /// Texture allocated and resized by the graph, connected to inputs with [`#add_attachment_edge`](RenderGraph::add_attachment_edge).
+    pub fn add_attachment(&mut self, name: &str, desc: AttachmentDesc) {
+        self.attachments.insert(name.to_string(), Attachment { desc, allocated: vec![] });
+    }
+
+    pub fn add_attachment_edge(&mut self, attachment: &str, to: &str, to_slot: &str) -> anyhow::Result<()> {
+        let Some(slot_type) = self.attachments.get(attachment).map(|it| it.desc.slot_type()) else {
+            anyhow::bail!("No render attachment '{}'", attachment);
+        };
+        let to_index = self.index(to)?;
+        let input = self.input_slot(to_index, to_slot)?;
+        if input.slot_type != slot_type {
+            anyhow::bail!("Can not connect attachment '{}' to {:?} '{}.{}'", attachment, input.slot_type, to, to_slot);
+        }
+        self.nodes[to_index].inputs.insert(input.name, SlotSource::Attachment(attachment.to_string()));
+        Ok(())
+    }
+
+    /// Names of the nodes in the order they run, fails on cycles.
+    pub fn order(&mut self) -> anyhow::Result<Vec<&str>> {
+        let order = self.sorted()?;
+        Ok(order.iter().map(|it| self.nodes[*it].name.as_str()).collect())
+    }
+
+    fn sorted(&mut self) -> anyhow::Result<Vec<usize>> {
+        if let Some(order) = &self.order {
+            return Ok(order.clone());
+        }
+        let mut edges = self.node_edges.clone();
+        for (to, state) in self.nodes.iter().enumerate() {
+            for source in state.inputs.values() {
+                if let SlotSource::Node { node, .. } = source {
+                    edges.push((self.index(node)?, to));
+                }
+            }
+        }
+
+        // Kahn's algorithm, taking the earliest added node whenever several are ready
+        let mut incoming = vec![0; self.nodes.len()];
+        edges.iter().for_each(|(_, to)| incoming[*to] += 1);
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut done = vec![false; self.nodes.len()];
+        while order.len() < self.nodes.len() {
+            let Some(next) = (0..self.nodes.len()).find(|it| !done[*it] && incoming[*it] == 0) else {
+                let stuck = (0..self.nodes.len()).filter(|it| !done[*it]).map(|it| self.nodes[it].name.as_str()).collect::<Vec<_>>();
+                anyhow::bail!("Render graph has a cycle between {:?}", stuck);
+            };
+            done[next] = true;
+            order.push(next);
+            edges.iter().filter(|(from, _)| *from == next).for_each(|(_, to)| incoming[*to] -= 1);
+        }
+        self.order = Some(order.clone());
+        Ok(order)
+    }
+
+    /// Allocate attachments read by any node, at their current size.
+    fn allocate_attachments(&mut self, device: &wgpu::Device, frame_context: &FrameContext) {
+        let size_of = |it: &Target| (it.size.width, it.size.height);
+        let read = self.nodes.iter()
+            .flat_map(|it| it.inputs.values())
+            .filter_map(|it| match it {
+                SlotSource::Attachment(name) => Some(name.clone()),
+                SlotSource::Node { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        for (name, attachment) in self.attachments.iter_mut() {
+            if !read.contains(name) {
+                continue;
+            }
+            let sizes = match attachment.desc.size {
+                AttachmentSize::Window => vec![size_of(&frame_context.output)],
+                AttachmentSize::Fixed(width, height) => vec![(width, height)],
+                AttachmentSize::Camera => frame_context.cameras.iter().map(|it| size_of(frame_context.target(it))).collect(),
+            };
+            // Textures are kept at the same index while their size stays the same
+            attachment.allocated.truncate(sizes.len());
+            for (i, size) in sizes.into_iter().enumerate() {
+                if attachment.allocated.get(i).is_some_and(|(allocated, _)| *allocated == size) {
+                    continue;
+                }
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(name),
+                    size: wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: attachment.desc.format,
+                    usage: attachment.desc.usage,
+                    view_formats: &[],
+                });
+                let view = (size, Arc::new(texture.create_view(&wgpu::TextureViewDescriptor::default())));
+                match i < attachment.allocated.len() {
+                    true => attachment.allocated[i] = view,
+                    false => attachment.allocated.push(view),
+                }
+            }
+        }
+    }
+
+    /// Prepare all nodes in order, a failing node is logged and not run this frame.
+    pub fn prepare(&mut self, world: &RenderWorld, context: &mut RenderContext, frame_context: &FrameContext) {
+        let order = match self.sorted() {
+            Ok(it) => it,
+            Err(err) => {
+                log::error!("{}", err);
+                return;
+            }
+        };
+        for index in order {
+            let state = &mut self.nodes[index];
+            state.prepared = match state.node.prepare(world, context, frame_context) {
+                Ok(()) => true,
+                Err(err) => {
+                    log::error!("Preparing render node '{}' failed: {}", state.name, err);
+                    false
+                }
+            };
+        }
+    }
+
+    /// Run all prepared nodes, a failing node is logged and skipped along with nodes missing its outputs.
+    pub fn run(&mut self, world: &RenderWorld, context: &mut RenderContext, frame_context: &mut FrameContext) {
+        let order = match self.sorted() {
+            Ok(it) => it,
+            Err(err) => {
+                log::error!("{}", err);
+                return;
+            }
+        };
+        self.allocate_attachments(&context.device, frame_context);
+
+        let mut outputs: HashMap<(String, &'static str), SlotValue> = HashMap::new();
+        'nodes: for index in order {
+            if !self.nodes[index].prepared {
+                continue;
+            }
+            let mut node_context = NodeContext { inputs: HashMap::new(), outputs: HashMap::new() };
+            for (slot, source) in self.nodes[index].inputs.iter() {
+                let value = match source {
+                    SlotSource::Node { node, slot } => outputs.get(&(node.clone(), *slot)).cloned(),
+                    SlotSource::Attachment(name) => self.attachments.get(name).and_then(|it| it.value()),
+                };
+                let Some(value) = value else {
+                    log::warn!("Skipping render node '{}', input '{}' has no value", self.nodes[index].name, slot);
+                    continue 'nodes;
+                };
+                node_context.inputs.insert(*slot, value);
+            }
+
+            let state = &mut self.nodes[index];
+            if let Err(err) = state.node.run(&mut node_context, world, context, frame_context) {
+                log::error!("Render node '{}' failed: {}", state.name, err);
+                continue;
+            }
+            for (slot, value) in node_context.outputs {
+                outputs.insert((state.name.clone(), slot), value);
+            }
+        }
+    }
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::render::{FrameContext, RenderContext};
+    use crate::render::extract::RenderWorld;
+    use crate::render::graph::{anchor, DEPTH_ATTACHMENT, Node, NodeContext, RenderGraph, SlotInfo, SlotType};
+
+    struct TestNode {
+        inputs: Vec<SlotInfo>,
+        outputs: Vec<SlotInfo>,
+    }
+
+    impl TestNode {
+        fn new(inputs: &[SlotInfo], outputs: &[SlotInfo]) -> Self {
+            Self { inputs: inputs.to_vec(), outputs: outputs.to_vec() }
+        }
+    }
+
+    impl Node for TestNode {
+        fn inputs(&self) -> Vec<SlotInfo> {
+            self.inputs.clone()
+        }
+
+        fn outputs(&self) -> Vec<SlotInfo> {
+            self.outputs.clone()
+        }
+
+        fn run(&mut self, _: &mut NodeContext, _: &RenderWorld, _: &mut RenderContext, _: &mut FrameContext) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    const COLOR: SlotInfo = SlotInfo::new("color", SlotType::TextureView);
+    const DEPTH: SlotInfo = SlotInfo::new("depth", SlotType::CameraTextures);
+    const LIGHTS: SlotInfo = SlotInfo::new("lights", SlotType::Buffer);
+
+    #[test]
+    fn test_nodes_ordered_by_anchors_and_slots() {
+        let mut graph = RenderGraph::new();
+        graph.add_node_after(anchor::POST, "bloom", TestNode::new(&[COLOR], &[])).unwrap();
+        graph.add_node_after(anchor::OPAQUE, "main", TestNode::new(&[COLOR, DEPTH], &[COLOR])).unwrap();
+        graph.add_node_before(anchor::OPAQUE, "shadows", TestNode::new(&[], &[])).unwrap();
+        graph.add_slot_edge("main", "color", "bloom", "color").unwrap();
+        graph.add_attachment_edge(DEPTH_ATTACHMENT, "main", "depth").unwrap();
+        assert!(graph.add_attachment_edge(DEPTH_ATTACHMENT, "main", "color").is_err());
+
+        assert_eq!(
+            graph.order().unwrap(),
+            vec!["shadow", "shadows", "opaque", "main", "transparent", "post", "bloom", "ui"],
+        );
+    }
+
+    #[test]
+    fn test_invalid_edges() {
+        let mut graph = RenderGraph::empty();
+        graph.add_node("a", TestNode::new(&[COLOR], &[COLOR])).unwrap();
+        graph.add_node("b", TestNode::new(&[COLOR, LIGHTS], &[COLOR])).unwrap();
+
+        assert!(graph.add_node("a", TestNode::new(&[], &[])).is_err());
+        assert!(graph.add_slot_edge("a", "color", "b", "lights").is_err());
+        assert!(graph.add_slot_edge("a", "depth", "b", "color").is_err());
+        assert!(graph.add_attachment_edge(DEPTH_ATTACHMENT, "a", "color").is_err());
+
+        graph.add_slot_edge("a", "color", "b", "color").unwrap();
+        graph.add_slot_edge("b", "color", "a", "color").unwrap();
+        assert!(graph.order().is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Vector3, Vector4};
+use hecs::World;
+use terre_core_macros::Resource;
+use crate::ecs::resource::ResManager;
+use crate::render::extract::RenderWorld;
+use crate::transform::GlobalTransform;
+
+const KIND_DIRECTIONAL: u32 = 0;
+const KIND_POINT: u32 = 1;
+const KIND_SPOT: u32 = 2;
+
+/// Light reaching every surface from every direction, added to the diffuse light of all lit materials.
+#[derive(Resource, Copy, Clone, Debug)]
+pub struct AmbientLight {
+    pub color: [f32; 3],
+    pub brightness: f32,
+}
+
+impl Default for AmbientLight {
+    fn default() -> Self {
+        Self { color: [1.0; 3], brightness: 0.05 }
+    }
+}
+
+impl AmbientLight {
+    pub fn uniform(&self) -> [f32; 4] {
+        let [r, g, b] = self.color.map(|it| it * self.brightness);
+        [r, g, b, 1.0]
+    }
+}
+
+/// Light shining from infinitely far away along the forward (`-Z`) axis of its [`GlobalTransform`], like the sun.
+#[derive(Copy, Clone, Debug)]
+pub struct DirectionalLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self { color: [1.0; 3], intensity: 1.0 }
+    }
+}
+
+/// Light shining in every direction from the position of its [`GlobalTransform`], fading out at `range`.
+#[derive(Copy, Clone, Debug)]
+pub struct PointLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+}
+
+impl Default for PointLight {
+    fn default() -> Self {
+        Self { color: [1.0; 3], intensity: 1.0, range: 10.0 }
+    }
+}
+
+/// [`PointLight`] limited to a cone around the forward (`-Z`) axis of its [`GlobalTransform`].
+/// Full brightness within `inner_angle`, fading to nothing at `outer_angle`, both in radians from the axis.
+#[derive(Copy, Clone, Debug)]
+pub struct SpotLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        Self {
+            color: [1.0; 3],
+            intensity: 1.0,
+            range: 10.0,
+            inner_angle: 0.3,
+            outer_angle: 0.5,
+        }
+    }
+}
+
+/// GPU layout of one light, matching `Light` in the shaders.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LightRaw {
+    pub position: [f32; 3],
+    pub kind: u32,
+    /// Direction the light shines along, normalized.
+    pub direction: [f32; 3],
+    pub range: f32,
+    /// Color multiplied by intensity.
+    pub color: [f32; 3],
+    pub inner_cos: f32,
+    pub outer_cos: f32,
+    _padding: [u32; 3],
+}
+
+unsafe impl Zeroable for LightRaw {}
+
+unsafe impl Pod for LightRaw {}
+
+impl LightRaw {
+    fn new(kind: u32, global: &GlobalTransform, color: [f32; 3], intensity: f32) -> Self {
+        let position = global.0.w.truncate();
+        let direction = (global.0 * Vector4::new(0.0, 0.0, -1.0, 0.0)).truncate();
+        let direction = match direction.magnitude2() > 0.0 {
+            true => direction.normalize(),
+            false => -Vector3::unit_z(),
+        };
+        Self {
+            position: position.into(),
+            kind,
+            direction: direction.into(),
+            range: 0.0,
+            color: color.map(|it| it * intensity),
+            inner_cos: 0.0,
+            outer_cos: 0.0,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Collect lights of the world, directional lights first, then point and spot lights,
+/// dropping whatever exceeds `max_lights`.
+pub fn gather_lights(world: &World, max_lights: usize) -> Vec<LightRaw> {
+    let mut lights = vec![];
+    for (_, (global, light)) in world.query::<(&GlobalTransform, &DirectionalLight)>().iter() {
+        lights.push(LightRaw::new(KIND_DIRECTIONAL, global, light.color, light.intensity));
+    }
+    for (_, (global, light)) in world.query::<(&GlobalTransform, &PointLight)>().iter() {
+        lights.push(LightRaw {
+            range: light.range,
+            ..LightRaw::new(KIND_POINT, global, light.color, light.intensity)
+        });
+    }
+    for (_, (global, light)) in world.query::<(&GlobalTransform, &SpotLight)>().iter() {
+        lights.push(LightRaw {
+            range: light.range,
+            inner_cos: light.inner_angle.cos(),
+            outer_cos: light.outer_angle.cos(),
+            ..LightRaw::new(KIND_SPOT, global, light.color, light.intensity)
+        });
+    }
+    lights.truncate(max_lights);
+    lights
+}
+
+/// Copy lights into the [`RenderWorld`].
+pub fn extract_lights(world: &World, _: &ResManager, render_world: &mut RenderWorld) {
+    for (entity, (global, light)) in world.query::<(&GlobalTransform, &DirectionalLight)>().iter() {
+        render_world.insert(entity, (*global, *light));
+    }
+    for (entity, (global, light)) in world.query::<(&GlobalTransform, &PointLight)>().iter() {
+        render_world.insert(entity, (*global, *light));
+    }
+    for (entity, (global, light)) in world.query::<(&GlobalTransform, &SpotLight)>().iter() {
+        render_world.insert(entity, (*global, *light));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{Matrix3, Matrix4, Quaternion, Rad, Rotation3, Vector3};
+    use hecs::World;
+    use crate::render::light::{DirectionalLight, gather_lights, KIND_DIRECTIONAL, KIND_POINT, PointLight, SpotLight};
+    use crate::transform::GlobalTransform;
+
+    fn global(position: Vector3<f32>, rotation: Quaternion<f32>) -> GlobalTransform {
+        GlobalTransform(Matrix4::from_translation(position) * Matrix4::from(rotation), Matrix3::from(rotation))
+    }
+
+    #[test]
+    fn test_gather_lights() {
+        let mut world = World::new();
+        let down = Quaternion::from_angle_x(Rad(-std::f32::consts::FRAC_PI_2));
+        world.spawn((global(Vector3::new(1.0, 2.0, 3.0), Quaternion::new(1.0, 0.0, 0.0, 0.0)), PointLight { intensity: 2.0, ..Default::default() }));
+        world.spawn((global(Vector3::new(0.0, 0.0, 0.0), down), DirectionalLight::default()));
+        world.spawn((global(Vector3::new(0.0, 5.0, 0.0), down), SpotLight::default()));
+
+        let lights = gather_lights(&world, 2);
+        assert_eq!(lights.len(), 2);
+        assert_eq!(lights[0].kind, KIND_DIRECTIONAL);
+        assert!((lights[0].direction[1] + 1.0).abs() < 1e-5);
+        assert_eq!(lights[1].kind, KIND_POINT);
+        assert_eq!(lights[1].position, [1.0, 2.0, 3.0]);
+        assert_eq!(lights[1].color, [2.0; 3]);
+        assert_eq!(std::mem::size_of_val(&lights[0]), 64);
+    }
+}
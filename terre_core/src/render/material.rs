@@ -1,5 +1,112 @@
+use std::ops::BitOr;
+use bytemuck::{Pod, Zeroable};
+use crate::asset::Asset;
+use crate::asset::handle::Handle;
+use crate::render::texture::Texture;
 
-//todo
-pub struct Material{
+/// Switches of a [`Material`], also read by shaders.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub struct MaterialFlags(pub u32);
 
-}
\ No newline at end of file
+impl MaterialFlags {
+    pub const NONE: MaterialFlags = MaterialFlags(0);
+    /// Alpha is below one, MTL `d` or `Tr`.
+    pub const TRANSPARENT: MaterialFlags = MaterialFlags(1);
+    /// Only the base color, without lighting, MTL `illum 0`.
+    pub const UNLIT: MaterialFlags = MaterialFlags(1 << 1);
+    /// Lit without highlights, MTL `illum 1`.
+    pub const NO_SPECULAR: MaterialFlags = MaterialFlags(1 << 2);
+
+    pub fn contains(&self, other: MaterialFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: MaterialFlags) {
+        self.0 |= other.0;
+    }
+}
+
+impl BitOr for MaterialFlags {
+    type Output = MaterialFlags;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        MaterialFlags(self.0 | rhs.0)
+    }
+}
+
+/// Surface of a [`Mesh`](crate::render::model::Mesh), shared by models through [`Handle`]s.
+/// # Explanation
+/// Missing textures are replaced by neutral ones when drawing: white for diffuse and specular,
+/// and a flat normal for normal maps, so the factors alone describe untextured materials.
+pub struct Material {
+    pub name: String,
+    /// Multiplied with the diffuse texture, MTL `Kd` with `d` as alpha.
+    pub base_color: [f32; 4],
+    /// Multiplied with the specular texture, MTL `Ks`.
+    pub specular: [f32; 3],
+    /// Specular exponent, MTL `Ns`.
+    pub shininess: f32,
+    pub flags: MaterialFlags,
+    pub diffuse_texture: Option<Handle<Texture>>,
+    /// Tangent space normals, stored linear.
+    pub normal_texture: Option<Handle<Texture>>,
+    pub specular_texture: Option<Handle<Texture>>,
+}
+
+impl Asset for Material {}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            base_color: [1.0; 4],
+            specular: [0.5; 3],
+            shininess: 32.0,
+            flags: MaterialFlags::NONE,
+            diffuse_texture: None,
+            normal_texture: None,
+            specular_texture: None,
+        }
+    }
+
+    pub fn uniform(&self) -> MaterialUniform {
+        MaterialUniform {
+            base_color: self.base_color,
+            specular: self.specular,
+            shininess: self.shininess,
+            flags: self.flags.0,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// GPU layout of [`Material`] factors, matching `MaterialUniform` in the shaders.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct MaterialUniform {
+    pub base_color: [f32; 4],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub flags: u32,
+    _padding: [u32; 3],
+}
+
+unsafe impl Zeroable for MaterialUniform {}
+
+unsafe impl Pod for MaterialUniform {}
+
+#[cfg(test)]
+mod test {
+    use crate::render::material::{Material, MaterialFlags};
+
+    #[test]
+    fn test_material_flags_and_uniform() {
+        let mut material = Material::new("glass");
+        material.flags.insert(MaterialFlags::TRANSPARENT | MaterialFlags::NO_SPECULAR);
+
+        assert!(material.flags.contains(MaterialFlags::TRANSPARENT));
+        assert!(!material.flags.contains(MaterialFlags::UNLIT));
+        assert_eq!(material.uniform().flags, 0b101);
+        assert_eq!(std::mem::size_of_val(&material.uniform()), 48);
+    }
+}
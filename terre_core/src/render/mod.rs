@@ -1,24 +1,28 @@
-use std::collections::HashMap;
-use hecs::World;
-use uuid::Uuid;
+use std::sync::Arc;
 use wgpu::CommandEncoder;
 use winit::window::Window;
-use crate::render::model::Model;
-use crate::render::pass::{Pass, PassQueue};
+use terre_core_macros::Resource;
+use crate::render::camera::{CameraView, collect_camera_views};
+use crate::render::extract::RenderWorld;
+use crate::render::graph::RenderGraph;
 
 pub mod texture;
 pub mod model;
 pub mod pass;
+pub mod graph;
+pub mod extract;
 pub mod work;
 pub mod material;
+pub mod light;
 pub mod camera;
+pub mod shader;
 
 
 
 pub struct RenderState {
     pub size: winit::dpi::PhysicalSize<u32>,
     pub render_context: RenderContext,
-    pub pass_queue: PassQueue,
+    pub render_graph: RenderGraph,
     pub window: Window,
 }
 
@@ -27,19 +31,46 @@ impl RenderState {
         let size = window.inner_size();
 
         let render_context = RenderContext::new(&window).await;
-        let pass_queue = PassQueue::new();
+        let render_graph = RenderGraph::new();
 
         Self {
             size,
             render_context,
             window,
-            pass_queue,
+            render_graph,
         }
     }
+
+    /// Reconfigure the surface to the new window size, a minimized window keeps the previous size.
+    pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        self.size = size;
+        self.render_context.resize(size.width, size.height);
+    }
 }
 
 
 
+/// Color the screen is cleared to before drawing, shown wherever nothing is drawn.
+#[derive(Resource, Copy, Clone, Debug)]
+pub struct ClearColor(pub wgpu::Color);
+
+impl Default for ClearColor {
+    fn default() -> Self {
+        Self(wgpu::Color { r: 0.1, g: 0.2, b: 0.3, a: 1.0 })
+    }
+}
+
+/// Device and queue of the [`RenderContext`] as a resource, for systems creating GPU resources
+/// outside of the render graph, e.g. uploading chunk meshes. Added before start systems run.
+#[derive(Resource, Clone)]
+pub struct RenderDevice {
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+}
+
 /// Package of render target.
 /// Including *view, format, size*.
 pub struct Target {
@@ -48,19 +79,15 @@ pub struct Target {
     pub size: wgpu::Extent3d,
 }
 
-#[derive(PartialEq, Copy, Clone, Hash, Eq)]
-pub struct ModelRef(Uuid);
-
 /// The main context of Rendering,
 /// just use `RenderContext::new()` to create a new context.
 pub struct RenderContext {
     pub instance: wgpu::Instance,
     pub surface: Option<RenderSurface>,
     pub adapter: wgpu::Adapter,
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
-
-    pub models: HashMap<ModelRef, Model>,
+    /// Shared with asset loaders.
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
 }
 
 /// Struct about surface
@@ -72,8 +99,18 @@ pub struct RenderSurface {
 /// A wrapper including output view (see [`Target`]) and [`CommandEncoder`], which are used just one frame.
 /// Will be created and dropped every frame.
 pub struct FrameContext {
+    /// The window.
     pub output: Target,
+    /// Views to draw this frame in order, each into the window or its own target.
+    pub cameras: Vec<CameraView>,
     pub encoder: CommandEncoder,
+    surface_texture: Option<wgpu::SurfaceTexture>,
+}
+
+impl FrameContext {
+    pub fn target<'a>(&'a self, camera: &'a CameraView) -> &'a Target {
+        camera.target.as_ref().unwrap_or(&self.output)
+    }
 }
 
 impl Target {
@@ -146,34 +183,49 @@ impl RenderContext {
         RenderContext {
             instance,
             adapter,
-            device,
-            queue,
+            device: Arc::new(device),
+            queue: Arc::new(queue),
             surface: Some(sur),
-
-            models: HashMap::new(),
-
         }
     }
 
-    pub fn new_frame_context(&mut self) -> FrameContext {
-        let output = self.surface.as_mut().unwrap().raw.get_current_texture().unwrap();
+    /// Begin a frame on the next texture of the surface, `None` when the frame should be skipped.
+    /// An outdated or lost surface is reconfigured, so the next frame gets a texture again.
+    pub fn new_frame_context(&mut self, render_world: &RenderWorld) -> Option<FrameContext> {
+        let surface = self.surface.as_mut().unwrap();
+        let output = match surface.raw.get_current_texture() {
+            Ok(it) => it,
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                surface.update_configure(&self.device);
+                return None;
+            }
+            Err(err) => {
+                log::warn!("Skipping frame: {}", err);
+                return None;
+            }
+        };
         let desc = wgpu::TextureViewDescriptor::default();
         let view = output.texture.create_view(&desc);
         let encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Main Render Encoder"),
         });
 
-        FrameContext { output: Target { view, size: output.texture.size(), format: output.texture.format() }, encoder }
+        let target = Target { view, size: output.texture.size(), format: output.texture.format() };
+        let cameras = collect_camera_views(render_world, &target);
+        Some(FrameContext { output: target, cameras, encoder, surface_texture: Some(output) })
     }
 
+    /// Prepare and render what was extracted into `render_world` this frame, then present.
+    pub fn render_and_present(&mut self, render_world: &RenderWorld, render_graph: &mut RenderGraph) {
+        let Some(mut frame_context) = self.new_frame_context(render_world) else { return };
 
-    /// Render this frame and present
-    pub fn render_and_present(&mut self, world: &mut World, pass_queue: &mut PassQueue) {
-        let mut frame_context = self.new_frame_context();
-
-        pass_queue.draw(world, self, &mut frame_context);
+        render_graph.prepare(render_world, self, &frame_context);
+        render_graph.run(render_world, self, &mut frame_context);
 
         self.queue.submit(Some(frame_context.encoder.finish()));
+        if let Some(it) = frame_context.surface_texture {
+            it.present();
+        }
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
@@ -188,25 +240,13 @@ impl RenderContext {
         surface.config.height = height;
         surface.update_configure(&self.device);
     }
-
-    pub fn add_model(&mut self, model: Model) -> ModelRef {
-        let model_ref = ModelRef(Uuid::new_v4());
-        self.models.entry(model_ref.clone()).or_insert(model);
-        model_ref
-    }
-    pub fn remove_model(&mut self, model: &ModelRef) {
-        self.models.remove(model);
-    }
-    pub fn get_model(&mut self, model: &ModelRef) -> Option<&Model> {
-        self.models.get(model)
-    }
 }
 
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
 );
 
@@ -1,6 +1,11 @@
 use std::ops::Range;
-use wgpu::{VertexBufferLayout};
-use crate::render::texture;
+use cgmath::{InnerSpace, Vector2, Vector3, Zero};
+use wgpu::{Device, Queue, VertexBufferLayout};
+use wgpu::util::DeviceExt;
+use crate::asset::assets::Assets;
+use crate::asset::handle::Handle;
+use crate::render::material::{Material, MaterialFlags};
+use crate::render::texture::Texture;
 
 pub trait Vertex{
     fn desc() -> wgpu::VertexBufferLayout<'static>;
@@ -17,6 +22,8 @@ pub struct ModelVertex{
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
     pub normal: [f32; 3],
+    pub tangent: [f32; 3],
+    pub bitangent: [f32; 3],
 }
 
 unsafe  impl bytemuck::Zeroable for ModelVertex{}
@@ -44,14 +51,29 @@ impl Vertex for ModelVertex{
                     shader_location: 2,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 11]>() as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ]
         }
     }
 }
 
-pub struct Material{
-    pub name: String,
-    pub diffuse_texture: texture::Texture,
+/// How normals missing from a source file are generated.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Default)]
+pub enum NormalMode {
+    /// Shared between faces, see [`MeshData#compute_normals`](MeshData::compute_normals).
+    #[default]
+    Smooth,
+    /// One per face, see [`MeshData#compute_flat_normals`](MeshData::compute_flat_normals).
+    Flat,
 }
 
 pub struct Mesh{
@@ -64,5 +86,200 @@ pub struct Mesh{
 
 pub struct Model{
     pub meshes: Vec<Mesh>,
-    pub materials: Vec<Material>,
+    /// Indexed by [`Mesh::material`].
+    pub materials: Vec<Handle<Material>>,
+}
+
+/// CPU side of [`Mesh`], built off the main thread and uploaded by [`#upload`](MeshData::upload).
+/// Vertices are [`ModelVertex`]es unless drawn by a pipeline of their own, e.g. voxel chunks.
+pub struct MeshData<V = ModelVertex> {
+    pub name: String,
+    pub vertices: Vec<V>,
+    pub indices: Vec<u32>,
+    pub material: usize,
+}
+
+impl<V> MeshData<V> where V: bytemuck::Pod {
+    pub fn upload(&self, device: &Device) -> Mesh {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Vertex Buffer", self.name)),
+            contents: bytemuck::cast_slice(&self.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{:?} Index Buffer", self.name)),
+            contents: bytemuck::cast_slice(&self.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Mesh {
+            name: self.name.clone(),
+            vertex_buffer,
+            index_buffer,
+            num_elements: self.indices.len() as u32,
+            material: self.material,
+        }
+    }
+}
+
+impl MeshData {
+    /// Replace normals with smooth ones, averaged from the faces sharing each vertex and weighted by their area.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vector3::zero(); self.vertices.len()];
+        for face in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(self.vertices[face[i] as usize].position));
+            // Length is twice the area of the face.
+            let normal = (b - a).cross(c - a);
+            face.iter().for_each(|it| normals[*it as usize] += normal);
+        }
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = match normal.magnitude2() > 0.0 {
+                true => normal.normalize().into(),
+                false => [0.0, 1.0, 0.0],
+            };
+        }
+    }
+
+    /// Give every face its own vertices with the face normal, so edges stay sharp.
+    pub fn compute_flat_normals(&mut self) {
+        let mut vertices = Vec::with_capacity(self.indices.len());
+        for face in self.indices.chunks_exact(3) {
+            let mut corners = [0, 1, 2].map(|i| self.vertices[face[i] as usize]);
+            let [a, b, c] = corners.map(|it| Vector3::from(it.position));
+            let normal = (b - a).cross(c - a);
+            let normal = match normal.magnitude2() > 0.0 {
+                true => normal.normalize().into(),
+                false => [0.0, 1.0, 0.0],
+            };
+            corners.iter_mut().for_each(|it| it.normal = normal);
+            vertices.extend(corners);
+        }
+        self.indices = (0..vertices.len() as u32).collect();
+        self.vertices = vertices;
+    }
+
+    /// Compute tangents and bitangents for normal mapping from positions, texture coordinates and normals.
+    /// # Explanation
+    /// Directions of the faces sharing a vertex are summed, then made orthogonal to its normal.
+    /// Vertices without usable texture coordinates get an arbitrary tangent perpendicular to the normal.
+    pub fn compute_tangents(&mut self) {
+        let mut tangents = vec![Vector3::zero(); self.vertices.len()];
+        let mut bitangents = vec![Vector3::zero(); self.vertices.len()];
+        for face in self.indices.chunks_exact(3) {
+            let corners = [0, 1, 2].map(|i| self.vertices[face[i] as usize]);
+            let [p0, p1, p2] = corners.map(|it| Vector3::from(it.position));
+            let [uv0, uv1, uv2] = corners.map(|it| Vector2::from(it.tex_coords));
+            let (edge1, edge2) = (p1 - p0, p2 - p0);
+            let (delta_uv1, delta_uv2) = (uv1 - uv0, uv2 - uv0);
+
+            let det = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+            if det.abs() <= f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge1 * delta_uv2.y - edge2 * delta_uv1.y) / det;
+            let bitangent = (edge2 * delta_uv1.x - edge1 * delta_uv2.x) / det;
+            for i in face {
+                tangents[*i as usize] += tangent;
+                bitangents[*i as usize] += bitangent;
+            }
+        }
+
+        for (i, vertex) in self.vertices.iter_mut().enumerate() {
+            let normal = Vector3::from(vertex.normal);
+            let mut tangent = tangents[i] - normal * normal.dot(tangents[i]);
+            if tangent.magnitude2() <= f32::EPSILON {
+                tangent = perpendicular(normal);
+            }
+            let tangent = tangent.normalize();
+            let handedness = match normal.cross(tangent).dot(bitangents[i]) < 0.0 {
+                true => -1.0,
+                false => 1.0,
+            };
+            vertex.tangent = tangent.into();
+            vertex.bitangent = (normal.cross(tangent) * handedness).into();
+        }
+    }
+}
+
+fn perpendicular(normal: Vector3<f32>) -> Vector3<f32> {
+    match normal.x.abs() < 0.9 {
+        true => normal.cross(Vector3::unit_x()),
+        false => normal.cross(Vector3::unit_y()),
+    }
+}
+
+/// CPU side of [`Material`], with decoded images instead of texture handles.
+pub struct MaterialData {
+    pub name: String,
+    pub base_color: [f32; 4],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub flags: MaterialFlags,
+    pub diffuse_texture: Option<image::DynamicImage>,
+    pub normal_texture: Option<image::DynamicImage>,
+    pub specular_texture: Option<image::DynamicImage>,
+}
+
+impl MaterialData {
+    /// Plain white, used for meshes without a material.
+    pub fn white(name: &str) -> Self {
+        let material = Material::new(name);
+        Self {
+            name: material.name,
+            base_color: material.base_color,
+            specular: material.specular,
+            shininess: material.shininess,
+            flags: material.flags,
+            diffuse_texture: None,
+            normal_texture: None,
+            specular_texture: None,
+        }
+    }
+
+    /// Upload the images into `textures` and create the material referencing them.
+    pub fn upload(&self, device: &Device, queue: &Queue, textures: &mut Assets<Texture>) -> anyhow::Result<Material> {
+        let mut upload = |image: &Option<image::DynamicImage>, linear: bool| -> anyhow::Result<Option<Handle<Texture>>> {
+            let Some(image) = image else { return Ok(None) };
+            let texture = match linear {
+                true => Texture::from_normal_image(device, queue, image, Some(&self.name))?,
+                false => Texture::from_image(device, queue, image, Some(&self.name))?,
+            };
+            Ok(Some(textures.add(texture)))
+        };
+
+        Ok(Material {
+            name: self.name.clone(),
+            base_color: self.base_color,
+            specular: self.specular,
+            shininess: self.shininess,
+            flags: self.flags,
+            diffuse_texture: upload(&self.diffuse_texture, false)?,
+            normal_texture: upload(&self.normal_texture, true)?,
+            specular_texture: upload(&self.specular_texture, false)?,
+        })
+    }
+}
+
+/// CPU side of [`Model`].
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+}
+
+impl ModelData {
+    /// Upload meshes, and add materials and their textures to their storages.
+    pub fn upload(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        textures: &mut Assets<Texture>,
+        materials: &mut Assets<Material>,
+    ) -> anyhow::Result<Model> {
+        let materials = self.materials.iter()
+            .map(|it| Ok(materials.add(it.upload(device, queue, textures)?)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let meshes = self.meshes.iter().map(|it| it.upload(device)).collect();
+
+        Ok(Model { meshes, materials })
+    }
 }
@@ -0,0 +1,258 @@
+//! Pieces shared by the built-in passes: the depth slot, globals and lights of every camera view, and pipelines
+//! rebuilt when their shader is reloaded.
+use std::collections::HashMap;
+use std::mem;
+use bytemuck::{Pod, Zeroable};
+use crate::asset::assets::AssetEvent;
+use crate::asset::handle::Handle;
+use crate::render::camera::{CameraUniform, CameraView};
+use crate::render::extract::{RenderAssets, RenderWorld};
+use crate::render::graph::{SlotInfo, SlotType};
+use crate::render::light::{AmbientLight, gather_lights, LightRaw};
+use crate::render::shader::{Shader, with_validation};
+use crate::time::Time;
+
+/// Depth of every camera view, taken from [`DEPTH_ATTACHMENT`](crate::render::graph::DEPTH_ATTACHMENT)
+/// or the node drawing before, and passed on after drawing.
+pub(crate) const DEPTH_SLOT: SlotInfo = SlotInfo::new("depth", SlotType::CameraTextures);
+
+/// Uniforms shared by every draw, matching `GlobalUniform` in the shaders.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct Globals {
+    pub(crate) camera: CameraUniform,
+    /// Color multiplied by brightness, alpha unused.
+    pub(crate) ambient: [f32; 4],
+    /// Elapsed and delta seconds of [`Time`], then unused.
+    pub(crate) time: [f32; 4],
+}
+
+unsafe impl Zeroable for Globals {}
+
+unsafe impl Pod for Globals {}
+
+/// Size of the header before the lights in the light buffer, the light count padded to 16 bytes.
+pub(crate) const LIGHTS_HEADER_SIZE: usize = 16;
+
+/// Bind group 0 of the built-in passes: [`Globals`] at binding 0, lights at binding 1 and an optional sampler at binding 2.
+/// # Explanation
+/// The globals of every camera view are written once per frame, `stride` apart, and the view being drawn is selected
+/// with the dynamic offset of [`#offset`](GlobalBindings::offset).
+pub(crate) struct GlobalBindings {
+    /// Prefix of labels and logs, e.g. `[Phong]`.
+    label: &'static str,
+    pub(crate) layout: wgpu::BindGroupLayout,
+    pub(crate) bind_group: wgpu::BindGroup,
+    buffer: wgpu::Buffer,
+    stride: usize,
+    capacity: usize,
+    /// Light count followed by up to `max_lights` [`LightRaw`]s.
+    light_buffer: wgpu::Buffer,
+    max_lights: usize,
+    sampler: Option<wgpu::Sampler>,
+}
+
+impl GlobalBindings {
+    pub(crate) fn new(label: &'static str, max_lights: usize, sampler: Option<wgpu::Sampler>, device: &wgpu::Device) -> Self {
+        let max_lights = max_lights.max(1);
+        let global_size = mem::size_of::<Globals>() as wgpu::BufferAddress;
+        let mut entries = vec![
+            // Global uniforms, offset to the camera view being drawn
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(global_size),
+                },
+                count: None,
+            },
+            // Lights
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: wgpu::BufferSize::new((LIGHTS_HEADER_SIZE + mem::size_of::<LightRaw>()) as u64),
+                },
+                count: None,
+            },
+        ];
+        if sampler.is_some() {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(&format!("{} Globals", label)),
+            entries: &entries,
+        });
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as usize;
+        let stride = (global_size as usize).div_ceil(alignment) * alignment;
+        let capacity = 4;
+        let buffer = Self::create_buffer(label, device, stride, capacity);
+        // Lights are gathered from the world every frame
+        let light_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Lights", label)),
+            size: (LIGHTS_HEADER_SIZE + max_lights * mem::size_of::<LightRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = Self::create_bind_group(label, device, &layout, &buffer, &light_buffer, sampler.as_ref());
+        Self { label, layout, bind_group, buffer, stride, capacity, light_buffer, max_lights, sampler }
+    }
+
+    fn create_buffer(label: &str, device: &wgpu::Device, stride: usize, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Globals", label)),
+            size: (stride * capacity) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        label: &str,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        globals: &wgpu::Buffer,
+        lights: &wgpu::Buffer,
+        sampler: Option<&wgpu::Sampler>,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: globals,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(mem::size_of::<Globals>() as u64),
+                }),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: lights.as_entire_binding(),
+            },
+        ];
+        if let Some(sampler) = sampler {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            });
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} Globals", label)),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    /// Dynamic offset of the globals of camera view `view`.
+    pub(crate) fn offset(&self, view: usize) -> u32 {
+        (view * self.stride) as u32
+    }
+
+    /// Upload globals of every camera view and the lights of `world`,
+    /// with [`AmbientLight`] and [`Time`] falling back to their defaults when missing.
+    pub(crate) fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, world: &RenderWorld, cameras: &[CameraView]) {
+        if cameras.len() > self.capacity {
+            self.capacity = cameras.len().next_power_of_two();
+            self.buffer = Self::create_buffer(self.label, device, self.stride, self.capacity);
+            self.bind_group = Self::create_bind_group(
+                self.label, device, &self.layout, &self.buffer, &self.light_buffer, self.sampler.as_ref(),
+            );
+        }
+        let ambient = world.res_manager.borrow_res::<AmbientLight>().map(|it| **it).unwrap_or_default();
+        let time = world.res_manager.borrow_res::<Time>()
+            .map(|it| [it.elapsed_seconds(), it.delta_seconds(), 0.0, 0.0])
+            .unwrap_or_default();
+        let mut bytes = vec![0u8; self.stride * cameras.len()];
+        for (i, camera) in cameras.iter().enumerate() {
+            let globals = Globals {
+                camera: camera.uniform,
+                ambient: ambient.uniform(),
+                time,
+            };
+            let offset = i * self.stride;
+            bytes[offset..offset + mem::size_of::<Globals>()].copy_from_slice(bytemuck::bytes_of(&globals));
+        }
+        queue.write_buffer(&self.buffer, 0, &bytes);
+
+        let lights = gather_lights(&world.world, self.max_lights);
+        let header = [lights.len() as u32, 0, 0, 0];
+        queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&header));
+        if !lights.is_empty() {
+            queue.write_buffer(&self.light_buffer, LIGHTS_HEADER_SIZE as wgpu::BufferAddress, bytemuck::cast_slice(&lights));
+        }
+    }
+}
+
+/// Pipelines `P` of every target format, built from a shader which is replaced when its file is reloaded.
+pub(crate) struct ShaderPipelines<P> {
+    /// Prefix of logs, e.g. `[Phong]`.
+    label: &'static str,
+    path: &'static str,
+    /// Source of the pipelines, the built-in one until the file is loaded.
+    source: String,
+    pipelines: HashMap<wgpu::TextureFormat, P>,
+}
+
+impl<P> ShaderPipelines<P> {
+    pub(crate) fn new(label: &'static str, path: &'static str, source: &str) -> Self {
+        Self { label, path, source: source.to_string(), pipelines: HashMap::new() }
+    }
+
+    /// Build the pipelines of `format` from the current source, if missing.
+    pub(crate) fn prepare(&mut self, format: wgpu::TextureFormat, create: impl Fn(wgpu::TextureFormat, &str) -> P) {
+        if !self.pipelines.contains_key(&format) {
+            self.pipelines.insert(format, create(format, &self.source));
+        }
+    }
+
+    /// Pipelines of `format`, which must be prepared this frame.
+    pub(crate) fn get(&self, format: wgpu::TextureFormat) -> &P {
+        &self.pipelines[&format]
+    }
+
+    /// Rebuild the pipelines when `shader` was created or modified since the previous frame,
+    /// an invalid shader keeps the previous pipelines.
+    pub(crate) fn reload(
+        &mut self,
+        world: &RenderWorld,
+        shader: Option<Handle<Shader>>,
+        device: &wgpu::Device,
+        create: impl Fn(wgpu::TextureFormat, &str) -> P,
+    ) {
+        let Some(handle) = shader else { return };
+        let Some(shaders) = world.res_manager.borrow_res::<RenderAssets<Shader>>() else { return };
+        let changed = shaders.events().iter()
+            .any(|it| matches!(it, AssetEvent::Created { .. } | AssetEvent::Modified { .. }) && *it.handle() == handle);
+        if !changed {
+            return;
+        }
+        let Some(shader) = shaders.get(&handle) else { return };
+
+        // Without pipelines yet, they are built only to validate the shader.
+        let mut formats = self.pipelines.keys().copied().collect::<Vec<_>>();
+        if formats.is_empty() {
+            formats.push(wgpu::TextureFormat::Rgba8UnormSrgb);
+        }
+        let pipelines = with_validation(device, || {
+            formats.iter().map(|it| (*it, create(*it, &shader.source))).collect::<Vec<_>>()
+        });
+        match pipelines {
+            Ok(it) => {
+                log::info!("{} Pipelines rebuilt from '{}'", self.label, self.path);
+                self.source = shader.source.clone();
+                let rebuilt = std::mem::take(&mut self.pipelines).into_keys().collect::<Vec<_>>();
+                self.pipelines = it.into_iter().filter(|(format, _)| rebuilt.contains(format)).collect();
+            }
+            Err(err) => log::error!("{} Invalid shader '{}', keeping the previous pipelines: {}", self.label, self.path, err),
+        }
+    }
+}
@@ -1,216 +1,195 @@
 use std::{collections::HashMap, mem};
-use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Vector3};
 use hecs::World;
+use terre_core_macros::Resource;
 
 use wgpu::{util::DeviceExt, BindGroupLayout, StoreOp};
-use crate::render::camera::{Camera, CameraUniform};
-use crate::render::{FrameContext, model, ModelRef, RenderContext, texture};
-use crate::render::model::{Vertex};
+use crate::asset::assets::AssetEvent;
+use crate::asset::handle::{Handle, HandleId};
+use crate::asset::server::AssetServer;
+use crate::ecs::resource::ResManager;
+use crate::render::{ClearColor, FrameContext, model, RenderContext, texture};
+use crate::render::extract::{extract_resource, RenderAssets, RenderWorld};
+use crate::render::material::{Material, MaterialFlags, MaterialUniform};
+use crate::render::model::{Model, Vertex};
+use crate::render::pass::common::{DEPTH_SLOT, GlobalBindings, ShaderPipelines};
+use crate::render::shader::Shader;
+use crate::render::texture::Texture;
 use crate::render::work::Renderer3D;
 use crate::transform::{GlobalTransform, GlobalTransformRaw};
 
-use super::Pass;
+use crate::app::{App, Plugin};
+use crate::render::graph::{anchor, DEPTH_ATTACHMENT, Node, NodeContext, SlotInfo};
+use crate::schedule::Stage;
 
-#[repr(C)]
-#[derive(Clone, Copy)]
-struct Globals {
-    view_position: [f32; 4],
-    view_proj: [[f32; 4]; 4],
-    ambient: [f32; 4],
-}
-
-unsafe impl Zeroable for Globals {}
+/// Built into the binary, and replaced by the file under the asset root once it is loaded.
+const PHONG_SHADER: &str = include_str!("../../../../res/shader.wgsl");
+pub const PHONG_SHADER_PATH: &str = "shader.wgsl";
+/// Name of [`PhongPass`] in the [`RenderGraph`](crate::render::graph::RenderGraph).
+pub const PHONG_NODE: &str = "phong";
 
-unsafe impl Pod for Globals {}
+/// Handle of [`PHONG_SHADER_PATH`], loaded on start and extracted for [`PhongPass`] to watch.
+#[derive(Resource, Clone)]
+pub struct PhongShader(pub Handle<Shader>);
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone)]
-pub struct LightUniform {
-    pub position: [f32; 3],
-    // Due to uniforms requiring 16 byte (4 float) spacing, we need to use a padding field here
-    _padding: u32,
-    pub color: [f32; 3],
-    _padding2: u32,
-    pub direction: [f32; 3],
-    _padding3: u32,
+pub struct PhongConfig {
+    /// Lights beyond this count are ignored, see [`gather_lights`](crate::render::light::gather_lights).
+    pub max_lights: usize,
 }
 
-unsafe impl Zeroable for LightUniform {}
+impl Default for PhongConfig {
+    fn default() -> Self {
+        Self {
+            max_lights: 16,
+        }
+    }
+}
 
-unsafe impl Pod for LightUniform {}
+/// Uniforms and textures of one [`Material`], cached until it or one of its textures changes.
+struct MaterialBinding {
+    bind_group: wgpu::BindGroup,
+    // Kept alive with the bind group.
+    _uniform_buffer: wgpu::Buffer,
+    /// Diffuse, normal and specular textures, bound or replaced by a fallback while loading.
+    textures: [Option<HandleId>; 3],
+    /// Drawn by [`PhongPipelines::transparent`] after all opaque meshes.
+    transparent: bool,
+}
 
-pub struct PhongConfig {
-    pub max_lights: usize,
-    pub ambient: [u32; 4],
+/// Pipelines of one target format.
+struct PhongPipelines {
+    opaque: wgpu::RenderPipeline,
+    /// Blends over the opaque meshes without writing depth.
+    transparent: wgpu::RenderPipeline,
 }
 
 pub struct PhongPass {
-    // Uniforms
-    pub global_bind_group_layout: BindGroupLayout,
-    pub global_uniform_buffer: wgpu::Buffer,
-    pub global_bind_group: wgpu::BindGroup,
-    pub local_bind_group_layout: BindGroupLayout,
-    pub local_bind_groups: HashMap<ModelRef, wgpu::BindGroup>,
-    pub local_transform_buffer: wgpu::Buffer,
+    /// Globals of every camera view and the lights, with the sampler of all material textures.
+    globals: GlobalBindings,
+    pub material_bind_group_layout: BindGroupLayout,
+    material_bindings: HashMap<HandleId, MaterialBinding>,
+    /// Transforms of all drawn entities this frame, see [`GlobalTransformRaw::desc`].
+    pub instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    /// Weak handles of the models drawn this frame, the model of instance `i` at `i`.
+    drawn: Vec<Handle<Model>>,
+    /// World position of instance `i`, to sort transparent meshes by their distance to each view.
+    positions: Vec<Vector3<f32>>,
     // Textures
-    pub depth_texture: texture::Texture,
-    // Render pipeline
-    pub render_pipeline: wgpu::RenderPipeline,
-    // Lighting
-    pub light_uniform: LightUniform,
-    pub light_buffer: wgpu::Buffer,
-    // Camera
-    pub camera_uniform: CameraUniform,
+    /// Bound in place of missing diffuse and specular textures.
+    pub white_texture: texture::Texture,
+    /// Bound in place of missing normal maps.
+    pub flat_normal_texture: texture::Texture,
+    /// Pipelines per target format.
+    pipelines: ShaderPipelines<PhongPipelines>,
+    pub pipeline_layout: wgpu::PipelineLayout,
 }
 
 impl PhongPass {
     pub fn new(
-        // phong_config: &PhongConfig,
+        phong_config: &PhongConfig,
         device: &wgpu::Device,
-        // queue: &wgpu::Queue,
-        config: &wgpu::SurfaceConfiguration,
-        camera: &Camera,
+        queue: &wgpu::Queue,
     ) -> PhongPass {
-        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Normal Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../../../../res/shader.wgsl").into()),
-        });
-
-        // Setup global uniforms
-        // Global bind group layout
-        let light_size = mem::size_of::<LightUniform>() as wgpu::BufferAddress;
-        let global_size = mem::size_of::<Globals>() as wgpu::BufferAddress;
-        let global_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("[Phong] Globals"),
-                entries: &[
-                    // Global uniforms
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(global_size),
-                        },
-                        count: None,
-                    },
-                    // Lights
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(light_size),
-                        },
-                        count: None,
-                    },
-                    // Sampler for textures
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-            });
-
-        // Global uniform buffer
-        let global_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("[Phong] Globals"),
-            size: global_size,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-        // Create light uniforms and setup buffer for them
-        let light_uniform = LightUniform {
-            position: [2.0, 2.0, 2.0],
-            _padding: 0,
-            color: [1.0, 1.0, 1.0],
-            _padding2: 0,
-            direction: [1.0, 1.0, 0.0],
-            _padding3: 0,
-        };
-        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("[Phong] Lights"),
-            contents: bytemuck::cast_slice(&[light_uniform]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-        // We also need a sampler for our textures
+        // We need a sampler for our textures
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             label: Some("[Phong] sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
             min_filter: wgpu::FilterMode::Linear,
             mag_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
-        let global_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("[Phong] Globals"),
-            layout: &global_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: global_uniform_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: light_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-        });
-
+        let globals = GlobalBindings::new("[Phong]", phong_config.max_lights, Some(sampler), device);
 
-        let local_trans_size = mem::size_of::<GlobalTransformRaw>() as wgpu::BufferAddress;
-        let local_transform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor{
-            label: Some("[Phong] Transform"),
-            contents: bytemuck::cast_slice(&[GlobalTransformRaw::default()]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-        let local_bind_group_layout =
+        let material_size = mem::size_of::<MaterialUniform>() as wgpu::BufferAddress;
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let material_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                label: Some("[Phong] Locals"),
+                label: Some("[Phong] Material"),
                 entries: &[
-                    // Mesh transform
+                    // Material factors
                     wgpu::BindGroupLayoutEntry {
                         binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
                             ty: wgpu::BufferBindingType::Uniform,
                             has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(local_trans_size),
+                            min_binding_size: wgpu::BufferSize::new(material_size),
                         },
                         count: None,
                     },
-
-                    //Mesh texture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            multisampled: false,
-                        },
-                        count: None,
-                    },
-
+                    // Diffuse, normal and specular textures
+                    texture_entry(1),
+                    texture_entry(2),
+                    texture_entry(3),
                 ],
             });
 
+        let instance_capacity = 64;
+        let instance_buffer = Self::create_instance_buffer(device, instance_capacity);
+
         // Setup the render pipeline
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("[Phong] Pipeline"),
-            bind_group_layouts: &[&global_bind_group_layout, &local_bind_group_layout],
+            bind_group_layouts: &[&globals.layout, &material_bind_group_layout],
             push_constant_ranges: &[],
         });
-        let vertex_buffers = [model::ModelVertex::desc()];
+        let white = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])));
+        let white_texture = Texture::from_image(device, queue, &white, Some("[Phong] White")).unwrap();
+        let flat_normal = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255])));
+        let flat_normal_texture = Texture::from_normal_image(device, queue, &flat_normal, Some("[Phong] Flat Normal")).unwrap();
+
+        PhongPass {
+            globals,
+            material_bind_group_layout,
+            material_bindings: HashMap::new(),
+            instance_buffer,
+            instance_capacity,
+            drawn: vec![],
+            positions: vec![],
+            white_texture,
+            flat_normal_texture,
+            pipelines: ShaderPipelines::new("[Phong]", PHONG_SHADER_PATH, PHONG_SHADER),
+            pipeline_layout,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("[Phong] Instances"),
+            size: (capacity * mem::size_of::<GlobalTransformRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_render_pipeline(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        target_format: wgpu::TextureFormat,
+        source: &str,
+        transparent: bool,
+    ) -> wgpu::RenderPipeline {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Normal Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let vertex_buffers = [model::ModelVertex::desc(), GlobalTransformRaw::desc()];
         let depth_stencil = Some(wgpu::DepthStencilState {
             format: texture::Texture::DEPTH_FORMAT,
-            depth_write_enabled: true,
+            // Transparent meshes are sorted instead, so they don't hide each other
+            depth_write_enabled: !transparent,
             depth_compare: wgpu::CompareFunction::LessEqual,
             stencil: Default::default(),
             bias: Default::default(),
@@ -223,9 +202,9 @@ impl PhongPass {
             ..Default::default()
         };
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("[Phong] Pipeline"),
-            layout: Some(&pipeline_layout),
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(if transparent { "[Phong] Transparent Pipeline" } else { "[Phong] Pipeline" }),
+            layout: Some(pipeline_layout),
             vertex: wgpu::VertexState {
                 module: &shader_module,
                 entry_point: "vs_main",
@@ -238,116 +217,253 @@ impl PhongPass {
                 module: &shader_module,
                 entry_point: "fs_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState {
-                        alpha: wgpu::BlendComponent::REPLACE,
-                        color: wgpu::BlendComponent::REPLACE,
+                    format: target_format,
+                    blend: Some(match transparent {
+                        true => wgpu::BlendState::ALPHA_BLENDING,
+                        false => wgpu::BlendState::REPLACE,
                     }),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
             }),
             multiview: None,
-        });
+        })
+    }
 
-        // Create depth texture
-        let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture");
+    /// Rebuild the pipelines when [`PHONG_SHADER_PATH`] is reloaded, then create a pipeline per format of the camera targets.
+    fn prepare_pipelines(&mut self, world: &RenderWorld, device: &wgpu::Device, frame_context: &FrameContext) {
+        let layout = &self.pipeline_layout;
+        let create = |format, source: &str| PhongPipelines {
+            opaque: Self::create_render_pipeline(device, layout, format, source, false),
+            transparent: Self::create_render_pipeline(device, layout, format, source, true),
+        };
+        let shader = world.res_manager.borrow_res::<PhongShader>().map(|it| it.0.clone());
+        self.pipelines.reload(world, shader, device, create);
+        for camera in frame_context.cameras.iter() {
+            self.pipelines.prepare(frame_context.target(camera).format, create);
+        }
+    }
 
-        // Setup camera uniform
-        let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update(&camera);
+    /// Drop bind groups of changed or unloaded materials, and of materials using a texture which changed,
+    /// since a bind group may hold a fallback for a texture which was still loading.
+    fn invalidate_materials(&mut self, world: &RenderWorld) {
+        if let Some(textures) = world.res_manager.borrow_res::<RenderAssets<Texture>>() {
+            let changed = textures.events().iter().map(|it| Some(it.handle().id())).collect::<Vec<_>>();
+            if !changed.is_empty() {
+                self.material_bindings.retain(|_, binding| !binding.textures.iter().any(|it| it.is_some() && changed.contains(it)));
+            }
+        }
+        let Some(materials) = world.res_manager.borrow_res::<RenderAssets<Material>>() else { return };
+        for event in materials.events().iter() {
+            if let AssetEvent::Modified { handle } | AssetEvent::Removed { handle } = event {
+                self.material_bindings.remove(&handle.id());
+            }
+        }
+    }
 
-        PhongPass {
-            global_bind_group_layout,
-            global_uniform_buffer,
-            global_bind_group,
-            local_bind_group_layout,
-            local_transform_buffer,
-            depth_texture,
-            render_pipeline,
-            local_bind_groups: HashMap::new(),
-            camera_uniform,
-            light_uniform,
-            light_buffer,
+    fn create_material_binding(&self, device: &wgpu::Device, material: &Material, textures: &RenderAssets<Texture>) -> MaterialBinding {
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("[Phong] Material {:?}", material.name)),
+            contents: bytemuck::cast_slice(&[material.uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        fn view<'a>(textures: &'a RenderAssets<Texture>, texture: &Option<Handle<Texture>>, fallback: &'a Texture) -> &'a wgpu::TextureView {
+            texture.as_ref().and_then(|it| textures.get(it)).map_or(&fallback.view, |it| &it.view)
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("[Phong] Material"),
+            layout: &self.material_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(view(textures, &material.diffuse_texture, &self.white_texture)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(view(textures, &material.normal_texture, &self.flat_normal_texture)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(view(textures, &material.specular_texture, &self.white_texture)),
+                },
+            ],
+        });
+        let textures = [&material.diffuse_texture, &material.normal_texture, &material.specular_texture]
+            .map(|it| it.as_ref().map(Handle::id));
+        let transparent = material.flags.contains(MaterialFlags::TRANSPARENT);
+        MaterialBinding { bind_group, _uniform_buffer: uniform_buffer, textures, transparent }
+    }
+
+    fn write_instances(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[GlobalTransformRaw]) {
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
         }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(instances));
     }
 }
 
-impl Pass for PhongPass {
-    fn draw(&mut self, world: &World, context: &mut RenderContext, frame_context: &mut FrameContext) {
-        {
-            // Update GlobalUniformBuffer
-            context.queue.write_buffer(&self.global_uniform_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
-
-            let mut query = world.query::<(&GlobalTransform, &Renderer3D)>();
-
-            for (_id, (global_trans, render3d)) in query.iter(){
-                // Update transform info
-                context.queue.write_buffer(&self.local_transform_buffer, 0, bytemuck::cast_slice(&[GlobalTransformRaw::from_global_transform(global_trans)]));
-
-                let mut render_pass = frame_context.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                    label: Some("Render Pass"),
-                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: &frame_context.output.view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            // Set the clear color during redraw
-                            // This is basically a background color applied if an object isn't taking up space
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.1,
-                                g: 0.2,
-                                b: 0.3,
-                                a: 1.0,
-                            }),
-                            store: StoreOp::Store,
+impl Node for PhongPass {
+    /// Depth of every camera view.
+    fn inputs(&self) -> Vec<SlotInfo> {
+        vec![DEPTH_SLOT]
+    }
+
+    /// The input depth, after drawing, for nodes drawing over the result.
+    fn outputs(&self) -> Vec<SlotInfo> {
+        vec![DEPTH_SLOT]
+    }
+
+    fn prepare(
+        &mut self,
+        world: &RenderWorld,
+        context: &mut RenderContext,
+        frame_context: &FrameContext,
+    ) -> anyhow::Result<()> {
+        self.drawn.clear();
+        self.positions.clear();
+        self.prepare_pipelines(world, &context.device, frame_context);
+        self.invalidate_materials(world);
+        let Some(models) = world.res_manager.borrow_res::<RenderAssets<Model>>() else { return Ok(()) };
+        let Some(materials) = world.res_manager.borrow_res::<RenderAssets<Material>>() else { return Ok(()) };
+        let Some(textures) = world.res_manager.borrow_res::<RenderAssets<Texture>>() else { return Ok(()) };
+
+        self.globals.write(&context.device, &context.queue, world, &frame_context.cameras);
+
+        // Entities whose models are loaded, drawn as instance `i` of the instance buffer.
+        let mut query = world.world.query::<(&GlobalTransform, &Renderer3D)>();
+        let (drawn, transforms): (Vec<&Model>, Vec<GlobalTransformRaw>) = query.iter()
+            .filter_map(|(_, (global_trans, render3d))| {
+                let model = models.get(&render3d.model)?;
+                self.drawn.push(render3d.model.weak());
+                self.positions.push(global_trans.0.w.truncate());
+                Some((model, GlobalTransformRaw::from_global_transform(global_trans)))
+            })
+            .unzip();
+        self.write_instances(&context.device, &context.queue, &transforms);
+
+        for handle in drawn.iter().flat_map(|it| it.materials.iter()) {
+            if self.material_bindings.contains_key(&handle.id()) {
+                continue;
+            }
+            let Some(material) = materials.get(handle) else { continue };
+            let binding = self.create_material_binding(&context.device, material, &textures);
+            self.material_bindings.insert(handle.id(), binding);
+        }
+        Ok(())
+    }
+
+    fn run(
+        &mut self,
+        graph: &mut NodeContext,
+        world: &RenderWorld,
+        _context: &mut RenderContext,
+        frame_context: &mut FrameContext,
+    ) -> anyhow::Result<()> {
+        let depths = graph.input(DEPTH_SLOT.name)?.clone();
+        graph.set_output(DEPTH_SLOT.name, depths);
+        let depths = graph.input_camera_textures(DEPTH_SLOT.name)?;
+        let Some(models) = world.res_manager.borrow_res::<RenderAssets<Model>>() else { return Ok(()) };
+        // Prepared models stay in the render world until the next extract.
+        let drawn: Vec<&Model> = self.drawn.iter().filter_map(|it| models.get(it)).collect();
+
+        let clear_color = world.res_manager.borrow_res::<ClearColor>().map(|it| it.0).unwrap_or_default();
+        let FrameContext { output, cameras, encoder, .. } = frame_context;
+        for (i, camera) in cameras.iter().enumerate() {
+            let target = camera.target.as_ref().unwrap_or(output);
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        // Only the first view of a target clears it, later ones draw over it
+                        load: match camera.clear {
+                            true => wgpu::LoadOp::Clear(clear_color),
+                            false => wgpu::LoadOp::Load,
                         },
-                    })],
-                    // Create a depth stencil buffer using the depth texture
-                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                        view: &self.depth_texture.view,
-                        depth_ops: Some(wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(1.0),
-                            store: StoreOp::Store,
-                        }),
-                        stencil_ops: None,
+                        store: StoreOp::Store,
+                    },
+                })],
+                // Every view starts from its own cleared depth
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depths[i],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: StoreOp::Store,
                     }),
-                    timestamp_writes: None,
-                    occlusion_query_set: None,
-                });
-
-                render_pass.set_pipeline(&self.render_pipeline);
-                render_pass.set_bind_group(0, &self.global_bind_group, &[]);
-
-                let model = context.models.get(&render3d.model);
-
-                if let Some(model) = model {
-                    self.local_bind_groups.entry(render3d.model.clone()).or_insert(
-                        context.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                            label: Some("[Phong] Locals"),
-                            layout: &self.local_bind_group_layout,
-                            entries: &[
-                                wgpu::BindGroupEntry {
-                                    binding: 0,
-                                    resource: self.local_transform_buffer.as_entire_binding(),
-                                },
-                                wgpu::BindGroupEntry {
-                                    binding: 1,
-                                    resource: wgpu::BindingResource::TextureView(
-                                        &model.materials[0].diffuse_texture.view,
-                                    ),
-                                },
-                            ],
-                        })
-                    );
-
-                    render_pass.set_bind_group(1, &self.local_bind_groups.get(&render3d.model).unwrap(), &[]);
-
-                    for mesh in model.meshes.iter() {
-                        render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-                        render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                        render_pass.draw_indexed(0..mesh.num_elements, 0, 0..1);
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            let [x, y, width, height] = camera.viewport;
+            render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+            let pipelines = self.pipelines.get(target.format);
+            render_pass.set_pipeline(&pipelines.opaque);
+            render_pass.set_bind_group(0, &self.globals.bind_group, &[self.globals.offset(i)]);
+            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+            // Opaque meshes first, transparent ones after them from back to front.
+            let mut transparent = vec![];
+            for (instance, model) in drawn.iter().enumerate() {
+                for mesh in model.meshes.iter() {
+                    // Meshes wait for their material to be loaded.
+                    let Some(binding) = model.materials.get(mesh.material)
+                        .and_then(|it| self.material_bindings.get(&it.id())) else { continue };
+                    if binding.transparent {
+                        transparent.push((instance, mesh, binding));
+                        continue;
                     }
+                    draw_mesh(&mut render_pass, mesh, binding, instance as u32);
                 }
             }
+            if transparent.is_empty() {
+                continue;
+            }
+            let eye = camera.uniform.view_position();
+            let distance = |instance: usize| (self.positions[instance] - eye).magnitude2();
+            transparent.sort_by(|(a, ..), (b, ..)| distance(*b).total_cmp(&distance(*a)));
+            render_pass.set_pipeline(&pipelines.transparent);
+            for (instance, mesh, binding) in transparent {
+                draw_mesh(&mut render_pass, mesh, binding, instance as u32);
+            }
         }
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+fn draw_mesh<'a>(render_pass: &mut wgpu::RenderPass<'a>, mesh: &'a model::Mesh, binding: &'a MaterialBinding, instance: u32) {
+    render_pass.set_bind_group(1, &binding.bind_group, &[]);
+    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    render_pass.draw_indexed(0..mesh.num_elements, 0, instance..instance + 1);
+}
+
+/// Draws models of [`Renderer3D`] entities with [`PhongPass`] after the [`anchor::OPAQUE`] anchor.
+#[derive(Default)]
+pub struct PhongPlugin {
+    pub config: PhongConfig,
+}
+
+impl Plugin for PhongPlugin {
+    fn build(&self, app: App) -> App {
+        let max_lights = self.config.max_lights;
+        app.add_system(Stage::Start, load_phong_shader)
+            .add_extract(extract_resource::<PhongShader>)
+            .configure_render_graph(move |graph, context| {
+                let config = PhongConfig { max_lights };
+                graph.add_node_after(anchor::OPAQUE, PHONG_NODE, PhongPass::new(&config, &context.device, &context.queue))?;
+                graph.add_attachment_edge(DEPTH_ATTACHMENT, PHONG_NODE, DEPTH_SLOT.name)
+            })
+    }
+}
+
+fn load_phong_shader(_: &mut World, res_manager: &mut ResManager) {
+    let Some(handle) = res_manager.borrow_res_mut::<AssetServer>().map(|mut it| it.load(PHONG_SHADER_PATH)) else { return };
+    res_manager.push_res(PhongShader(handle)).unwrap();
+}
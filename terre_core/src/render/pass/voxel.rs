@@ -0,0 +1,396 @@
+use std::mem;
+use cgmath::{InnerSpace, Vector3};
+use hecs::World;
+use terre_core_macros::Resource;
+use wgpu::{BindGroupLayout, StoreOp};
+use crate::app::{App, Plugin};
+use crate::asset::handle::Handle;
+use crate::asset::server::AssetServer;
+use crate::ecs::resource::ResManager;
+use crate::render::{ClearColor, FrameContext, RenderContext, texture};
+use crate::render::extract::{extract_resource, RenderWorld};
+use crate::render::graph::{anchor, DEPTH_ATTACHMENT, Node, NodeContext, SlotInfo};
+use crate::render::model::Vertex;
+use crate::render::pass::common::{DEPTH_SLOT, GlobalBindings, ShaderPipelines};
+use crate::render::pass::phong::PHONG_NODE;
+use crate::render::shader::Shader;
+use crate::render::texture::TextureArrayBuilder;
+use crate::schedule::Stage;
+use crate::transform::GlobalTransform;
+use crate::voxel::chunk::CHUNK_SIZE;
+use crate::voxel::mesher::{ChunkMesh, VoxelVertex};
+use crate::voxel::registry::RenderLayer;
+use crate::voxel::textures::BlockTextures;
+
+/// Built into the binary, and replaced by the file under the asset root once it is loaded.
+const VOXEL_SHADER: &str = include_str!("../../../../res/voxel.wgsl");
+pub const VOXEL_SHADER_PATH: &str = "voxel.wgsl";
+/// Name of [`VoxelPass`] in the [`RenderGraph`](crate::render::graph::RenderGraph).
+pub const VOXEL_NODE: &str = "voxel";
+
+/// Layers drawn in this order, with the pipeline at the same index.
+const LAYERS: [RenderLayer; 3] = [RenderLayer::Opaque, RenderLayer::Cutout, RenderLayer::Translucent];
+
+/// Handle of [`VOXEL_SHADER_PATH`], loaded on start and extracted for [`VoxelPass`] to watch.
+#[derive(Resource, Clone)]
+pub struct VoxelShader(pub Handle<Shader>);
+
+/// Draws the [`ChunkMesh`]es of the voxel world into the target of every camera.
+/// # Explanation
+/// Every chunk is an instance of its own meshes, offset by its origin, and textured from the one texture array
+/// of [`BlockTextures`], so all chunks share a bind group.
+/// Opaque and cutout chunks are drawn in any order, translucent ones after them from back to front.
+///
+/// Lighting follows [`PhongPass`](crate::render::pass::phong::PhongPass) without specular,
+/// then each vertex is darkened by its ambient occlusion, see [`mesh_chunk`](crate::voxel::mesher::mesh_chunk).
+/// Each camera view is drawn with its depth from the graph, so chunks are hidden by the models of the same view.
+pub struct VoxelPass {
+    /// Globals of every camera view and the lights.
+    globals: GlobalBindings,
+    /// Layers of [`BlockTextures`] once it is added, a single white layer until then.
+    block_texture: texture::Texture,
+    /// Last [`BlockTextures`] uploaded, uploaded again once reloaded.
+    uploaded_textures: Option<BlockTextures>,
+    pub block_bind_group_layout: BindGroupLayout,
+    pub block_bind_group: wgpu::BindGroup,
+    /// Origins of the chunks drawn this frame.
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    /// Chunks drawn this frame, chunk `i` as instance `i`.
+    drawn: Vec<(ChunkMesh, Vector3<f32>)>,
+    /// One pipeline per layer of [`LAYERS`], for each target format.
+    pipelines: ShaderPipelines<[wgpu::RenderPipeline; 3]>,
+    pipeline_layout: wgpu::PipelineLayout,
+    /// Whether the pass clears the camera targets and depths, when nothing drew before it.
+    clear: bool,
+}
+
+impl VoxelPass {
+    pub fn new(max_lights: usize, clear: bool, device: &wgpu::Device, queue: &wgpu::Queue) -> VoxelPass {
+        let globals = GlobalBindings::new("[Voxel]", max_lights, None, device);
+
+        let block_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("[Voxel] Blocks"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        // Until block textures are loaded, every layer is white
+        let mut white = TextureArrayBuilder::new();
+        let white_image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])));
+        white.add("white", &white_image).unwrap();
+        let block_texture = white.build(device, queue, "[Voxel] White").unwrap();
+        let block_bind_group = Self::create_block_bind_group(device, &block_bind_group_layout, &block_texture);
+
+        let instance_capacity = 64;
+        let instance_buffer = Self::create_instance_buffer(device, instance_capacity);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("[Voxel] Pipeline"),
+            bind_group_layouts: &[&globals.layout, &block_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        VoxelPass {
+            globals,
+            block_texture,
+            uploaded_textures: None,
+            block_bind_group_layout,
+            block_bind_group,
+            instance_buffer,
+            instance_capacity,
+            drawn: vec![],
+            pipelines: ShaderPipelines::new("[Voxel]", VOXEL_SHADER_PATH, VOXEL_SHADER),
+            pipeline_layout,
+            clear,
+        }
+    }
+
+    /// Bind group of a texture array built by [`TextureArrayBuilder`].
+    pub fn create_block_bind_group(device: &wgpu::Device, layout: &BindGroupLayout, texture: &texture::Texture) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("[Voxel] Blocks"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+        })
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("[Voxel] Instances"),
+            size: (capacity * mem::size_of::<[f32; 4]>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_render_pipelines(
+        device: &wgpu::Device,
+        pipeline_layout: &wgpu::PipelineLayout,
+        target_format: wgpu::TextureFormat,
+        source: &str,
+    ) -> [wgpu::RenderPipeline; 3] {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Voxel Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let instance = wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 5,
+                format: wgpu::VertexFormat::Float32x4,
+            }],
+        };
+        let vertex_buffers = [VoxelVertex::desc(), instance];
+
+        LAYERS.map(|layer| {
+            let translucent = layer == RenderLayer::Translucent;
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(&format!("[Voxel] {:?} Pipeline", layer)),
+                layout: Some(pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &vertex_buffers,
+                },
+                primitive: wgpu::PrimitiveState {
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
+                },
+                // Translucent faces do not hide what is drawn behind them later
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: texture::Texture::DEPTH_FORMAT,
+                    depth_write_enabled: !translucent,
+                    depth_compare: wgpu::CompareFunction::LessEqual,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: Default::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: match layer {
+                        RenderLayer::Cutout => "fs_cutout",
+                        _ => "fs_main",
+                    },
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target_format,
+                        blend: Some(match translucent {
+                            true => wgpu::BlendState::ALPHA_BLENDING,
+                            false => wgpu::BlendState::REPLACE,
+                        }),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+            })
+        })
+    }
+
+    /// Rebuild the pipelines when [`VOXEL_SHADER_PATH`] is reloaded, then create pipelines per format of the camera targets.
+    fn prepare_pipelines(&mut self, world: &RenderWorld, device: &wgpu::Device, frame_context: &FrameContext) {
+        let layout = &self.pipeline_layout;
+        let create = |format, source: &str| Self::create_render_pipelines(device, layout, format, source);
+        let shader = world.res_manager.borrow_res::<VoxelShader>().map(|it| it.0.clone());
+        self.pipelines.reload(world, shader, device, create);
+        for camera in frame_context.cameras.iter() {
+            self.pipelines.prepare(frame_context.target(camera).format, create);
+        }
+    }
+
+    /// Replace the layers by those of [`BlockTextures`] once it exists, and again each time it is reloaded.
+    fn upload_block_textures(&mut self, world: &RenderWorld, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let Some(textures) = world.res_manager.borrow_res::<BlockTextures>() else { return };
+        if self.uploaded_textures.as_ref().is_some_and(|it| it.ptr_eq(&textures)) {
+            return;
+        }
+        self.uploaded_textures = Some(textures.clone());
+        match textures.array().build(device, queue, "[Voxel] Blocks") {
+            Ok(texture) => {
+                self.block_bind_group = Self::create_block_bind_group(device, &self.block_bind_group_layout, &texture);
+                self.block_texture = texture;
+            }
+            Err(err) => log::error!("[Voxel] Failed to upload block textures: {}", err),
+        }
+    }
+
+    fn write_instances(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.drawn.len() > self.instance_capacity {
+            self.instance_capacity = self.drawn.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+        }
+        let origins = self.drawn.iter().map(|(_, origin)| origin.extend(0.0).into()).collect::<Vec<[f32; 4]>>();
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&origins));
+    }
+}
+
+impl Node for VoxelPass {
+    /// Depth of every camera view.
+    fn inputs(&self) -> Vec<SlotInfo> {
+        vec![DEPTH_SLOT]
+    }
+
+    /// The input depth, after drawing, for nodes drawing over the result.
+    fn outputs(&self) -> Vec<SlotInfo> {
+        vec![DEPTH_SLOT]
+    }
+
+    fn prepare(
+        &mut self,
+        world: &RenderWorld,
+        context: &mut RenderContext,
+        frame_context: &FrameContext,
+    ) -> anyhow::Result<()> {
+        self.prepare_pipelines(world, &context.device, frame_context);
+        self.upload_block_textures(world, &context.device, &context.queue);
+        self.globals.write(&context.device, &context.queue, world, &frame_context.cameras);
+
+        let mut query = world.world.query::<(&GlobalTransform, &ChunkMesh)>();
+        self.drawn = query.iter()
+            .map(|(_, (global, mesh))| (mesh.clone(), global.0.w.truncate()))
+            .collect();
+        self.write_instances(&context.device, &context.queue);
+        Ok(())
+    }
+
+    fn run(
+        &mut self,
+        graph: &mut NodeContext,
+        world: &RenderWorld,
+        _context: &mut RenderContext,
+        frame_context: &mut FrameContext,
+    ) -> anyhow::Result<()> {
+        let depths = graph.input(DEPTH_SLOT.name)?.clone();
+        graph.set_output(DEPTH_SLOT.name, depths);
+        let depths = graph.input_camera_textures(DEPTH_SLOT.name)?;
+        let clear_color = world.res_manager.borrow_res::<ClearColor>().map(|it| it.0).unwrap_or_default();
+        let FrameContext { output, cameras, encoder, .. } = frame_context;
+        for (i, camera) in cameras.iter().enumerate() {
+            let target = camera.target.as_ref().unwrap_or(output);
+            let clear = self.clear && camera.clear;
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Voxel Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: match clear {
+                            true => wgpu::LoadOp::Clear(clear_color),
+                            false => wgpu::LoadOp::Load,
+                        },
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depths[i],
+                    depth_ops: Some(wgpu::Operations {
+                        load: match self.clear {
+                            true => wgpu::LoadOp::Clear(1.0),
+                            false => wgpu::LoadOp::Load,
+                        },
+                        store: StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            let [x, y, width, height] = camera.viewport;
+            render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+            render_pass.set_bind_group(0, &self.globals.bind_group, &[self.globals.offset(i)]);
+            render_pass.set_bind_group(1, &self.block_bind_group, &[]);
+            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+            let mut order = (0..self.drawn.len()).collect::<Vec<_>>();
+            let half = Vector3::new(1.0, 1.0, 1.0) * (CHUNK_SIZE as f32 / 2.0);
+            let eye = camera.uniform.view_position();
+            for (layer, pipeline) in LAYERS.iter().zip(self.pipelines.get(target.format).iter()) {
+                if *layer == RenderLayer::Translucent {
+                    let distance = |it: &usize| (self.drawn[*it].1 + half - eye).magnitude2();
+                    order.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+                }
+                render_pass.set_pipeline(pipeline);
+                for instance in order.iter() {
+                    let Some(mesh) = self.drawn[*instance].0.layer(*layer) else { continue };
+                    let instance = *instance as u32;
+                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    render_pass.draw_indexed(0..mesh.num_elements, 0, instance..instance + 1);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Copy chunk meshes into the [`RenderWorld`], sharing their buffers.
+pub fn extract_chunk_meshes(world: &World, _: &ResManager, render_world: &mut RenderWorld) {
+    for (entity, (global, mesh)) in world.query::<(&GlobalTransform, &ChunkMesh)>().iter() {
+        render_world.insert(entity, (*global, mesh.clone()));
+    }
+}
+
+/// Draws [`ChunkMesh`] entities with [`VoxelPass`] after the [`anchor::OPAQUE`] anchor.
+/// # Usage
+/// Add it after [`PhongPlugin`](crate::render::pass::phong::PhongPlugin) to draw with its models,
+/// otherwise the voxel pass clears the camera targets itself.
+pub struct VoxelPlugin {
+    /// Lights beyond this count are ignored, see [`gather_lights`](crate::render::light::gather_lights).
+    pub max_lights: usize,
+}
+
+impl Default for VoxelPlugin {
+    fn default() -> Self {
+        Self {
+            max_lights: 16,
+        }
+    }
+}
+
+impl Plugin for VoxelPlugin {
+    fn build(&self, app: App) -> App {
+        let max_lights = self.max_lights;
+        app.add_system(Stage::Start, load_voxel_shader)
+            .add_extract(extract_resource::<VoxelShader>)
+            .add_extract(extract_chunk_meshes)
+            .configure_render_graph(move |graph, context| {
+                let after_phong = graph.contains_node(PHONG_NODE);
+                graph.add_node_after(anchor::OPAQUE, VOXEL_NODE, VoxelPass::new(max_lights, !after_phong, &context.device, &context.queue))?;
+                match after_phong {
+                    true => graph.add_slot_edge(PHONG_NODE, DEPTH_SLOT.name, VOXEL_NODE, DEPTH_SLOT.name),
+                    false => graph.add_attachment_edge(DEPTH_ATTACHMENT, VOXEL_NODE, DEPTH_SLOT.name),
+                }
+            })
+    }
+}
+
+fn load_voxel_shader(_: &mut World, res_manager: &mut ResManager) {
+    let Some(handle) = res_manager.borrow_res_mut::<AssetServer>().map(|mut it| it.load(VOXEL_SHADER_PATH)) else { return };
+    res_manager.push_res(VoxelShader(handle)).unwrap();
+}
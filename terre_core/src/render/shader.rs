@@ -0,0 +1,41 @@
+use pollster::block_on;
+use crate::asset::Asset;
+use crate::asset::io::AssetIo;
+use crate::asset::server::AssetLoader;
+
+/// WGSL source as an asset, so passes can rebuild their pipelines when it is reloaded.
+pub struct Shader {
+    pub source: String,
+}
+
+impl Asset for Shader {}
+
+pub struct ShaderLoader;
+
+impl AssetLoader for ShaderLoader {
+    type Asset = Shader;
+    type Data = String;
+
+    fn extensions(&self) -> &[&str] {
+        &["wgsl"]
+    }
+
+    fn load(&self, path: &str, io: &dyn AssetIo) -> anyhow::Result<Self::Data> {
+        io.read_to_string(path)
+    }
+
+    fn finish(&self, data: Self::Data) -> anyhow::Result<Self::Asset> {
+        Ok(Shader { source: data })
+    }
+}
+
+/// Run `function` inside a validation error scope, so invalid shaders or pipelines
+/// are returned as errors instead of reaching the uncaptured error handler, which panics.
+pub fn with_validation<T>(device: &wgpu::Device, function: impl FnOnce() -> T) -> anyhow::Result<T> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = function();
+    match block_on(device.pop_error_scope()) {
+        None => Ok(result),
+        Some(err) => Err(anyhow::Error::msg(err.to_string())),
+    }
+}
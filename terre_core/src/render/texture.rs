@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt::DebugList;
 use std::sync::Condvar;
 use image::GenericImageView;
@@ -13,9 +14,13 @@ pub struct Texture {
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
     pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self{
+        Self::create_depth_texture_sized(device, config.width, config.height, label)
+    }
+
+    pub fn create_depth_texture_sized(device: &wgpu::Device, width: u32, height: u32, label: &str) -> Self{
         let size = wgpu::Extent3d{
-            width: config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
         let desc = wgpu::TextureDescriptor{
@@ -47,6 +52,30 @@ impl Texture {
         );
         Self {texture, view, sampler}
     }
+    /// Texture cameras can render into, see [`RenderTarget`](crate::render::camera::RenderTarget),
+    /// which can also be sampled like a loaded texture, e.g. to show it in a UI.
+    pub fn create_render_target(device: &wgpu::Device, width: u32, height: u32, format: wgpu::TextureFormat, label: &str) -> Self{
+        let texture = device.create_texture(&wgpu::TextureDescriptor{
+            label: Some(label),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor{
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {texture, view, sampler}
+    }
+
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -62,6 +91,26 @@ impl Texture {
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+    ) -> Result<Self> {
+        Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    /// Normal maps hold directions instead of colors, so they are stored linear.
+    pub fn from_normal_image(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        Self::from_image_with_format(device, queue, img, label, wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    fn from_image_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
     ) -> Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
@@ -79,7 +128,7 @@ impl Texture {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                format,
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
             }
@@ -115,4 +164,168 @@ impl Texture {
 
         Ok(Self { texture, view, sampler })
     }
-}
\ No newline at end of file
+}
+
+/// Named images of equal size, packed into the layers of one `texture_2d_array`.
+/// # Usage
+/// [`#add`](TextureArrayBuilder::add) images, then [`#build`](TextureArrayBuilder::build) the texture
+/// and look up the layer of each image by name with [`#layer`](TextureArrayBuilder::layer).
+/// # Explanation
+/// Mipmaps are generated on the CPU while building, each level averaging 2x2 texels of the previous one,
+/// down to a single texel.
+#[derive(Default)]
+pub struct TextureArrayBuilder {
+    size: Option<(u32, u32)>,
+    images: Vec<image::RgbaImage>,
+    layers: HashMap<String, u32>,
+}
+
+impl TextureArrayBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Width and height of every layer, set by the first image.
+    pub fn size(&self) -> Option<(u32, u32)> {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    pub fn layer(&self, name: &str) -> Option<u32> {
+        self.layers.get(name).copied()
+    }
+
+    /// Names of all layers with their layer.
+    pub fn layers(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.layers.iter().map(|(name, layer)| (name.as_str(), *layer))
+    }
+
+    /// Add `image` as the next layer and return it, or the layer `name` already has.
+    /// Fails if `image` is not the size of the first one.
+    pub fn add(&mut self, name: &str, image: &image::DynamicImage) -> Result<u32> {
+        if let Some(layer) = self.layer(name) {
+            return Ok(layer);
+        }
+        let size = image.dimensions();
+        match self.size {
+            Some(expected) if expected != size => {
+                bail!("Texture '{}' is {}x{}, other layers are {}x{}", name, size.0, size.1, expected.0, expected.1)
+            }
+            _ => self.size = Some(size),
+        }
+        let layer = self.images.len() as u32;
+        self.images.push(image.to_rgba8());
+        self.layers.insert(name.to_string(), layer);
+        Ok(layer)
+    }
+
+    /// Mip levels of every layer, down to one texel.
+    pub fn mip_level_count(&self) -> u32 {
+        self.size.map_or(1, |(width, height)| u32::BITS - width.max(height).leading_zeros())
+    }
+
+    /// Upload every layer with its mipmaps, as an sRGB texture viewed as a `texture_2d_array`.
+    pub fn build(&self, device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Result<Texture> {
+        let Some((width, height)) = self.size else { bail!("Texture array '{}' has no layers", label) };
+        let mip_level_count = self.mip_level_count();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: self.images.len() as u32 },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        for (layer, image) in self.images.iter().enumerate() {
+            for (level, mip) in mip_chain(image, mip_level_count).iter().enumerate() {
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        aspect: wgpu::TextureAspect::All,
+                        texture: &texture,
+                        mip_level: level as u32,
+                        origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                    },
+                    mip,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * mip.width()),
+                        rows_per_image: Some(mip.height()),
+                    },
+                    wgpu::Extent3d { width: mip.width(), height: mip.height(), depth_or_array_layers: 1 },
+                );
+            }
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        // Texels stay sharp up close, and blend between mipmaps far away
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Ok(Texture { texture, view, sampler })
+    }
+}
+
+/// `image` followed by `count - 1` levels, each half the size of the previous one, but at least one texel.
+fn mip_chain(image: &image::RgbaImage, count: u32) -> Vec<image::RgbaImage> {
+    let mut levels = vec![image.clone()];
+    for _ in 1..count {
+        let previous = levels.last().unwrap();
+        let (width, height) = ((previous.width() / 2).max(1), (previous.height() / 2).max(1));
+        let level = image::RgbaImage::from_fn(width, height, |x, y| {
+            // Texels of the previous level under this one, a single row or column once that side is one texel
+            let xs = [(x * 2).min(previous.width() - 1), (x * 2 + 1).min(previous.width() - 1)];
+            let ys = [(y * 2).min(previous.height() - 1), (y * 2 + 1).min(previous.height() - 1)];
+            let mut sum = [0u32; 4];
+            for (x, y) in ys.iter().flat_map(|y| xs.iter().map(move |x| (*x, *y))) {
+                let texel = previous.get_pixel(x, y);
+                sum.iter_mut().zip(texel.0).for_each(|(sum, channel)| *sum += channel as u32);
+            }
+            image::Rgba(sum.map(|it| ((it + 2) / 4) as u8))
+        });
+        levels.push(level);
+    }
+    levels
+}
+
+#[cfg(test)]
+mod test {
+    use crate::render::texture::{mip_chain, TextureArrayBuilder};
+
+    #[test]
+    fn test_texture_array_layers_and_mips() {
+        let image = |width, height, value| image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(width, height, image::Rgba([value; 4])));
+        let mut builder = TextureArrayBuilder::new();
+        assert_eq!(builder.add("stone", &image(16, 8, 10)).unwrap(), 0);
+        assert_eq!(builder.add("dirt", &image(16, 8, 20)).unwrap(), 1);
+        assert_eq!(builder.add("stone", &image(16, 8, 30)).unwrap(), 0);
+        assert!(builder.add("big", &image(32, 32, 0)).is_err());
+        assert_eq!((builder.len(), builder.layer("dirt"), builder.layer("big")), (2, Some(1), None));
+        assert_eq!(builder.mip_level_count(), 5);
+
+        // Levels average 2x2 texels, a side of one texel stays one texel
+        let mut checker = image::RgbaImage::new(4, 2);
+        checker.enumerate_pixels_mut().for_each(|(x, y, it)| *it = image::Rgba([((x + y) % 2 * 200) as u8, 0, 0, 255]));
+        let mips = mip_chain(&checker, 3);
+        assert_eq!(mips.iter().map(|it| it.dimensions()).collect::<Vec<_>>(), vec![(4, 2), (2, 1), (1, 1)]);
+        assert!(mips[1].pixels().all(|it| it.0 == [100, 0, 0, 255]));
+        assert_eq!(mips[2].get_pixel(0, 0).0, [100, 0, 0, 255]);
+    }
+}
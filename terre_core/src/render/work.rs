@@ -1,10 +1,23 @@
+use hecs::World;
 use crate::app::{App, Plugin};
-use crate::render::ModelRef;
+use crate::asset::handle::Handle;
+use crate::ecs::resource::ResManager;
+use crate::render::extract::RenderWorld;
+use crate::render::model::Model;
 use crate::schedule::Stage;
+use crate::transform::GlobalTransform;
 
 
+#[derive(Clone)]
 pub struct Renderer3D{
-    pub model: ModelRef,
+    pub model: Handle<Model>,
+}
+
+/// Copy entities with a [`Renderer3D`] into the [`RenderWorld`].
+pub fn extract_renderers(world: &World, _: &ResManager, render_world: &mut RenderWorld) {
+    for (entity, (global, renderer)) in world.query::<(&GlobalTransform, &Renderer3D)>().iter() {
+        render_world.insert(entity, (*global, renderer.clone()));
+    }
 }
 
 pub struct RenderPlugin;
@@ -21,4 +34,4 @@ fn startup_render(){
 
 fn update_render(){
     
-}
\ No newline at end of file
+}
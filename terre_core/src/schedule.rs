@@ -1,5 +1,6 @@
 use std::collections::HashMap;
-use hecs::World;
+use hecs::{Entity, EntityBuilder, World};
+use crate::ecs::removal::Removals;
 use crate::ecs::resource::ResManager;
 use crate::ecs::system::{IntoSystem, System};
 
@@ -7,27 +8,58 @@ use crate::ecs::system::{IntoSystem, System};
 /// # Start
 /// invoke when game start;
 /// # Updates
-/// invoke per frame;
+/// invoke per frame, in order `First`, `AssetUpload`, `PreUpdate`, `Update`, `PostUpdate`;
+/// `First` clears the events of last frame, `AssetUpload` moves assets loaded by workers into storages.
+/// # Exit
+/// invoke once when the game exits, e.g. to save it.
 #[derive(Eq, PartialEq, Copy, Clone, Hash)]
 pub enum Stage {
     Start,
+    First,
+    AssetUpload,
     PreUpdate,
     Update,
     PostUpdate,
+    Exit,
 }
 
+impl Stage {
+    /// Order of the stage within its frame, `Start` and `Exit` coming after the updates of their frame.
+    fn order(self) -> u8 {
+        match self {
+            Stage::First => 0,
+            Stage::AssetUpload => 1,
+            Stage::PreUpdate => 2,
+            Stage::Update => 3,
+            Stage::PostUpdate => 4,
+            Stage::Start | Stage::Exit => 5,
+        }
+    }
+}
+
+/// Invoked with a despawned entity and the components it had.
+pub type DespawnHook = Box<dyn FnMut(Entity, &EntityBuilder, &mut World, &mut ResManager)>;
+
 pub struct GameSchedule {
     pub systems: HashMap<Stage, Vec<Box<dyn System>>>,
+    pub despawn_hooks: Vec<DespawnHook>,
 }
 
 
 impl GameSchedule {
     pub fn new() -> Self {
         Self {
-            systems: HashMap::new()
+            systems: HashMap::new(),
+            despawn_hooks: vec![],
         }
     }
 
+    /// Hooks are invoked after the stage in which the entity was despawned through [`Removals`],
+    /// with the components it had, e.g. to despawn what belonged to it.
+    pub fn add_despawn_hook(&mut self, hook: impl FnMut(Entity, &EntityBuilder, &mut World, &mut ResManager) + 'static) {
+        self.despawn_hooks.push(Box::new(hook));
+    }
+
     pub fn add_system<Params>(&mut self, stage: Stage, function: impl IntoSystem<Params>) {
         let vec = self.systems.get_mut(&stage);
         let to_add = Box::new(function.into_system());
@@ -40,17 +72,51 @@ impl GameSchedule {
     fn run_stages(&mut self, world: &mut World, stages: Vec<Stage>, res_manager: &mut ResManager){
         stages.iter().for_each(|stage| {
             if let Some(it) = self.systems.get_mut(stage) {
-                it.iter_mut().for_each(|sys| sys.run(world, res_manager));
+                it.iter_mut().enumerate().for_each(|(i, sys)| {
+                    if let Some(mut removals) = res_manager.get_res_mut::<Removals>() {
+                        removals.enter_system(stage.order(), i);
+                    }
+                    sys.run(world, res_manager)
+                });
             }
+            // Despawns of hooks come after every system of the stage
+            if let Some(mut removals) = res_manager.get_res_mut::<Removals>() {
+                removals.enter_system(stage.order(), usize::MAX);
+            }
+            self.run_despawn_hooks(world, res_manager);
         });
     }
+
+    /// Run the hooks of entities despawned during the stage, then of those the hooks despawned.
+    fn run_despawn_hooks(&mut self, world: &mut World, res_manager: &mut ResManager) {
+        loop {
+            let despawned = match res_manager.get_res_mut::<Removals>() {
+                None => return,
+                Some(mut removals) => removals.take_unhooked(),
+            };
+            if despawned.is_empty() {
+                return;
+            }
+            for (entity, components) in despawned {
+                self.despawn_hooks.iter_mut().for_each(|hook| hook(entity, &components, world, res_manager));
+            }
+        }
+    }
+
     pub fn run_updates(&mut self, world: &mut World, res_manager: &mut ResManager){
-        self.run_stages(world, vec![Stage::PreUpdate, Stage::Update, Stage::PostUpdate], res_manager);
+        if let Some(mut removals) = res_manager.get_res_mut::<Removals>() {
+            removals.next_frame();
+        }
+        self.run_stages(world, vec![Stage::First, Stage::AssetUpload, Stage::PreUpdate, Stage::Update, Stage::PostUpdate], res_manager);
     }
 
     pub fn run_starts(&mut self, world: &mut World, res_manager: &mut ResManager) {
         self.run_stages(world, vec![Stage::Start], res_manager);
     }
+
+    pub fn run_exits(&mut self, world: &mut World, res_manager: &mut ResManager) {
+        self.run_stages(world, vec![Stage::Exit], res_manager);
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,103 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender};
+use std::thread::JoinHandle;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed set of worker threads running jobs in the order they are spawned.
+/// # Usage
+/// Results are usually sent back through a channel and collected on the main thread.
+/// Dropping the pool waits for the queued jobs to finish.
+/// # Explanation
+/// A panicking job is logged and the worker moves on to the next one,
+/// so a bad asset or chunk does not stall every job queued after it.
+pub struct TaskPool {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl TaskPool {
+    pub fn new(name: &str, threads: usize) -> Self {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads.max(1)).map(|i| {
+            let receiver = receiver.clone();
+            let name = name.to_string();
+            std::thread::Builder::new()
+                .name(format!("{} {}", name, i))
+                .spawn(move || loop {
+                    let job = match receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        // Pool dropped
+                        Err(_) => break,
+                    };
+                    // The panic message is already printed by the panic hook
+                    if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        log::error!("Job panicked on thread '{} {}'", name, i);
+                    }
+                })
+                .unwrap()
+        }).collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Use all cores but one, which is left for the main thread.
+    pub fn with_default_threads(name: &str) -> Self {
+        let cores = std::thread::available_parallelism().map_or(2, |it| it.get());
+        Self::new(name, cores.saturating_sub(1))
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+        if let Some(sender) = &self.sender {
+            sender.send(Box::new(job)).unwrap();
+        }
+    }
+}
+
+impl Drop for TaskPool {
+    fn drop(&mut self) {
+        self.sender.take();
+        self.workers.drain(..).for_each(|it| { let _ = it.join(); });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::channel;
+    use crate::task::TaskPool;
+
+    #[test]
+    fn test_task_pool_runs_all_jobs() {
+        let pool = TaskPool::new("Test", 3);
+        let (sender, receiver) = channel();
+        for i in 0..16 {
+            let sender = sender.clone();
+            pool.spawn(move || sender.send(i * 2).unwrap());
+        }
+        drop(pool);
+
+        let mut results = receiver.try_iter().collect::<Vec<_>>();
+        results.sort();
+        assert_eq!(results, (0..16).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_task_pool_survives_panics() {
+        let pool = TaskPool::new("Test", 1);
+        let (sender, receiver) = channel();
+        pool.spawn(|| panic!("Job failed"));
+        pool.spawn(move || sender.send(1).unwrap());
+        drop(pool);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![1]);
+    }
+}
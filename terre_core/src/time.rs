@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+use terre_core_macros::Resource;
+use crate::ecs::resource::ResMut;
+
+/// Clock of the game, advanced once at the start of every frame.
+/// # Usage
+/// Read [`#delta_seconds`](Time::delta_seconds) to move things at a constant speed,
+/// and [`#elapsed_seconds`](Time::elapsed_seconds) to animate, e.g. day and night.
+#[derive(Resource, Clone)]
+pub struct Time {
+    startup: Instant,
+    last_update: Option<Instant>,
+    delta: Duration,
+    elapsed: Duration,
+}
+
+impl Time {
+    pub fn new() -> Self {
+        Self::new_at(Instant::now())
+    }
+
+    pub fn new_at(startup: Instant) -> Self {
+        Self {
+            startup,
+            last_update: None,
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn update(&mut self) {
+        self.update_at(Instant::now());
+    }
+
+    /// Advance to `now`, the first update after startup has no delta.
+    pub fn update_at(&mut self, now: Instant) {
+        self.delta = match self.last_update {
+            Some(last) => now.saturating_duration_since(last),
+            None => Duration::ZERO,
+        };
+        self.elapsed = now.saturating_duration_since(self.startup);
+        self.last_update = Some(now);
+    }
+
+    /// Time between the last two frames.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Time from startup to the start of this frame.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) fn update_time(mut time: ResMut<Time>) {
+    time.update();
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+    use crate::time::Time;
+
+    #[test]
+    fn test_time_update() {
+        let startup = Instant::now();
+        let mut time = Time::new_at(startup);
+
+        time.update_at(startup + Duration::from_millis(100));
+        assert_eq!(time.delta(), Duration::ZERO);
+        assert_eq!(time.elapsed(), Duration::from_millis(100));
+
+        time.update_at(startup + Duration::from_millis(116));
+        assert_eq!(time.delta(), Duration::from_millis(16));
+        assert_eq!(time.elapsed(), Duration::from_millis(116));
+    }
+}
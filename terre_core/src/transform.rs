@@ -1,5 +1,5 @@
 use bytemuck::Zeroable;
-use cgmath::{Matrix3, Matrix4, Quaternion, Vector3};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix3, Matrix4, Point3, Quaternion, Vector3};
 use hecs::Entity;
 use crate::app::{App, Plugin};
 
@@ -11,6 +11,7 @@ impl Plugin for TransformPlugin{
     }
 }
 
+#[derive(Copy, Clone, Debug)]
 pub struct Transform {
     /// todo parent part
     /// set_parent()
@@ -20,6 +21,43 @@ pub struct Transform {
     pub scale: Vector3<f32>
 }
 
+impl Transform {
+    pub fn from_position(position: Vector3<f32>) -> Self {
+        Self {
+            parent: None,
+            position,
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// At `position` with the forward (`-Z`) axis pointing at `target`.
+    pub fn looking_at(position: Vector3<f32>, target: Point3<f32>, up: Vector3<f32>) -> Self {
+        Self {
+            rotation: look_rotation(target - Point3::from_vec(position), up),
+            ..Self::from_position(position)
+        }
+    }
+
+    /// Direction of the `-Z` axis.
+    pub fn forward(&self) -> Vector3<f32> {
+        self.rotation * -Vector3::unit_z()
+    }
+}
+
+/// Rotation turning `-Z` to `direction`, keeping `+Y` as close to `up` as possible.
+pub fn look_rotation(direction: Vector3<f32>, up: Vector3<f32>) -> Quaternion<f32> {
+    let forward = direction.normalize();
+    let right = forward.cross(up);
+    let right = match right.magnitude2() > f32::EPSILON {
+        true => right.normalize(),
+        false => forward.cross(Vector3::unit_z()).normalize(),
+    };
+    let up = right.cross(forward);
+    Quaternion::from(Matrix3::from_cols(right, up, -forward))
+}
+
+#[derive(Copy, Clone, Debug)]
 pub struct GlobalTransform(
     pub Matrix4<f32>,
     pub Matrix3<f32>
@@ -0,0 +1,57 @@
+use crate::voxel::IVec3;
+
+/// Type of a block, stored in every position of a [`Chunk`](crate::voxel::chunk::Chunk).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u16);
+
+impl BlockId {
+    /// Empty space, what chunks are filled with at first.
+    pub const AIR: BlockId = BlockId(0);
+
+    pub fn is_air(self) -> bool {
+        self == Self::AIR
+    }
+}
+
+/// Side of a block, named by the axis direction it faces.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Face {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [Face::PosX, Face::NegX, Face::PosY, Face::NegY, Face::PosZ, Face::NegZ];
+
+    /// Position in [`Face::ALL`].
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Offset to the neighbouring block this face touches.
+    pub fn normal(self) -> IVec3 {
+        match self {
+            Face::PosX => IVec3::new(1, 0, 0),
+            Face::NegX => IVec3::new(-1, 0, 0),
+            Face::PosY => IVec3::new(0, 1, 0),
+            Face::NegY => IVec3::new(0, -1, 0),
+            Face::PosZ => IVec3::new(0, 0, 1),
+            Face::NegZ => IVec3::new(0, 0, -1),
+        }
+    }
+
+    pub fn opposite(self) -> Face {
+        match self {
+            Face::PosX => Face::NegX,
+            Face::NegX => Face::PosX,
+            Face::PosY => Face::NegY,
+            Face::NegY => Face::PosY,
+            Face::PosZ => Face::NegZ,
+            Face::NegZ => Face::PosZ,
+        }
+    }
+}
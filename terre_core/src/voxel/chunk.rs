@@ -0,0 +1,147 @@
+use crate::voxel::block::BlockId;
+use crate::voxel::IVec3;
+use crate::voxel::palette::PalettedStorage;
+
+/// Blocks along each axis of a chunk.
+pub const CHUNK_SIZE: i32 = 32;
+pub const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Position of a chunk, in chunks; the chunk at `(1, 0, 0)` starts at block `(32, 0, 0)`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Chunk containing the block at `block`.
+    pub fn of_block(block: IVec3) -> Self {
+        Self::split(block).0
+    }
+
+    /// Chunk containing `block`, and the position of the block within it.
+    pub fn split(block: IVec3) -> (Self, IVec3) {
+        let chunk = Self::new(block.x.div_euclid(CHUNK_SIZE), block.y.div_euclid(CHUNK_SIZE), block.z.div_euclid(CHUNK_SIZE));
+        let local = IVec3::new(block.x.rem_euclid(CHUNK_SIZE), block.y.rem_euclid(CHUNK_SIZE), block.z.rem_euclid(CHUNK_SIZE));
+        (chunk, local)
+    }
+
+    /// World position of the first block of the chunk.
+    pub fn origin(&self) -> IVec3 {
+        IVec3::new(self.x, self.y, self.z) * CHUNK_SIZE
+    }
+
+    /// World position of the block at `local` in this chunk.
+    pub fn block(&self, local: IVec3) -> IVec3 {
+        self.origin() + local
+    }
+}
+
+/// Whether `local` is a position within a chunk.
+pub fn contains_local(local: IVec3) -> bool {
+    (0..CHUNK_SIZE).contains(&local.x) && (0..CHUNK_SIZE).contains(&local.y) && (0..CHUNK_SIZE).contains(&local.z)
+}
+
+/// Index of the block at `local` in the storage of a chunk, `X` first, then `Z`, then `Y`.
+/// Panics if `local` is outside of the chunk.
+pub fn local_index(local: IVec3) -> usize {
+    assert!(contains_local(local), "{:?} is outside of a chunk", local);
+    ((local.y * CHUNK_SIZE + local.z) * CHUNK_SIZE + local.x) as usize
+}
+
+/// Position within a chunk of the block at `index`, see [`local_index`].
+pub fn local_position(index: usize) -> IVec3 {
+    let index = index as i32;
+    IVec3::new(index % CHUNK_SIZE, index / (CHUNK_SIZE * CHUNK_SIZE), index / CHUNK_SIZE % CHUNK_SIZE)
+}
+
+/// [`CHUNK_SIZE`]³ blocks, addressed by their local position.
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    blocks: PalettedStorage,
+}
+
+impl Chunk {
+    /// A chunk of air.
+    pub fn new() -> Self {
+        Self::filled(BlockId::AIR)
+    }
+
+    pub fn filled(block: BlockId) -> Self {
+        Self { blocks: PalettedStorage::new(CHUNK_VOLUME, block) }
+    }
+
+    /// Panics if `local` is outside of the chunk.
+    pub fn get(&self, local: IVec3) -> BlockId {
+        self.blocks.get(local_index(local))
+    }
+
+    /// Set the block at `local` and return the one replaced, panics if `local` is outside of the chunk.
+    pub fn set(&mut self, local: IVec3, block: BlockId) -> BlockId {
+        self.blocks.set(local_index(local), block)
+    }
+
+    pub fn fill(&mut self, block: BlockId) {
+        self.blocks.fill(block);
+    }
+
+    /// Whether every block is air, without looking at each of them when possible.
+    pub fn is_air(&self) -> bool {
+        match self.blocks.palette() {
+            Some(palette) if palette.iter().all(|it| it.is_air()) => true,
+            Some(palette) if palette.iter().all(|it| !it.is_air()) => false,
+            _ => (0..CHUNK_VOLUME).all(|it| self.blocks.get(it).is_air()),
+        }
+    }
+
+    pub fn blocks(&self) -> &PalettedStorage {
+        &self.blocks
+    }
+
+    /// See [`PalettedStorage#compact`](PalettedStorage::compact), worth it after replacing many blocks.
+    pub fn compact(&mut self) {
+        self.blocks.compact();
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::voxel::block::BlockId;
+    use crate::voxel::chunk::{Chunk, CHUNK_VOLUME, ChunkPos, local_index, local_position};
+    use crate::voxel::IVec3;
+
+    #[test]
+    fn test_split_negative_positions() {
+        assert_eq!(ChunkPos::split(IVec3::new(0, 31, 32)), (ChunkPos::new(0, 0, 1), IVec3::new(0, 31, 0)));
+        assert_eq!(ChunkPos::split(IVec3::new(-1, -32, -33)), (ChunkPos::new(-1, -1, -2), IVec3::new(31, 0, 31)));
+        let block = IVec3::new(-70, 5, 100);
+        let (chunk, local) = ChunkPos::split(block);
+        assert_eq!(chunk.block(local), block);
+
+        assert!((0..CHUNK_VOLUME).all(|it| local_index(local_position(it)) == it));
+    }
+
+    #[test]
+    fn test_chunk_get_set() {
+        let mut chunk = Chunk::new();
+        assert!(chunk.is_air());
+        let local = IVec3::new(31, 0, 5);
+        assert_eq!(chunk.set(local, BlockId(3)), BlockId::AIR);
+        assert_eq!(chunk.get(local), BlockId(3));
+        assert!(!chunk.is_air());
+
+        chunk.set(local, BlockId::AIR);
+        assert!(chunk.is_air());
+    }
+}
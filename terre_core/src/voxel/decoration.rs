@@ -0,0 +1,562 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+use anyhow::anyhow;
+use crate::asset::io::AssetIo;
+use crate::voxel::block::BlockId;
+use crate::voxel::chunk::{Chunk, CHUNK_SIZE, ChunkPos, contains_local};
+use crate::voxel::IVec3;
+use crate::voxel::prefab::Prefab;
+use crate::voxel::registry::BlockRegistry;
+use crate::voxel::terrain::{Biome, TerrainGenerator};
+
+/// Prefabs placed by [`Decorator::standard`], with the biomes they stand in and their chance per chunk.
+pub const STANDARD_PREFABS: [(&str, &[Biome], f64); 2] = [
+    ("prefabs/well.prefab", &[Biome::Plains, Biome::Desert], 0.04),
+    ("prefabs/hut.prefab", &[Biome::Plains, Biome::Forest, Biome::Tundra], 0.03),
+];
+
+/// Which blocks a [`BlockWrite`] may replace.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Replace {
+    Any,
+    Air,
+    /// Only this block, e.g. stone for ores.
+    Only(BlockId),
+}
+
+impl Replace {
+    pub fn allows(self, current: BlockId) -> bool {
+        match self {
+            Replace::Any => true,
+            Replace::Air => current.is_air(),
+            Replace::Only(block) => current == block,
+        }
+    }
+}
+
+/// Block placed by a [`Feature`], at its world position.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BlockWrite {
+    pub position: IVec3,
+    pub block: BlockId,
+    pub replace: Replace,
+}
+
+/// SplitMix64, small and deterministic across platforms, unlike the random generators of the standard library.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Generator of the feature at `index` of the chunk at `pos`, so features do not depend on each other.
+    pub fn for_feature(seed: u32, pos: ChunkPos, index: usize) -> Self {
+        let mut state = seed as u64;
+        for it in [pos.x as u32 as u64, pos.y as u32 as u64, pos.z as u32 as u64, index as u64] {
+            state = Self::new(state ^ it).next_u64();
+        }
+        Self::new(state)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    pub fn float(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform in `range`, which must not be empty.
+    pub fn range(&mut self, range: Range<i32>) -> i32 {
+        assert!(!range.is_empty(), "Empty range {:?}", range);
+        let len = (range.end as i64 - range.start as i64) as u64;
+        (range.start as i64 + (self.next_u64() % len) as i64) as i32
+    }
+
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.float() < probability
+    }
+}
+
+/// What a [`Feature`] sees of the chunk it decorates, and collects its writes.
+/// # Explanation
+/// Features only read the chunk as its terrain generated it, never blocks written by other features,
+/// so decorating a chunk does not depend on which chunks were decorated before.
+pub struct FeatureContext<'a> {
+    pub pos: ChunkPos,
+    chunk: &'a Chunk,
+    terrain: &'a dyn TerrainGenerator,
+    writes: Vec<BlockWrite>,
+}
+
+impl<'a> FeatureContext<'a> {
+    pub fn new(pos: ChunkPos, chunk: &'a Chunk, terrain: &'a dyn TerrainGenerator) -> Self {
+        Self { pos, chunk, terrain, writes: vec![] }
+    }
+
+    /// Place `block` at the world position `position`, possibly in another chunk.
+    pub fn set(&mut self, position: IVec3, block: BlockId, replace: Replace) {
+        self.writes.push(BlockWrite { position, block, replace });
+    }
+
+    /// Generated block at the world position `position`, `None` outside of the chunk.
+    pub fn block(&self, position: IVec3) -> Option<BlockId> {
+        let local = position - self.pos.origin();
+        contains_local(local).then(|| self.chunk.get(local))
+    }
+
+    /// Highest block of the column at local `(x, z)` which is one of `ground` and has air above it,
+    /// as generated by the terrain for the top block of the chunk.
+    pub fn surface(&self, x: i32, z: i32, ground: &[BlockId]) -> Option<IVec3> {
+        (0..CHUNK_SIZE).rev()
+            .map(|y| IVec3::new(x, y, z))
+            .find(|it| ground.contains(&self.chunk.get(*it)) && self.above(*it).is_air())
+            .map(|it| self.pos.block(it))
+    }
+
+    fn above(&self, local: IVec3) -> BlockId {
+        let above = local + IVec3::new(0, 1, 0);
+        match contains_local(above) {
+            true => self.chunk.get(above),
+            false => self.terrain.block(self.pos.block(above)),
+        }
+    }
+
+    pub fn biome(&self, x: i32, z: i32) -> Option<Biome> {
+        self.terrain.biome(x, z)
+    }
+
+    /// Whether features limited to `biomes` may stand at the world column `(x, z)`, any biome if empty.
+    pub fn allows_biome(&self, biomes: &[Biome], x: i32, z: i32) -> bool {
+        biomes.is_empty() || self.biome(x, z).is_none_or(|it| biomes.contains(&it))
+    }
+
+    pub fn into_writes(self) -> Vec<BlockWrite> {
+        self.writes
+    }
+}
+
+/// Something placed on generated terrain, e.g. a tree.
+/// # Explanation
+/// A feature may write blocks up to a chunk away from the one it decorates,
+/// writes outside of it are kept in [`SpilledWrites`] and applied to their chunk when it is loaded.
+/// It must only use `rng` for randomness, so a seed always places the same features.
+pub trait Feature: Send + Sync {
+    fn place(&self, context: &mut FeatureContext, rng: &mut Rng);
+}
+
+/// Trunk with a blob of leaves, on `ground` blocks.
+pub struct Tree {
+    pub trunk: BlockId,
+    pub leaves: BlockId,
+    pub ground: Vec<BlockId>,
+    /// Biomes trees grow in, any if empty.
+    pub biomes: Vec<Biome>,
+    /// Trees tried per chunk, only those on ground are placed.
+    pub attempts: u32,
+    pub height: Range<i32>,
+}
+
+impl Feature for Tree {
+    fn place(&self, context: &mut FeatureContext, rng: &mut Rng) {
+        for _ in 0..self.attempts {
+            let (x, z) = (rng.range(0..CHUNK_SIZE), rng.range(0..CHUNK_SIZE));
+            let height = rng.range(self.height.clone());
+            let Some(ground) = context.surface(x, z, &self.ground) else { continue };
+            if !context.allows_biome(&self.biomes, ground.x, ground.z) {
+                continue;
+            }
+            let top = ground + IVec3::new(0, height, 0);
+            for y in ground.y + 1..=top.y {
+                context.set(IVec3::new(ground.x, y, ground.z), self.trunk, Replace::Any);
+            }
+            for dy in -2..=1 {
+                let radius: i32 = if dy < 0 { 2 } else { 1 };
+                for dz in -radius..=radius {
+                    for dx in -radius..=radius {
+                        // Corners are left out at random, rounding the blob
+                        if dx.abs() == radius && dz.abs() == radius && (dy == 1 || rng.chance(0.5)) {
+                            continue;
+                        }
+                        context.set(top + IVec3::new(dx, dy, dz), self.leaves, Replace::Air);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Random walk of `ore` through `host` blocks.
+pub struct OreVein {
+    pub ore: BlockId,
+    pub host: BlockId,
+    /// Veins tried per chunk.
+    pub attempts: u32,
+    /// Blocks per vein at most.
+    pub size: u32,
+    /// World heights veins start at.
+    pub heights: Range<i32>,
+}
+
+impl Feature for OreVein {
+    fn place(&self, context: &mut FeatureContext, rng: &mut Rng) {
+        let origin = context.pos.origin();
+        let heights = self.heights.start.max(origin.y)..self.heights.end.min(origin.y + CHUNK_SIZE);
+        if heights.is_empty() {
+            return;
+        }
+        for _ in 0..self.attempts {
+            let mut position = IVec3::new(origin.x + rng.range(0..CHUNK_SIZE), rng.range(heights.clone()), origin.z + rng.range(0..CHUNK_SIZE));
+            for _ in 0..self.size {
+                context.set(position, self.ore, Replace::Only(self.host));
+                position[rng.range(0..3) as usize] += if rng.chance(0.5) { 1 } else { -1 };
+            }
+        }
+    }
+}
+
+/// Rough ball of `block` half sunk into the ground.
+pub struct Boulder {
+    pub block: BlockId,
+    pub ground: Vec<BlockId>,
+    pub biomes: Vec<Biome>,
+    /// Chance of a boulder per chunk.
+    pub chance: f64,
+    pub radius: Range<i32>,
+}
+
+impl Feature for Boulder {
+    fn place(&self, context: &mut FeatureContext, rng: &mut Rng) {
+        if !rng.chance(self.chance) {
+            return;
+        }
+        let (x, z) = (rng.range(0..CHUNK_SIZE), rng.range(0..CHUNK_SIZE));
+        let radius = rng.range(self.radius.clone());
+        let Some(center) = context.surface(x, z, &self.ground) else { return };
+        if !context.allows_biome(&self.biomes, center.x, center.z) {
+            return;
+        }
+        for dy in -radius..=radius {
+            for dz in -radius..=radius {
+                for dx in -radius..=radius {
+                    let distance = (dx * dx + dy * dy + dz * dz) as f64;
+                    if distance <= (radius * radius) as f64 + rng.float() * radius as f64 {
+                        context.set(center + IVec3::new(dx, dy, dz), self.block, Replace::Air);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// [`Prefab`] with its anchor on a `ground` block.
+pub struct PrefabFeature {
+    pub prefab: Arc<Prefab>,
+    pub ground: Vec<BlockId>,
+    pub biomes: Vec<Biome>,
+    /// Chance of the prefab per chunk.
+    pub chance: f64,
+}
+
+impl Feature for PrefabFeature {
+    fn place(&self, context: &mut FeatureContext, rng: &mut Rng) {
+        if !rng.chance(self.chance) {
+            return;
+        }
+        let (x, z) = (rng.range(0..CHUNK_SIZE), rng.range(0..CHUNK_SIZE));
+        let Some(anchor) = context.surface(x, z, &self.ground) else { return };
+        if !context.allows_biome(&self.biomes, anchor.x, anchor.z) {
+            return;
+        }
+        for (offset, block) in self.prefab.blocks() {
+            context.set(anchor + offset, block, Replace::Any);
+        }
+    }
+}
+
+/// Decoration stage of generation, placing [`Feature`]s on the terrain of chunks.
+/// # Usage
+/// Generate chunks with [`#generate`](Decorator::generate), and record the writes it returns for other chunks
+/// in [`SpilledWrites`], which applies them to those chunks.
+/// # Explanation
+/// Features are placed in the order they were added, each with its own [`Rng`] seeded by the seed,
+/// the chunk position and the index of the feature.
+/// A chunk gets the writes of its own features first, then those of its neighbors by [`SpilledWrites::apply`].
+pub struct Decorator {
+    seed: u32,
+    features: Vec<Box<dyn Feature>>,
+}
+
+impl Decorator {
+    pub fn new(seed: u32) -> Self {
+        Self { seed, features: vec![] }
+    }
+
+    pub fn with_feature(mut self, feature: impl Feature + 'static) -> Self {
+        self.features.push(Box::new(feature));
+        self
+    }
+
+    /// Trees, ores, boulders and the [`STANDARD_PREFABS`], failing if their blocks are not registered.
+    pub fn standard(seed: u32, registry: &BlockRegistry, io: &dyn AssetIo) -> anyhow::Result<Self> {
+        let id = |name: &str| registry.id(name).ok_or_else(|| anyhow!("Block '{}' is not registered", name));
+        let stone = id("terre:stone")?;
+        let grass = id("terre:grass")?;
+        let dirt = id("terre:dirt")?;
+        let sand = id("terre:sand")?;
+        let snow = id("terre:snow")?;
+        let mut decorator = Self::new(seed)
+            .with_feature(OreVein { ore: id("terre:coal_ore")?, host: stone, attempts: 12, size: 12, heights: -64..64 })
+            .with_feature(OreVein { ore: id("terre:iron_ore")?, host: stone, attempts: 8, size: 6, heights: -64..16 })
+            .with_feature(Boulder { block: stone, ground: vec![grass, snow], biomes: vec![], chance: 0.2, radius: 1..3 });
+        for (path, biomes, chance) in STANDARD_PREFABS {
+            let prefab = Arc::new(Prefab::load(io, path, registry)?);
+            decorator = decorator.with_feature(PrefabFeature { prefab, ground: vec![grass, sand, snow], biomes: biomes.to_vec(), chance });
+        }
+        Ok(decorator
+            .with_feature(Tree {
+                trunk: id("terre:log")?,
+                leaves: id("terre:leaves")?,
+                ground: vec![grass, dirt, snow],
+                biomes: vec![Biome::Forest],
+                attempts: 10,
+                height: 4..7,
+            })
+            .with_feature(Tree {
+                trunk: id("terre:log")?,
+                leaves: id("terre:leaves")?,
+                ground: vec![grass, dirt],
+                biomes: vec![Biome::Plains],
+                attempts: 1,
+                height: 4..6,
+            }))
+    }
+
+    /// Place the features on `chunk`, generated by `terrain` at `pos`, returning the writes to other chunks.
+    pub fn decorate(&self, pos: ChunkPos, chunk: &mut Chunk, terrain: &dyn TerrainGenerator) -> Vec<BlockWrite> {
+        let mut context = FeatureContext::new(pos, chunk, terrain);
+        for (index, feature) in self.features.iter().enumerate() {
+            feature.place(&mut context, &mut Rng::for_feature(self.seed, pos, index));
+        }
+        let (own, others) = context.into_writes().into_iter()
+            .partition::<Vec<_>, _>(|it| ChunkPos::of_block(it.position) == pos);
+        apply_writes(pos, chunk, &own);
+        others
+    }
+
+    /// Generate and decorate the chunk at `pos`, returning it with the writes to other chunks.
+    pub fn generate(&self, pos: ChunkPos, terrain: &dyn TerrainGenerator) -> (Chunk, Vec<BlockWrite>) {
+        let mut chunk = terrain.generate(pos);
+        let others = self.decorate(pos, &mut chunk, terrain);
+        chunk.compact();
+        (chunk, others)
+    }
+}
+
+/// Apply the `writes` of blocks in the chunk at `pos` their [`Replace`] allows, returning whether a block changed.
+pub fn apply_writes(pos: ChunkPos, chunk: &mut Chunk, writes: &[BlockWrite]) -> bool {
+    let mut changed = false;
+    for write in writes {
+        let (write_pos, local) = ChunkPos::split(write.position);
+        debug_assert_eq!(write_pos, pos, "Write to {:?} outside of chunk {:?}", write.position, pos);
+        let current = chunk.get(local);
+        if current != write.block && write.replace.allows(current) {
+            chunk.set(local, write.block);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Writes of features to the chunks around the one they decorate, by target and source chunk.
+/// # Usage
+/// Record the writes of each generated chunk with [`#record`](SpilledWrites::record), then apply them
+/// with [`#apply`](SpilledWrites::apply) to the chunk itself, and to the loaded chunks `record` returns.
+/// Drop what is not needed anymore with [`#retain`](SpilledWrites::retain) as chunks unload.
+/// # Explanation
+/// Writes of several chunks overlap, e.g. trees of the neighbors along x and along z near the corner
+/// they share with a third chunk, so the result depends on the order writes are applied in.
+/// To give the same world whichever order chunks are generated in, applying to a chunk first puts back
+/// the blocks it had before any write, then applies the writes of all its sources, sorted by position.
+#[derive(Default)]
+pub struct SpilledWrites {
+    /// Writes to the target chunk, by target, then source.
+    writes: HashMap<ChunkPos, HashMap<ChunkPos, Vec<BlockWrite>>>,
+    /// Blocks of the target chunks writes were applied to, as they were before, by local position.
+    replaced: HashMap<ChunkPos, HashMap<IVec3, BlockId>>,
+}
+
+impl SpilledWrites {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep the writes of the chunk at `source` to other chunks,
+    /// returning the chunks which got writes of `source` they did not have yet.
+    pub fn record(&mut self, source: ChunkPos, writes: Vec<BlockWrite>) -> Vec<ChunkPos> {
+        let mut targets: HashMap<ChunkPos, Vec<BlockWrite>> = HashMap::new();
+        writes.into_iter().for_each(|it| targets.entry(ChunkPos::of_block(it.position)).or_default().push(it));
+        targets.into_iter()
+            .filter_map(|(target, writes)| {
+                let recorded = self.writes.entry(target).or_default();
+                // Generation is pure, a source generated again writes the same blocks
+                if recorded.contains_key(&source) {
+                    return None;
+                }
+                recorded.insert(source, writes);
+                Some(target)
+            })
+            .collect()
+    }
+
+    /// Apply the writes recorded for the chunk at `pos` from all its sources, returning whether a block changed.
+    pub fn apply(&mut self, pos: ChunkPos, chunk: &mut Chunk) -> bool {
+        let Some(recorded) = self.writes.get(&pos) else { return false };
+        let mut sources = recorded.keys().copied().collect::<Vec<_>>();
+        sources.sort_unstable_by_key(|it| (it.x, it.y, it.z));
+        let writes = sources.iter().flat_map(|it| &recorded[it]).copied().collect::<Vec<_>>();
+
+        let replaced = self.replaced.entry(pos).or_default();
+        for write in &writes {
+            let local = write.position - pos.origin();
+            replaced.entry(local).or_insert_with(|| chunk.get(local));
+        }
+        let before = replaced.keys().map(|it| (*it, chunk.get(*it))).collect::<Vec<_>>();
+        replaced.iter().for_each(|(local, block)| { chunk.set(*local, *block); });
+        apply_writes(pos, chunk, &writes);
+        chunk.compact();
+        before.into_iter().any(|(local, block)| chunk.get(local) != block)
+    }
+
+    /// Forget the writes between chunks which are both not `loaded`, so they are recorded again
+    /// when their source is generated again, and the blocks replaced in chunks which are not loaded.
+    pub fn retain(&mut self, loaded: impl Fn(ChunkPos) -> bool) {
+        self.writes.retain(|target, sources| {
+            if !loaded(*target) {
+                sources.retain(|source, _| loaded(*source));
+            }
+            !sources.is_empty()
+        });
+        self.replaced.retain(|target, _| loaded(*target));
+    }
+
+    /// Writes kept from a source to a target chunk.
+    pub fn len(&self) -> usize {
+        self.writes.values().map(|it| it.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use crate::voxel::block::BlockId;
+    use crate::voxel::chunk::{Chunk, CHUNK_SIZE, CHUNK_VOLUME, ChunkPos};
+    use crate::voxel::decoration::{Decorator, FeatureContext, PrefabFeature, SpilledWrites, Tree};
+    use crate::voxel::IVec3;
+    use crate::voxel::prefab::PrefabData;
+    use crate::voxel::registry::BlockRegistry;
+    use crate::voxel::terrain::TerrainGenerator;
+    use crate::voxel::world::VoxelWorld;
+
+    const GRASS: BlockId = BlockId(1);
+    const LOG: BlockId = BlockId(2);
+    const LEAVES: BlockId = BlockId(3);
+
+    /// Grass at height `0`, air above.
+    struct Flat;
+
+    impl TerrainGenerator for Flat {
+        fn generate(&self, pos: ChunkPos) -> Chunk {
+            match pos.y {
+                0 => {
+                    let mut chunk = Chunk::new();
+                    (0..CHUNK_SIZE).for_each(|x| (0..CHUNK_SIZE).for_each(|z| { chunk.set(IVec3::new(x, 0, z), GRASS); }));
+                    chunk
+                }
+                y if y < 0 => Chunk::filled(GRASS),
+                _ => Chunk::new(),
+            }
+        }
+    }
+
+    /// Grass below height `0`, so the ground is the top block of chunks at `y = -1`.
+    struct Below;
+
+    impl TerrainGenerator for Below {
+        fn generate(&self, pos: ChunkPos) -> Chunk {
+            if pos.y < 0 { Chunk::filled(GRASS) } else { Chunk::new() }
+        }
+    }
+
+    fn decorator(seed: u32) -> Decorator {
+        let mut registry = BlockRegistry::new();
+        registry.load_json(r#"{ "blocks": [{ "id": "terre:grass" }, { "id": "terre:log" }] }"#).unwrap();
+        let prefab = PrefabData::parse("size 1 3 1\nkey L terre:log\nlayer\nL\nlayer\nL\nlayer\nL").unwrap();
+        Decorator::new(seed)
+            .with_feature(Tree { trunk: LOG, leaves: LEAVES, ground: vec![GRASS], biomes: vec![], attempts: 24, height: 4..6 })
+            .with_feature(PrefabFeature { prefab: Arc::new(prefab.resolve(&registry).unwrap()), ground: vec![GRASS], biomes: vec![], chance: 1.0 })
+    }
+
+    /// Generate `positions` in order into a world, as chunks stream in.
+    fn generate(decorator: &Decorator, positions: &[ChunkPos]) -> VoxelWorld {
+        let mut world = VoxelWorld::new();
+        let mut spills = SpilledWrites::new();
+        for pos in positions {
+            let (chunk, writes) = decorator.generate(*pos, &Flat);
+            let targets = spills.record(*pos, writes);
+            world.insert_chunk(*pos, chunk);
+            for target in [*pos].into_iter().chain(targets) {
+                if let Some(chunk) = world.chunk_mut(target) {
+                    spills.apply(target, chunk);
+                }
+            }
+        }
+        world
+    }
+
+    fn blocks(world: &VoxelWorld, pos: ChunkPos) -> Vec<BlockId> {
+        let chunk = world.chunk(pos).unwrap();
+        (0..CHUNK_VOLUME).map(|it| chunk.blocks().get(it)).collect()
+    }
+
+    #[test]
+    fn test_decoration_across_chunks() {
+        let (a, b, c, d) = (ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0), ChunkPos::new(0, 0, 1), ChunkPos::new(1, 0, 1));
+        let (_, writes) = decorator(7).generate(a, &Flat);
+        assert!(!writes.is_empty(), "No feature reached another chunk");
+
+        // Chunks are the same whichever is generated first, even where writes of several chunks overlap, and differ with the seed
+        let first = generate(&decorator(7), &[a, b, c, d]);
+        for order in [[d, c, b, a], [b, d, a, c]] {
+            let other = generate(&decorator(7), &order);
+            assert!(order.iter().all(|it| blocks(&first, *it) == blocks(&other, *it)), "Chunks differ generated in the order {:?}", order);
+        }
+        assert_ne!(blocks(&first, a), blocks(&generate(&decorator(8), &[a, b]), a));
+
+        // Trees stand on the ground, and leaves spilled over the border of the chunks
+        let logs = blocks(&first, a).into_iter().filter(|it| *it == LOG).count();
+        assert!(logs >= 4);
+        assert!((0..CHUNK_SIZE).any(|z| (1..CHUNK_SIZE).any(|y| first.get_block(IVec3::new(CHUNK_SIZE, y, z)) == Some(LEAVES)
+            && first.get_block(IVec3::new(CHUNK_SIZE - 1, y, z)) == Some(LEAVES))));
+
+        // Ground at the top of a chunk is found by asking the terrain what is above it
+        let below = ChunkPos::new(0, -1, 0);
+        let chunk = Below.generate(below);
+        assert_eq!(FeatureContext::new(below, &chunk, &Below).surface(3, 5, &[GRASS]), Some(IVec3::new(3, -1, 5)));
+        assert_eq!(FeatureContext::new(below, &chunk, &Flat).surface(3, 5, &[GRASS]), None);
+    }
+}
@@ -0,0 +1,294 @@
+use hecs::World;
+use terre_core_macros::Resource;
+use winit::event::{MouseButton, VirtualKeyCode};
+use crate::app::{App, Plugin};
+use crate::ecs::event::Events;
+use crate::ecs::resource::ResManager;
+use crate::input::{CursorGrab, KeyInput, MouseButtonInput};
+use crate::render::camera::{active_camera, Ray};
+use crate::schedule::Stage;
+use crate::transform::Transform;
+use crate::voxel::block::BlockId;
+use crate::voxel::chunk::{CHUNK_SIZE, ChunkPos};
+use crate::voxel::IVec3;
+use crate::voxel::raycast::raycast;
+use crate::voxel::registry::BlockRegistry;
+use crate::voxel::streaming::ChunkManager;
+use crate::voxel::world::VoxelWorld;
+
+/// Sent for every block changed by a [`BlockEditor`], including by undo and redo.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockChanged {
+    pub position: IVec3,
+    pub old: BlockId,
+    pub new: BlockId,
+}
+
+/// Set blocks in loaded chunks, skipping those which are not loaded or already set.
+pub fn set_blocks(voxels: &mut VoxelWorld, blocks: impl IntoIterator<Item = (IVec3, BlockId)>) -> Vec<BlockChanged> {
+    blocks.into_iter()
+        .filter_map(|(position, new)| {
+            let old = voxels.get_block(position)?;
+            (old != new).then(|| {
+                voxels.set_block(position, new);
+                BlockChanged { position, old, new }
+            })
+        })
+        .collect()
+}
+
+/// The chunk of the block at `position` first, then the neighbors whose meshes it is part of:
+/// those sharing the faces, edges and corners of its chunk the block lies on.
+pub fn chunks_touching(position: IVec3) -> Vec<ChunkPos> {
+    let (pos, local) = ChunkPos::split(position);
+    let offsets = [local.x, local.y, local.z].map(|it| match it {
+        0 => vec![0, -1],
+        it if it == CHUNK_SIZE - 1 => vec![0, 1],
+        _ => vec![0],
+    });
+    let mut chunks = vec![];
+    for y in &offsets[1] {
+        for z in &offsets[2] {
+            for x in &offsets[0] {
+                chunks.push(ChunkPos::new(pos.x + x, pos.y + y, pos.z + z));
+            }
+        }
+    }
+    chunks
+}
+
+/// Block placed by [`BlockEditPlugin`], and the history of edits for undo and redo, as a resource.
+/// # Usage
+/// Edit blocks with [`#edit`](BlockEditor::edit), each call being one batch undone and redone at once,
+/// and send the changes it returns as [`BlockChanged`] events, so chunks are remeshed.
+/// # Explanation
+/// Undo sets the blocks of the last batch back to what they were, whatever happened to them since.
+/// Blocks in chunks which are not loaded anymore are skipped.
+#[derive(Resource)]
+pub struct BlockEditor {
+    pub selected: BlockId,
+    /// Distance blocks are edited at, in blocks.
+    pub reach: f32,
+    /// Batches kept for undo, older ones are forgotten.
+    pub max_history: usize,
+    undo: Vec<Vec<BlockChanged>>,
+    redo: Vec<Vec<BlockChanged>>,
+}
+
+impl BlockEditor {
+    pub fn new(selected: BlockId, reach: f32) -> Self {
+        Self {
+            selected,
+            reach,
+            max_history: 100,
+            undo: vec![],
+            redo: vec![],
+        }
+    }
+
+    /// Set `blocks` as one batch, clearing what could be redone if a block changed.
+    pub fn edit(&mut self, voxels: &mut VoxelWorld, blocks: impl IntoIterator<Item = (IVec3, BlockId)>) -> Vec<BlockChanged> {
+        let changes = set_blocks(voxels, blocks);
+        if !changes.is_empty() {
+            self.redo.clear();
+            self.undo.push(changes.clone());
+            if self.undo.len() > self.max_history {
+                self.undo.remove(0);
+            }
+        }
+        changes
+    }
+
+    /// Undo the last batch, returning the changes undoing it made.
+    pub fn undo(&mut self, voxels: &mut VoxelWorld) -> Vec<BlockChanged> {
+        let Some(batch) = self.undo.pop() else { return vec![] };
+        let changes = set_blocks(voxels, batch.iter().rev().map(|it| (it.position, it.old)));
+        self.redo.push(batch);
+        changes
+    }
+
+    /// Redo the last undone batch, returning the changes redoing it made.
+    pub fn redo(&mut self, voxels: &mut VoxelWorld) -> Vec<BlockChanged> {
+        let Some(batch) = self.redo.pop() else { return vec![] };
+        let changes = set_blocks(voxels, batch.iter().map(|it| (it.position, it.new)));
+        self.undo.push(batch);
+        changes
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+/// Breaks and places blocks aimed at from the center of the active camera, where a crosshair would be,
+/// with undo and redo for creative mode.
+/// # Usage
+/// Add after [`ChunkStreamingPlugin`](crate::voxel::streaming::ChunkStreamingPlugin).
+/// Left click breaks, right click places [`BlockEditor::selected`], `Ctrl+Z` undoes and `Ctrl+Y` redoes.
+/// # Explanation
+/// Edits send [`BlockChanged`] events, then the chunks of the changed blocks are marked modified,
+/// and neighbors sharing the border the blocks lie on are remeshed, see [`chunks_touching`].
+/// Edits run in [`Stage::PreUpdate`], before the click grabbing the cursor is handled,
+/// so that click does not edit when [`require_grab`](BlockEditPlugin::require_grab) is set.
+pub struct BlockEditPlugin {
+    /// Name of the block placed at first.
+    pub block: String,
+    pub reach: f32,
+    pub break_button: MouseButton,
+    pub place_button: MouseButton,
+    /// Pressed with `Ctrl`.
+    pub undo_key: VirtualKeyCode,
+    /// Pressed with `Ctrl`.
+    pub redo_key: VirtualKeyCode,
+    /// Only edit while the cursor is grabbed, e.g. by a [`FlyCamera`](crate::render::camera::controller::FlyCamera).
+    pub require_grab: bool,
+}
+
+impl Default for BlockEditPlugin {
+    fn default() -> Self {
+        Self {
+            block: "terre:stone".to_string(),
+            reach: 8.0,
+            break_button: MouseButton::Left,
+            place_button: MouseButton::Right,
+            undo_key: VirtualKeyCode::Z,
+            redo_key: VirtualKeyCode::Y,
+            require_grab: true,
+        }
+    }
+}
+
+/// Input bindings of [`BlockEditPlugin`].
+#[derive(Copy, Clone)]
+struct EditBindings {
+    break_button: MouseButton,
+    place_button: MouseButton,
+    undo_key: VirtualKeyCode,
+    redo_key: VirtualKeyCode,
+    require_grab: bool,
+}
+
+impl Plugin for BlockEditPlugin {
+    fn build(&self, app: App) -> App {
+        let block = self.block.clone();
+        let bindings = EditBindings {
+            break_button: self.break_button,
+            place_button: self.place_button,
+            undo_key: self.undo_key,
+            redo_key: self.redo_key,
+            require_grab: self.require_grab,
+        };
+        app.add_event::<BlockChanged>()
+            .add_resource(BlockEditor::new(BlockId::AIR, self.reach))
+            .add_system(Stage::Start, move |_: &mut World, res_manager: &mut ResManager| select_block(&block, res_manager))
+            .add_system(Stage::PreUpdate, move |world: &mut World, res_manager: &mut ResManager| edit_blocks(bindings, world, res_manager))
+    }
+}
+
+fn select_block(name: &str, res_manager: &mut ResManager) {
+    let id = res_manager.borrow_res::<BlockRegistry>().and_then(|it| it.id(name));
+    match (id, res_manager.borrow_res_mut::<BlockEditor>()) {
+        (Some(id), Some(mut editor)) => editor.selected = id,
+        _ => log::error!("Failed to select block '{}' to place, it is not registered", name),
+    }
+}
+
+fn edit_blocks(bindings: EditBindings, world: &mut World, res_manager: &mut ResManager) {
+    if bindings.require_grab && !res_manager.borrow_res::<CursorGrab>().is_some_and(|it| it.grabbed) {
+        return;
+    }
+    let (Some(keys), Some(buttons)) = (res_manager.borrow_res::<KeyInput>(), res_manager.borrow_res::<MouseButtonInput>()) else { return };
+    let (Some(mut editor), Some(mut voxels)) = (res_manager.borrow_res_mut::<BlockEditor>(), res_manager.borrow_res_mut::<VoxelWorld>()) else {
+        return;
+    };
+    let control = keys.pressed(VirtualKeyCode::LControl) || keys.pressed(VirtualKeyCode::RControl);
+    let changes = if control && keys.just_pressed(bindings.undo_key) {
+        editor.undo(&mut voxels)
+    } else if control && keys.just_pressed(bindings.redo_key) {
+        editor.redo(&mut voxels)
+    } else if buttons.just_pressed(bindings.break_button) || buttons.just_pressed(bindings.place_button) {
+        let Some(transform) = active_camera(world).and_then(|it| world.get::<&Transform>(it).ok().map(|it| *it)) else { return };
+        let registry = res_manager.borrow_res::<BlockRegistry>();
+        let solid = |id: BlockId| registry.as_ref().map_or(!id.is_air(), |it| it.properties(id).solid);
+        let ray = Ray::new(transform.position, transform.forward());
+        let Some(hit) = raycast(&voxels, &ray, editor.reach, solid) else { return };
+        let (position, block) = match buttons.just_pressed(bindings.break_button) {
+            true => (hit.block, BlockId::AIR),
+            false => (hit.adjacent, editor.selected),
+        };
+        // Blocks only replace what can be walked through, e.g. air or water
+        if block != BlockId::AIR && voxels.get_block(position).is_none_or(solid) {
+            return;
+        }
+        editor.edit(&mut voxels, [(position, block)])
+    } else {
+        return;
+    };
+    if changes.is_empty() {
+        return;
+    }
+
+    if let Some(mut manager) = res_manager.borrow_res_mut::<ChunkManager>() {
+        for change in &changes {
+            let chunks = chunks_touching(change.position);
+            manager.mark_modified(chunks[0]);
+            chunks[1..].iter().for_each(|it| { manager.remesh(*it); });
+        }
+    }
+    if let Some(mut events) = res_manager.borrow_res_mut::<Events<BlockChanged>>() {
+        events.send_batch(changes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::voxel::block::BlockId;
+    use crate::voxel::chunk::{Chunk, ChunkPos};
+    use crate::voxel::edit::{BlockChanged, BlockEditor, chunks_touching};
+    use crate::voxel::IVec3;
+    use crate::voxel::world::VoxelWorld;
+
+    #[test]
+    fn test_edit_undo_redo() {
+        let mut voxels = VoxelWorld::new();
+        voxels.insert_chunk(ChunkPos::new(0, 0, 0), Chunk::new());
+        let mut editor = BlockEditor::new(BlockId(1), 8.0);
+        let (a, b, unloaded) = (IVec3::new(1, 1, 1), IVec3::new(2, 1, 1), IVec3::new(-1, 1, 1));
+
+        let changes = editor.edit(&mut voxels, [(a, BlockId(1)), (b, BlockId(2)), (unloaded, BlockId(1))]);
+        assert_eq!(changes, vec![
+            BlockChanged { position: a, old: BlockId::AIR, new: BlockId(1) },
+            BlockChanged { position: b, old: BlockId::AIR, new: BlockId(2) },
+        ]);
+        editor.edit(&mut voxels, [(a, BlockId(3))]);
+        // Edits changing nothing are not batches
+        assert!(editor.edit(&mut voxels, [(a, BlockId(3))]).is_empty());
+
+        assert_eq!(editor.undo(&mut voxels), vec![BlockChanged { position: a, old: BlockId(3), new: BlockId(1) }]);
+        assert_eq!(editor.undo(&mut voxels).len(), 2);
+        assert_eq!((voxels.get_block(a), voxels.get_block(b)), (Some(BlockId::AIR), Some(BlockId::AIR)));
+        assert!(!editor.can_undo() && editor.undo(&mut voxels).is_empty());
+
+        assert_eq!(editor.redo(&mut voxels).len(), 2);
+        assert_eq!(voxels.get_block(a), Some(BlockId(1)));
+        // A new edit drops what could be redone
+        editor.edit(&mut voxels, [(b, BlockId::AIR)]);
+        assert!(!editor.can_redo());
+        assert_eq!(voxels.get_block(b), Some(BlockId::AIR));
+    }
+
+    #[test]
+    fn test_chunks_touching_blocks() {
+        assert_eq!(chunks_touching(IVec3::new(5, 5, 5)), vec![ChunkPos::new(0, 0, 0)]);
+        assert_eq!(chunks_touching(IVec3::new(0, 5, 5)), vec![ChunkPos::new(0, 0, 0), ChunkPos::new(-1, 0, 0)]);
+        // A corner block is part of the meshes of the 7 chunks around its corner
+        let corner = chunks_touching(IVec3::new(-1, 31, 0));
+        assert_eq!(corner.len(), 8);
+        assert_eq!(corner[0], ChunkPos::new(-1, 0, 0));
+        assert!(corner.contains(&ChunkPos::new(0, 1, -1)));
+    }
+}
@@ -0,0 +1,527 @@
+use std::sync::Arc;
+use bytemuck::{Pod, Zeroable};
+use wgpu::VertexBufferLayout;
+use crate::render::model::{Mesh, MeshData, Vertex};
+use crate::voxel::block::{BlockId, Face};
+use crate::voxel::chunk::{Chunk, CHUNK_SIZE, CHUNK_VOLUME, contains_local, local_position};
+use crate::voxel::IVec3;
+use crate::voxel::registry::{BlockRegistry, RenderLayer};
+
+/// Texture layer of faces whose texture is not found.
+pub const MISSING_TEXTURE_LAYER: u32 = 0;
+
+/// Side of the chunk with its one block border, see [`ChunkNeighborhood`].
+const PADDED_SIZE: i32 = CHUNK_SIZE + 2;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VoxelVertex {
+    /// Relative to the origin of the chunk.
+    pub position: [f32; 3],
+    /// In blocks, so textures repeat once per block across merged faces.
+    pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    /// Layer of the block texture array.
+    pub layer: u32,
+    /// Ambient occlusion from `0`, a corner surrounded by blocks, to `1`, an open corner.
+    pub ao: f32,
+}
+
+unsafe impl Zeroable for VoxelVertex {}
+
+unsafe impl Pod for VoxelVertex {}
+
+impl Vertex for VoxelVertex {
+    fn desc() -> VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<VoxelVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 8]>() + mem::size_of::<u32>()) as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32,
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MeshMode {
+    /// Merge neighbouring faces of the same block into rectangles.
+    #[default]
+    Greedy,
+    /// One quad per visible face, to debug culling and texturing.
+    Culled,
+}
+
+/// What the mesher needs to know about a block.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BlockMeshInfo {
+    pub render_layer: RenderLayer,
+    /// Whether faces of neighbours touching the block are hidden.
+    pub opaque: bool,
+    /// Texture layer by [`Face#index`](Face::index).
+    pub layers: [u32; 6],
+}
+
+impl BlockMeshInfo {
+    pub const INVISIBLE: BlockMeshInfo = BlockMeshInfo {
+        render_layer: RenderLayer::Invisible,
+        opaque: false,
+        layers: [MISSING_TEXTURE_LAYER; 6],
+    };
+}
+
+/// [`BlockMeshInfo`] of every block, by [`BlockId`].
+pub struct MeshTable {
+    blocks: Vec<BlockMeshInfo>,
+}
+
+impl MeshTable {
+    /// Look up the texture layer of each face by texture name with `texture_layer`,
+    /// faces without a texture or with an unknown one get [`MISSING_TEXTURE_LAYER`].
+    pub fn new(registry: &BlockRegistry, texture_layer: impl Fn(&str) -> Option<u32>) -> Self {
+        let blocks = registry.iter()
+            .map(|(_, properties)| BlockMeshInfo {
+                render_layer: properties.render_layer,
+                opaque: properties.opaque,
+                layers: Face::ALL.map(|face| properties.texture(face).and_then(&texture_layer).unwrap_or(MISSING_TEXTURE_LAYER)),
+            })
+            .collect();
+        Self { blocks }
+    }
+
+    pub fn from_blocks(blocks: Vec<BlockMeshInfo>) -> Self {
+        Self { blocks }
+    }
+
+    /// Blocks missing from the table are invisible.
+    pub fn get(&self, id: BlockId) -> BlockMeshInfo {
+        self.blocks.get(id.0 as usize).copied().unwrap_or(BlockMeshInfo::INVISIBLE)
+    }
+}
+
+/// A chunk to mesh, and the chunks around it whose border blocks hide its faces and occlude its corners.
+/// Missing neighbours count as air, so the faces towards them are meshed.
+pub struct ChunkNeighborhood<'a> {
+    pub chunk: &'a Chunk,
+    /// The 26 chunks around, by [`neighbor_index`] of their offset.
+    neighbors: [Option<&'a Chunk>; 27],
+}
+
+impl<'a> ChunkNeighborhood<'a> {
+    pub fn new(chunk: &'a Chunk) -> Self {
+        Self { chunk, neighbors: [None; 27] }
+    }
+
+    /// Add the chunk touching `face`.
+    pub fn with_neighbor(self, face: Face, chunk: &'a Chunk) -> Self {
+        self.with_neighbor_at(face.normal(), chunk)
+    }
+
+    /// Add the chunk at `offset` in chunks, each component from `-1` to `1`;
+    /// edge and corner neighbours only change ambient occlusion along the border.
+    pub fn with_neighbor_at(mut self, offset: IVec3, chunk: &'a Chunk) -> Self {
+        assert!(offset != IVec3::new(0, 0, 0), "The chunk is not its own neighbour");
+        self.neighbors[neighbor_index(offset)] = Some(chunk);
+        self
+    }
+
+    /// Blocks of the chunk surrounded by the border layers of its neighbours, see [`padded_index`].
+    fn padded(&self) -> Vec<BlockId> {
+        let mut blocks = vec![BlockId::AIR; (PADDED_SIZE * PADDED_SIZE * PADDED_SIZE) as usize];
+        for index in 0..CHUNK_VOLUME {
+            let local = local_position(index);
+            blocks[padded_index(local)] = self.chunk.blocks().get(index);
+        }
+        if self.neighbors.iter().all(|it| it.is_none()) {
+            return blocks;
+        }
+        for y in -1..=CHUNK_SIZE {
+            for z in -1..=CHUNK_SIZE {
+                for x in -1..=CHUNK_SIZE {
+                    let position = IVec3::new(x, y, z);
+                    if contains_local(position) {
+                        continue;
+                    }
+                    let offset = position.map(|it| it.div_euclid(CHUNK_SIZE));
+                    if let Some(neighbor) = self.neighbors[neighbor_index(offset)] {
+                        blocks[padded_index(position)] = neighbor.get(position.map(|it| it.rem_euclid(CHUNK_SIZE)));
+                    }
+                }
+            }
+        }
+        blocks
+    }
+}
+
+/// Index of the neighbour at `offset` in [`ChunkNeighborhood`].
+fn neighbor_index(offset: IVec3) -> usize {
+    assert!((-1..=1).contains(&offset.x) && (-1..=1).contains(&offset.y) && (-1..=1).contains(&offset.z),
+            "{:?} is not the offset of a neighbour", offset);
+    ((offset.y + 1) * 9 + (offset.z + 1) * 3 + offset.x + 1) as usize
+}
+
+/// Index of the block at `local` in blocks padded by one on each side, `local` going from `-1` to [`CHUNK_SIZE`].
+fn padded_index(local: IVec3) -> usize {
+    (((local.y + 1) * PADDED_SIZE + local.z + 1) * PADDED_SIZE + local.x + 1) as usize
+}
+
+/// Axis a face is perpendicular to, and whether it faces the positive direction.
+fn face_axis(face: Face) -> (usize, bool) {
+    let normal = face.normal();
+    let axis = (0..3).find(|it| normal[*it] != 0).unwrap();
+    (axis, normal[axis] > 0)
+}
+
+/// Position with `d` along `axis`, and `u`, `v` along the next two axes, wrapping around.
+/// `u` cross `v` points along `axis`.
+fn axis_vec(axis: usize, d: i32, u: i32, v: i32) -> IVec3 {
+    let mut it = IVec3::new(0, 0, 0);
+    it[axis] = d;
+    it[(axis + 1) % 3] = u;
+    it[(axis + 2) % 3] = v;
+    it
+}
+
+/// Texture coordinates of a point on `face`, upright on side faces and never mirrored when seen from outside.
+fn tex_coords(face: Face, position: [f32; 3]) -> [f32; 2] {
+    let [x, y, z] = position;
+    match face {
+        Face::PosX => [-z, -y],
+        Face::NegX => [z, -y],
+        Face::PosZ => [x, -y],
+        Face::NegZ => [-x, -y],
+        Face::PosY => [x, z],
+        Face::NegY => [x, -z],
+    }
+}
+
+/// Meshes of a chunk, one per [`RenderLayer`] that is drawn.
+pub struct ChunkMeshData {
+    pub opaque: MeshData<VoxelVertex>,
+    pub cutout: MeshData<VoxelVertex>,
+    pub translucent: MeshData<VoxelVertex>,
+}
+
+impl ChunkMeshData {
+    fn new() -> Self {
+        let mesh = |name: &str| MeshData { name: name.to_string(), vertices: vec![], indices: vec![], material: 0 };
+        Self {
+            opaque: mesh("chunk opaque"),
+            cutout: mesh("chunk cutout"),
+            translucent: mesh("chunk translucent"),
+        }
+    }
+
+    pub fn layer(&self, layer: RenderLayer) -> Option<&MeshData<VoxelVertex>> {
+        match layer {
+            RenderLayer::Invisible => None,
+            RenderLayer::Opaque => Some(&self.opaque),
+            RenderLayer::Cutout => Some(&self.cutout),
+            RenderLayer::Translucent => Some(&self.translucent),
+        }
+    }
+
+    fn layer_mut(&mut self, layer: RenderLayer) -> Option<&mut MeshData<VoxelVertex>> {
+        match layer {
+            RenderLayer::Invisible => None,
+            RenderLayer::Opaque => Some(&mut self.opaque),
+            RenderLayer::Cutout => Some(&mut self.cutout),
+            RenderLayer::Translucent => Some(&mut self.translucent),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.opaque.indices.is_empty() && self.cutout.indices.is_empty() && self.translucent.indices.is_empty()
+    }
+
+    /// Upload the layers which have faces.
+    pub fn upload(&self, device: &wgpu::Device) -> ChunkMesh {
+        let upload = |mesh: &MeshData<VoxelVertex>| (!mesh.indices.is_empty()).then(|| Arc::new(mesh.upload(device)));
+        ChunkMesh {
+            opaque: upload(&self.opaque),
+            cutout: upload(&self.cutout),
+            translucent: upload(&self.translucent),
+        }
+    }
+
+    /// Add a `width` by `height` rectangle of `face`s in the plane of the face of block `d` along the axis of `face`,
+    /// starting at `(u, v)` in the plane, with the ambient occlusion `ao` of its corners, see [`corner_ao`].
+    fn push_quad(&mut self, info: BlockMeshInfo, face: Face, d: i32, [u, v]: [i32; 2], [width, height]: [i32; 2], ao: [u8; 4]) {
+        let Some(mesh) = self.layer_mut(info.render_layer) else { return };
+        let (axis, positive) = face_axis(face);
+        let plane = d + positive as i32;
+        let corners = [(u, v), (u + width, v), (u + width, v + height), (u, v + height)];
+        let normal = face.normal().cast::<f32>().unwrap().into();
+        let base = mesh.vertices.len() as u32;
+        for (corner, (u, v)) in corners.into_iter().enumerate() {
+            let position: [f32; 3] = axis_vec(axis, plane, u, v).cast::<f32>().unwrap().into();
+            mesh.vertices.push(VoxelVertex {
+                position,
+                tex_coords: tex_coords(face, position),
+                normal,
+                layer: info.layers[face.index()],
+                ao: ao[corner] as f32 / 3.0,
+            });
+        }
+        // Counter-clockwise seen from the side the face points to, split along the brighter diagonal
+        // so a single dark or light corner stays within one triangle whichever way the quad is oriented.
+        let flipped = ao[0] + ao[2] < ao[1] + ao[3];
+        let indices = match (positive, flipped) {
+            (true, false) => [0, 1, 2, 0, 2, 3],
+            (false, false) => [0, 2, 1, 0, 3, 2],
+            (true, true) => [0, 1, 3, 1, 2, 3],
+            (false, true) => [0, 3, 1, 1, 3, 2],
+        };
+        mesh.indices.extend(indices.map(|it| base + it));
+    }
+}
+
+/// Uploaded [`ChunkMeshData`], drawn by [`VoxelPass`](crate::render::pass::voxel::VoxelPass)
+/// at the translation of the [`GlobalTransform`](crate::transform::GlobalTransform) of its entity, the chunk origin.
+/// Clones share the buffers, which keeps extracting it cheap.
+#[derive(Clone, Default)]
+pub struct ChunkMesh {
+    pub opaque: Option<Arc<Mesh>>,
+    pub cutout: Option<Arc<Mesh>>,
+    pub translucent: Option<Arc<Mesh>>,
+}
+
+impl ChunkMesh {
+    pub fn layer(&self, layer: RenderLayer) -> Option<&Mesh> {
+        match layer {
+            RenderLayer::Invisible => None,
+            RenderLayer::Opaque => self.opaque.as_deref(),
+            RenderLayer::Cutout => self.cutout.as_deref(),
+            RenderLayer::Translucent => self.translucent.as_deref(),
+        }
+    }
+}
+
+/// Ambient occlusion of the corners of the face of the block at `position` facing along `axis` towards `normal`,
+/// in the order of the corners of [`ChunkMeshData#push_quad`](ChunkMeshData::push_quad),
+/// from `0` for a corner between two opaque blocks to `3` for one without opaque blocks around.
+fn corner_ao(blocks: &[BlockId], table: &MeshTable, position: IVec3, axis: usize, normal: IVec3) -> [u8; 4] {
+    let front = position + normal;
+    let opaque = |offset: IVec3| table.get(blocks[padded_index(front + offset)]).opaque as u8;
+    [(-1, -1), (1, -1), (1, 1), (-1, 1)].map(|(u, v)| {
+        let side_u = opaque(axis_vec(axis, 0, u, 0));
+        let side_v = opaque(axis_vec(axis, 0, 0, v));
+        let corner = opaque(axis_vec(axis, 0, u, v));
+        match side_u + side_v {
+            2 => 0,
+            sides => 3 - sides - corner,
+        }
+    })
+}
+
+/// Mesh the visible faces of a chunk, without uploading anything.
+/// # Explanation
+/// A face is visible when its block is drawn and the neighbour it touches is neither opaque nor the same block,
+/// so faces between water blocks or between glass blocks disappear as well.
+/// For each face direction and slice of the chunk, visible faces are collected into a mask,
+/// then [`MeshMode::Greedy`] grows rectangles of faces of the same block, first along `u`, then along `v`.
+///
+/// Each corner of a face is darkened by the opaque blocks around it in front of the face, see [`corner_ao`].
+/// Faces only merge when their corners have the same occlusion, so merging never changes the shading.
+pub fn mesh_chunk(neighborhood: &ChunkNeighborhood, table: &MeshTable, mode: MeshMode) -> ChunkMeshData {
+    let blocks = neighborhood.padded();
+    let size = CHUNK_SIZE as usize;
+    let mut meshes = ChunkMeshData::new();
+    let mut mask: Vec<Option<(BlockId, [u8; 4])>> = vec![None; size * size];
+
+    for face in Face::ALL {
+        let (axis, _) = face_axis(face);
+        let normal = face.normal();
+        for d in 0..CHUNK_SIZE {
+            for v in 0..CHUNK_SIZE {
+                for u in 0..CHUNK_SIZE {
+                    let position = axis_vec(axis, d, u, v);
+                    let block = blocks[padded_index(position)];
+                    let neighbor = blocks[padded_index(position + normal)];
+                    let visible = table.get(block).render_layer != RenderLayer::Invisible
+                        && !table.get(neighbor).opaque
+                        && neighbor != block;
+                    mask[v as usize * size + u as usize] = visible.then(|| (block, corner_ao(&blocks, table, position, axis, normal)));
+                }
+            }
+
+            for v in 0..size {
+                let mut u = 0;
+                while u < size {
+                    let Some((block, ao)) = mask[v * size + u] else {
+                        u += 1;
+                        continue;
+                    };
+                    let (mut width, mut height) = (1, 1);
+                    if mode == MeshMode::Greedy {
+                        while u + width < size && mask[v * size + u + width] == Some((block, ao)) {
+                            width += 1;
+                        }
+                        while v + height < size && (0..width).all(|it| mask[(v + height) * size + u + it] == Some((block, ao))) {
+                            height += 1;
+                        }
+                    }
+                    for row in v..v + height {
+                        mask[row * size + u..row * size + u + width].fill(None);
+                    }
+                    meshes.push_quad(table.get(block), face, d, [u as i32, v as i32], [width as i32, height as i32], ao);
+                    u += width;
+                }
+            }
+        }
+    }
+    meshes
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::{InnerSpace, Vector3};
+    use crate::voxel::block::{BlockId, Face};
+    use crate::voxel::chunk::{Chunk, CHUNK_SIZE};
+    use crate::voxel::IVec3;
+    use crate::voxel::mesher::{BlockMeshInfo, ChunkMeshData, ChunkNeighborhood, mesh_chunk, MeshMode, MeshTable};
+    use crate::voxel::registry::RenderLayer;
+
+    const STONE: BlockId = BlockId(1);
+    const DIRT: BlockId = BlockId(2);
+    const GLASS: BlockId = BlockId(3);
+
+    fn table() -> MeshTable {
+        let solid = BlockMeshInfo { render_layer: RenderLayer::Opaque, opaque: true, layers: [1, 1, 2, 3, 1, 1] };
+        MeshTable::from_blocks(vec![
+            BlockMeshInfo::INVISIBLE,
+            solid,
+            BlockMeshInfo { layers: [4; 6], ..solid },
+            BlockMeshInfo { render_layer: RenderLayer::Cutout, opaque: false, layers: [5; 6] },
+        ])
+    }
+
+    fn quads(meshes: &ChunkMeshData) -> usize {
+        (meshes.opaque.indices.len() + meshes.cutout.indices.len()) / 6
+    }
+
+    #[test]
+    fn test_single_block_faces_outward() {
+        let mut chunk = Chunk::new();
+        chunk.set(IVec3::new(3, 4, 5), STONE);
+        let meshes = mesh_chunk(&ChunkNeighborhood::new(&chunk), &table(), MeshMode::Greedy);
+        assert_eq!(quads(&meshes), 6);
+
+        let mesh = &meshes.opaque;
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|it| Vector3::from(mesh.vertices[triangle[it] as usize].position));
+            let normal = Vector3::from(mesh.vertices[triangle[0] as usize].normal);
+            assert!((b - a).cross(c - a).dot(normal) > 0.0);
+        }
+        // Each face lies on the side of the block its normal points to
+        for quad in mesh.vertices.chunks_exact(4) {
+            let center = (Vector3::from(quad[0].position) + Vector3::from(quad[2].position)) / 2.0;
+            let normal = Vector3::from(quad[0].normal);
+            assert!((center - Vector3::new(3.5, 4.5, 5.5) - normal * 0.5).magnitude() < 1e-5);
+        }
+        let top = mesh.vertices.iter().find(|it| it.normal == [0.0, 1.0, 0.0]).unwrap();
+        assert_eq!(top.layer, 2);
+    }
+
+    #[test]
+    fn test_greedy_merges_same_blocks() {
+        let full = Chunk::filled(STONE);
+        let greedy = mesh_chunk(&ChunkNeighborhood::new(&full), &table(), MeshMode::Greedy);
+        assert_eq!(quads(&greedy), 6);
+        let culled = mesh_chunk(&ChunkNeighborhood::new(&full), &table(), MeshMode::Culled);
+        assert_eq!(quads(&culled), 6 * (CHUNK_SIZE * CHUNK_SIZE) as usize);
+
+        // Border layers of neighbours hide the faces touching them
+        let mut neighborhood = ChunkNeighborhood::new(&full);
+        for face in Face::ALL {
+            neighborhood = neighborhood.with_neighbor(face, &full);
+        }
+        assert!(mesh_chunk(&neighborhood, &table(), MeshMode::Greedy).is_empty());
+
+        // Different blocks are not merged, faces between glass blocks are hidden
+        let mut chunk = Chunk::new();
+        chunk.set(IVec3::new(0, 0, 0), STONE);
+        chunk.set(IVec3::new(1, 0, 0), DIRT);
+        chunk.set(IVec3::new(0, 5, 0), GLASS);
+        chunk.set(IVec3::new(1, 5, 0), GLASS);
+        let meshes = mesh_chunk(&ChunkNeighborhood::new(&chunk), &table(), MeshMode::Greedy);
+        assert_eq!(meshes.opaque.indices.len() / 6, 10);
+        assert_eq!(meshes.cutout.indices.len() / 6, 6);
+    }
+
+    #[test]
+    fn test_ambient_occlusion() {
+        let top_face = |meshes: &ChunkMeshData| {
+            let mesh = &meshes.opaque;
+            let start = mesh.vertices.iter().position(|it| it.normal == [0.0, 1.0, 0.0] && it.position[1] == 5.0).unwrap();
+            let indices = mesh.indices.iter()
+                .filter(|it| (start..start + 4).contains(&(**it as usize)))
+                .map(|it| *it as usize - start)
+                .collect::<Vec<_>>();
+            (mesh.vertices[start..start + 4].to_vec(), indices)
+        };
+
+        // A block above one edge darkens both corners along it
+        let mut chunk = Chunk::new();
+        chunk.set(IVec3::new(3, 4, 5), STONE);
+        chunk.set(IVec3::new(4, 5, 5), STONE);
+        let (vertices, _) = top_face(&mesh_chunk(&ChunkNeighborhood::new(&chunk), &table(), MeshMode::Greedy));
+        for vertex in vertices {
+            let expected = if vertex.position[0] == 4.0 { 2.0 / 3.0 } else { 1.0 };
+            assert_eq!(vertex.ao, expected);
+        }
+
+        // A single darker corner is only part of one triangle
+        let mut chunk = Chunk::new();
+        chunk.set(IVec3::new(3, 4, 5), STONE);
+        chunk.set(IVec3::new(4, 5, 6), STONE);
+        let (vertices, indices) = top_face(&mesh_chunk(&ChunkNeighborhood::new(&chunk), &table(), MeshMode::Greedy));
+        let dark = vertices.iter().position(|it| it.ao < 1.0).unwrap();
+        assert_eq!(vertices[dark].position, [4.0, 5.0, 6.0]);
+        assert_eq!(indices.iter().filter(|it| **it == dark).count(), 1);
+
+        // Occluded faces do not merge with open ones, occluders in neighbours count as well
+        let mut floor = Chunk::new();
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                floor.set(IVec3::new(x, 0, z), STONE);
+            }
+        }
+        let top_quads = |meshes: &ChunkMeshData| meshes.opaque.vertices.chunks_exact(4).filter(|it| it[0].normal == [0.0, 1.0, 0.0]).count();
+        assert_eq!(top_quads(&mesh_chunk(&ChunkNeighborhood::new(&floor), &table(), MeshMode::Greedy)), 1);
+        let mut corner = Chunk::new();
+        corner.set(IVec3::new(0, 1, 0), STONE);
+        let neighborhood = ChunkNeighborhood::new(&floor).with_neighbor_at(IVec3::new(1, 0, 1), &corner);
+        let meshes = mesh_chunk(&neighborhood, &table(), MeshMode::Greedy);
+        assert!(top_quads(&meshes) > 1);
+        // The top, +X and +Z faces all meet at the occluded corner
+        let darkened = meshes.opaque.vertices.iter().filter(|it| it.ao < 1.0).collect::<Vec<_>>();
+        assert_eq!(darkened.len(), 3);
+        assert!(darkened.iter().all(|it| it.position == [32.0, 1.0, 32.0]));
+    }
+}
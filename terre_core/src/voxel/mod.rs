@@ -0,0 +1,24 @@
+//! Blocks of the voxel world, stored in [`Chunk`](chunk::Chunk)s of [`CHUNK_SIZE`](chunk::CHUNK_SIZE)³ blocks.
+//! # Coordinates
+//! Block positions are [`IVec3`]s in world space, one unit per block.
+//! They split into the [`ChunkPos`](chunk::ChunkPos) of their chunk and a local position within it,
+//! rounding towards negative infinity, so block `-1` is the last block of chunk `-1`.
+use cgmath::Vector3;
+
+pub mod block;
+pub mod palette;
+pub mod chunk;
+pub mod registry;
+pub mod mesher;
+pub mod world;
+pub mod textures;
+pub mod terrain;
+pub mod prefab;
+pub mod decoration;
+pub mod storage;
+pub mod streaming;
+pub mod raycast;
+pub mod edit;
+
+/// Integer position, e.g. of a block in world space.
+pub type IVec3 = Vector3<i32>;
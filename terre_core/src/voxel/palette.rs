@@ -0,0 +1,226 @@
+use crate::voxel::block::BlockId;
+
+/// Largest palette index width before switching to a full array, palettes hold up to 256 blocks.
+const MAX_PALETTE_BITS: u32 = 8;
+/// Bits per block of the full array.
+const FULL_BITS: u32 = u16::BITS;
+
+/// Fixed number of [`BlockId`]s, compressed by how many different blocks they hold.
+/// # Explanation
+/// Storage starts as a single value shared by every index.
+/// Setting a second block switches to indices into a palette, bit-packed into `u64` words with
+/// 1, 2, 4 or 8 bits each, widened as the palette grows.
+/// Beyond 256 different blocks every block is stored in full.
+///
+/// Palette entries are never removed while setting, [`#compact`](PalettedStorage::compact)
+/// switches back to the smallest representation once blocks were replaced.
+#[derive(Clone, Debug)]
+pub struct PalettedStorage {
+    len: usize,
+    data: Data,
+}
+
+#[derive(Clone, Debug)]
+enum Data {
+    Single(BlockId),
+    Packed(PackedArray),
+    Full(Box<[BlockId]>),
+}
+
+#[derive(Clone, Debug)]
+struct PackedArray {
+    palette: Vec<BlockId>,
+    bits: u32,
+    words: Vec<u64>,
+}
+
+impl PackedArray {
+    fn new(len: usize, bits: u32, palette: Vec<BlockId>) -> Self {
+        let per_word = (u64::BITS / bits) as usize;
+        Self {
+            palette,
+            bits,
+            words: vec![0; len.div_ceil(per_word)],
+        }
+    }
+
+    fn index(&self, index: usize) -> usize {
+        let per_word = (u64::BITS / self.bits) as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = (1u64 << self.bits) - 1;
+        ((self.words[index / per_word] >> shift) & mask) as usize
+    }
+
+    fn set_index(&mut self, index: usize, value: usize) {
+        let per_word = (u64::BITS / self.bits) as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let word = &mut self.words[index / per_word];
+        *word = (*word & !mask) | ((value as u64) << shift);
+    }
+
+    fn get(&self, index: usize) -> BlockId {
+        self.palette[self.index(index)]
+    }
+}
+
+/// Smallest supported width of palette indices holding `count` entries.
+fn palette_bits(count: usize) -> u32 {
+    let needed = usize::BITS - (count.max(2) - 1).leading_zeros();
+    needed.next_power_of_two()
+}
+
+impl PalettedStorage {
+    /// `len` copies of `block`.
+    pub fn new(len: usize, block: BlockId) -> Self {
+        Self { len, data: Data::Single(block) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bits stored per block, `0` while every block is the same.
+    pub fn bits_per_block(&self) -> u32 {
+        match &self.data {
+            Data::Single(_) => 0,
+            Data::Packed(packed) => packed.bits,
+            Data::Full(_) => FULL_BITS,
+        }
+    }
+
+    /// Blocks this storage can hold without switching representation, if it has a palette.
+    pub fn palette(&self) -> Option<&[BlockId]> {
+        match &self.data {
+            Data::Single(block) => Some(std::slice::from_ref(block)),
+            Data::Packed(packed) => Some(&packed.palette),
+            Data::Full(_) => None,
+        }
+    }
+
+    /// Panics if `index` is out of bounds.
+    pub fn get(&self, index: usize) -> BlockId {
+        assert!(index < self.len, "Index {} out of bounds of {}", index, self.len);
+        match &self.data {
+            Data::Single(block) => *block,
+            Data::Packed(packed) => packed.get(index),
+            Data::Full(blocks) => blocks[index],
+        }
+    }
+
+    /// Set the block at `index` and return the one replaced, panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, block: BlockId) -> BlockId {
+        let previous = self.get(index);
+        if previous == block {
+            return previous;
+        }
+        match &mut self.data {
+            Data::Single(single) => {
+                let mut packed = PackedArray::new(self.len, 1, vec![*single, block]);
+                packed.set_index(index, 1);
+                self.data = Data::Packed(packed);
+            }
+            Data::Packed(packed) => {
+                let value = match packed.palette.iter().position(|it| *it == block) {
+                    Some(it) => it,
+                    None if packed.palette.len() < 1 << packed.bits => {
+                        packed.palette.push(block);
+                        packed.palette.len() - 1
+                    }
+                    None if packed.bits < MAX_PALETTE_BITS => {
+                        *packed = Self::repack(packed, self.len, packed.bits * 2);
+                        packed.palette.push(block);
+                        packed.palette.len() - 1
+                    }
+                    None => {
+                        let mut blocks: Box<[BlockId]> = (0..self.len).map(|it| packed.get(it)).collect();
+                        blocks[index] = block;
+                        self.data = Data::Full(blocks);
+                        return previous;
+                    }
+                };
+                packed.set_index(index, value);
+            }
+            Data::Full(blocks) => blocks[index] = block,
+        }
+        previous
+    }
+
+    /// Set every block to `block`.
+    pub fn fill(&mut self, block: BlockId) {
+        self.data = Data::Single(block);
+    }
+
+    fn repack(packed: &PackedArray, len: usize, bits: u32) -> PackedArray {
+        let mut repacked = PackedArray::new(len, bits, packed.palette.clone());
+        (0..len).for_each(|it| repacked.set_index(it, packed.index(it)));
+        repacked
+    }
+
+    /// Drop palette entries no block uses anymore and switch to the smallest representation.
+    pub fn compact(&mut self) {
+        let mut palette: Vec<BlockId> = vec![];
+        let mut indices = Vec::with_capacity(self.len);
+        for index in 0..self.len {
+            let block = self.get(index);
+            let value = match palette.iter().position(|it| *it == block) {
+                Some(it) => it,
+                None => {
+                    palette.push(block);
+                    palette.len() - 1
+                }
+            };
+            indices.push(value);
+        }
+        self.data = match palette.len() {
+            0 => return,
+            1 => Data::Single(palette[0]),
+            count if palette_bits(count) <= MAX_PALETTE_BITS => {
+                let mut packed = PackedArray::new(self.len, palette_bits(count), palette);
+                indices.iter().enumerate().for_each(|(index, value)| packed.set_index(index, *value));
+                Data::Packed(packed)
+            }
+            _ => Data::Full(indices.iter().map(|it| palette[*it]).collect()),
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::voxel::block::BlockId;
+    use crate::voxel::palette::PalettedStorage;
+
+    #[test]
+    fn test_storage_switches_representation() {
+        let mut storage = PalettedStorage::new(1000, BlockId::AIR);
+        assert_eq!(storage.bits_per_block(), 0);
+
+        assert_eq!(storage.set(7, BlockId(1)), BlockId::AIR);
+        assert_eq!(storage.bits_per_block(), 1);
+        storage.set(8, BlockId(2));
+        assert_eq!(storage.bits_per_block(), 2);
+        assert_eq!((storage.get(6), storage.get(7), storage.get(8)), (BlockId::AIR, BlockId(1), BlockId(2)));
+
+        for i in 0..300 {
+            storage.set(i, BlockId(i as u16 + 1));
+        }
+        assert_eq!(storage.bits_per_block(), 16);
+        assert!((0..300).all(|i| storage.get(i) == BlockId(i as u16 + 1)));
+        assert_eq!(storage.get(300), BlockId::AIR);
+
+        // Replaced blocks leave the palette only once compacted
+        for i in 0..300 {
+            storage.set(i, BlockId(i as u16 % 3));
+        }
+        storage.compact();
+        assert_eq!(storage.bits_per_block(), 2);
+        assert!((0..300).all(|i| storage.get(i) == BlockId(i as u16 % 3)));
+
+        storage.fill(BlockId(5));
+        assert_eq!((storage.bits_per_block(), storage.get(999)), (0, BlockId(5)));
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use anyhow::{anyhow, bail, Context};
+use crate::asset::io::AssetIo;
+use crate::voxel::block::BlockId;
+use crate::voxel::IVec3;
+use crate::voxel::registry::BlockRegistry;
+
+/// Cells of a prefab which leave the world as it is.
+const EMPTY_CELLS: [char; 2] = ['.', ' '];
+
+/// Small voxel structure placed by [`PrefabFeature`](crate::voxel::decoration::PrefabFeature).
+/// # Explanation
+/// Prefabs are text files, e.g. under `res/prefabs`:
+/// ```text
+/// # A stone pillar, comments start with '#'
+/// size 3 2 3
+/// anchor 1 0 1
+/// key S terre:stone
+/// key _ terre:air
+/// layer
+/// .S.
+/// S_S
+/// .S.
+/// layer
+/// ...
+/// .S.
+/// ...
+/// ```
+/// `size` is the width along `X`, height and depth along `Z`, followed by one `layer` per height from the bottom up.
+/// A layer has one row per `Z`, with one character per `X`; `key` maps characters to block names,
+/// `.` and spaces leave the world unchanged, so mapping a character to air clears blocks.
+/// Lines are trimmed and blank lines skipped, so rows may be indented and an empty row is written with `.`.
+/// The `anchor` cell is placed on the surface block the prefab stands on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrefabData {
+    pub size: IVec3,
+    pub anchor: IVec3,
+    /// By [`#index`](PrefabData::index), `None` where the world is unchanged.
+    pub blocks: Vec<Option<String>>,
+}
+
+impl PrefabData {
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let mut size = None;
+        let mut anchor = IVec3::new(0, 0, 0);
+        let mut keys: HashMap<char, String> = HashMap::new();
+        let mut layers: Vec<Vec<&str>> = vec![];
+
+        for (number, line) in source.lines().enumerate() {
+            let error = || format!("Line {}: '{}'", number + 1, line);
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let words = trimmed.split_whitespace().collect::<Vec<_>>();
+            match words.as_slice() {
+                ["size", x, y, z] => size = Some(parse_vec([x, y, z]).with_context(error)?),
+                ["anchor", x, y, z] => anchor = parse_vec([x, y, z]).with_context(error)?,
+                ["key", key, name] => {
+                    let mut chars = key.chars();
+                    let (Some(key), None) = (chars.next(), chars.next()) else {
+                        return Err(anyhow!("Keys are one character")).with_context(error);
+                    };
+                    if EMPTY_CELLS.contains(&key) {
+                        return Err(anyhow!("'{}' is an empty cell", key)).with_context(error);
+                    }
+                    keys.insert(key, name.to_string());
+                }
+                ["layer"] => layers.push(vec![]),
+                _ => match layers.last_mut() {
+                    Some(layer) => layer.push(trimmed),
+                    None => return Err(anyhow!("Expected size, anchor, key or layer")).with_context(error),
+                },
+            }
+        }
+
+        let size = size.ok_or_else(|| anyhow!("Missing size"))?;
+        if size.x <= 0 || size.y <= 0 || size.z <= 0 {
+            bail!("Size {:?} is empty", size);
+        }
+        if layers.len() != size.y as usize {
+            bail!("Expected {} layers, found {}", size.y, layers.len());
+        }
+        let mut blocks = vec![None; (size.x * size.y * size.z) as usize];
+        for (y, layer) in layers.iter().enumerate() {
+            if layer.len() != size.z as usize {
+                bail!("Layer {} has {} rows instead of {}", y, layer.len(), size.z);
+            }
+            for (z, row) in layer.iter().enumerate() {
+                if row.chars().count() > size.x as usize {
+                    bail!("Row {} of layer {} is wider than {}", z, y, size.x);
+                }
+                for (x, cell) in row.chars().enumerate().filter(|(_, it)| !EMPTY_CELLS.contains(it)) {
+                    let name = keys.get(&cell).ok_or_else(|| anyhow!("Unknown key '{}' in layer {}", cell, y))?;
+                    blocks[index(size, IVec3::new(x as i32, y as i32, z as i32))] = Some(name.clone());
+                }
+            }
+        }
+        Ok(Self { size, anchor, blocks })
+    }
+
+    /// Look up the blocks by name, failing on blocks which are not registered.
+    pub fn resolve(&self, registry: &BlockRegistry) -> anyhow::Result<Prefab> {
+        let blocks = self.blocks.iter()
+            .map(|it| it.as_ref()
+                .map(|name| registry.id(name).ok_or_else(|| anyhow!("Block '{}' is not registered", name)))
+                .transpose())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Prefab { size: self.size, anchor: self.anchor, blocks })
+    }
+}
+
+fn parse_vec(words: [&&str; 3]) -> anyhow::Result<IVec3> {
+    let [x, y, z] = words.map(|it| it.parse::<i32>());
+    Ok(IVec3::new(x?, y?, z?))
+}
+
+/// Index of the cell at `local` in cells of `size`, `X` first, then `Z`, then `Y`.
+fn index(size: IVec3, local: IVec3) -> usize {
+    ((local.y * size.z + local.z) * size.x + local.x) as usize
+}
+
+/// [`PrefabData`] with block ids.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prefab {
+    pub size: IVec3,
+    pub anchor: IVec3,
+    blocks: Vec<Option<BlockId>>,
+}
+
+impl Prefab {
+    /// Parse the prefab at `path` and resolve it with `registry`.
+    pub fn load(io: &dyn AssetIo, path: &str, registry: &BlockRegistry) -> anyhow::Result<Self> {
+        let source = io.read_to_string(path)?;
+        PrefabData::parse(&source)
+            .and_then(|it| it.resolve(registry))
+            .with_context(|| format!("Invalid prefab '{}'", path))
+    }
+
+    /// Blocks relative to the anchor, skipping empty cells.
+    pub fn blocks(&self) -> impl Iterator<Item = (IVec3, BlockId)> + '_ {
+        let size = self.size;
+        (0..size.y).flat_map(move |y| (0..size.z).flat_map(move |z| (0..size.x).map(move |x| IVec3::new(x, y, z))))
+            .filter_map(move |local| self.blocks[index(size, local)].map(|block| (local - self.anchor, block)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::voxel::block::BlockId;
+    use crate::voxel::IVec3;
+    use crate::voxel::prefab::PrefabData;
+    use crate::voxel::registry::BlockRegistry;
+
+    const PILLAR: &str = "
+        # A pillar
+        size 3 2 2
+        anchor 1 0 0
+        key S terre:stone
+        key _ terre:air
+        layer
+        S_S
+        .S
+        layer
+        # Comments and blank lines between rows
+
+        .S.
+        ...
+    ";
+
+    #[test]
+    fn test_parse_prefab() {
+        let data = PrefabData::parse(PILLAR).unwrap();
+        assert_eq!((data.size, data.anchor), (IVec3::new(3, 2, 2), IVec3::new(1, 0, 0)));
+
+        let mut registry = BlockRegistry::new();
+        registry.load_json(r#"{ "blocks": [{ "id": "terre:stone" }] }"#).unwrap();
+        let prefab = data.resolve(&registry).unwrap();
+        let blocks = prefab.blocks().collect::<Vec<_>>();
+        assert_eq!(blocks, vec![
+            (IVec3::new(-1, 0, 0), BlockId(1)),
+            (IVec3::new(0, 0, 0), BlockId::AIR),
+            (IVec3::new(1, 0, 0), BlockId(1)),
+            (IVec3::new(0, 0, 1), BlockId(1)),
+            (IVec3::new(0, 1, 0), BlockId(1)),
+        ]);
+
+        for source in [include_str!("../../../res/prefabs/well.prefab"), include_str!("../../../res/prefabs/hut.prefab")] {
+            PrefabData::parse(source).unwrap();
+        }
+        assert!(PrefabData::parse("size 1 2 1\nkey S terre:stone\nlayer\nS").is_err());
+        assert!(PrefabData::parse("size 1 1 1\nlayer\nS").is_err());
+        assert!(PrefabData::parse("size 1 1 1\nkey S terre:stone\nlayer\nSS").is_err());
+        assert!(PrefabData::parse("size 1 1 1\nkey S terre:gold\nlayer\nS").unwrap().resolve(&registry).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use cgmath::Vector3;
+use crate::render::camera::Ray;
+use crate::voxel::block::BlockId;
+use crate::voxel::IVec3;
+use crate::voxel::world::VoxelWorld;
+
+/// Block found by [`raycast`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VoxelHit {
+    /// World position of the block.
+    pub block: IVec3,
+    pub id: BlockId,
+    /// Normal of the face the ray entered the block through.
+    pub normal: IVec3,
+    /// Distance along the ray to the face.
+    pub distance: f32,
+    /// Block in front of the face, the one the ray crossed before, where a block would be placed.
+    pub adjacent: IVec3,
+}
+
+/// First block along `ray` within `max_distance` for which `hits` is true, e.g. solid blocks for picking.
+/// # Explanation
+/// Walks the blocks the ray crosses in order, as in *A Fast Voxel Traversal Algorithm* by Amanatides and Woo:
+/// for each axis, the distance along the ray to the next block boundary is kept, and the ray steps over
+/// whichever boundary is nearest.
+///
+/// The block containing the origin is skipped, so its face normal is always known.
+/// The ray stops without a hit at chunks which are not loaded.
+pub fn raycast(world: &VoxelWorld, ray: &Ray, max_distance: f32, hits: impl Fn(BlockId) -> bool) -> Option<VoxelHit> {
+    let mut block = ray.origin.map(|it| it.floor() as i32);
+    let step = ray.direction.map(|it| if it > 0.0 { 1 } else if it < 0.0 { -1 } else { 0 });
+    // Distance to the first boundary along each axis, and between boundaries
+    let mut next: Vector3<f32> = Vector3::new(0, 1, 2).map(|axis| match step[axis] {
+        0 => f32::INFINITY,
+        1 => (block[axis] as f32 + 1.0 - ray.origin[axis]) / ray.direction[axis],
+        _ => (block[axis] as f32 - ray.origin[axis]) / ray.direction[axis],
+    });
+    let delta = ray.direction.map(|it| if it == 0.0 { f32::INFINITY } else { 1.0 / it.abs() });
+
+    loop {
+        let axis = if next.x <= next.y && next.x <= next.z { 0 } else if next.y <= next.z { 1 } else { 2 };
+        let distance = next[axis];
+        if distance > max_distance {
+            return None;
+        }
+        block[axis] += step[axis];
+        next[axis] += delta[axis];
+
+        let id = world.get_block(block)?;
+        if hits(id) {
+            let mut normal = IVec3::new(0, 0, 0);
+            normal[axis] = -step[axis];
+            return Some(VoxelHit { block, id, normal, distance, adjacent: block + normal });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cgmath::Vector3;
+    use crate::render::camera::Ray;
+    use crate::voxel::block::BlockId;
+    use crate::voxel::chunk::{Chunk, ChunkPos};
+    use crate::voxel::IVec3;
+    use crate::voxel::raycast::raycast;
+    use crate::voxel::world::VoxelWorld;
+
+    #[test]
+    fn test_raycast_blocks() {
+        let mut world = VoxelWorld::new();
+        world.insert_chunk(ChunkPos::new(0, 0, 0), Chunk::new());
+        world.insert_chunk(ChunkPos::new(-1, 0, 0), Chunk::new());
+        for block in [IVec3::new(5, 2, 2), IVec3::new(4, 2, 0), IVec3::new(-3, 2, 2)] {
+            world.set_block(block, BlockId(1));
+        }
+        let solid = |it: BlockId| !it.is_air();
+
+        let ray = Ray::new(Vector3::new(0.5, 2.5, 2.5), Vector3::new(1.0, 0.0, 0.0));
+        let hit = raycast(&world, &ray, 10.0, solid).unwrap();
+        assert_eq!((hit.block, hit.id, hit.normal, hit.adjacent), (IVec3::new(5, 2, 2), BlockId(1), IVec3::new(-1, 0, 0), IVec3::new(4, 2, 2)));
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+        assert_eq!(raycast(&world, &ray, 4.0, solid), None);
+
+        // Into negative coordinates, and diagonally, entering the block from the side after crossing a boundary in y
+        let hit = raycast(&world, &Ray::new(Vector3::new(0.5, 2.5, 2.5), Vector3::new(-1.0, 0.0, 0.0)), 10.0, solid).unwrap();
+        assert_eq!((hit.block, hit.normal, hit.adjacent), (IVec3::new(-3, 2, 2), IVec3::new(1, 0, 0), IVec3::new(-2, 2, 2)));
+        let hit = raycast(&world, &Ray::new(Vector3::new(0.5, 0.5, 0.5), Vector3::new(1.0, 0.5, 0.0)), 10.0, solid).unwrap();
+        assert_eq!((hit.block, hit.normal), (IVec3::new(4, 2, 0), IVec3::new(-1, 0, 0)));
+        assert!((hit.distance - 3.5 * 1.25f32.sqrt()).abs() < 1e-4);
+
+        // Rays stop at chunks which are not loaded, and the starting block is skipped
+        assert_eq!(raycast(&world, &Ray::new(Vector3::new(0.5, 2.5, 2.5), Vector3::new(0.0, 1.0, 0.0)), 100.0, solid), None);
+        assert_eq!(raycast(&world, &Ray::new(Vector3::new(5.5, 2.5, 2.5), Vector3::new(1.0, 0.0, 0.0)), 10.0, solid), None);
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::anyhow;
+use hecs::World;
+use serde::Deserialize;
+use terre_core_macros::Resource;
+use crate::app::{App, Plugin};
+use crate::asset::server::AssetServer;
+use crate::ecs::resource::ResManager;
+use crate::schedule::Stage;
+use crate::voxel::block::{BlockId, Face};
+use crate::voxel::storage::DirChunkStore;
+
+/// Name of [`BlockId::AIR`], registered by every [`BlockRegistry`].
+pub const AIR_NAME: &str = "terre:air";
+/// Brightest [`BlockProperties::light_emission`].
+pub const MAX_LIGHT: u8 = 15;
+
+/// Which pass draws a block, and how it mixes with what is behind it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderLayer {
+    /// Not drawn at all, e.g. air.
+    Invisible,
+    #[default]
+    Opaque,
+    /// Fully transparent where the texture is, e.g. leaves.
+    Cutout,
+    /// Blended over what is behind, e.g. water and glass.
+    Translucent,
+}
+
+/// Everything the game knows about a type of block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockProperties {
+    /// Unique name like `terre:stone`.
+    pub name: String,
+    /// Whether entities collide with the block.
+    pub solid: bool,
+    /// Whether the block hides the faces of its neighbours and blocks light.
+    pub opaque: bool,
+    /// Whether light and sight pass through parts of the block.
+    pub transparent: bool,
+    /// Light level given off, from `0` to [`MAX_LIGHT`].
+    pub light_emission: u8,
+    /// Seconds to break by hand, negative for unbreakable.
+    pub hardness: f32,
+    /// Texture names by [`Face#index`](Face::index), `None` for faces drawn without one.
+    pub textures: [Option<String>; 6],
+    pub render_layer: RenderLayer,
+}
+
+impl BlockProperties {
+    /// A solid and opaque block without textures.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            solid: true,
+            opaque: true,
+            transparent: false,
+            light_emission: 0,
+            hardness: 1.0,
+            textures: Default::default(),
+            render_layer: RenderLayer::Opaque,
+        }
+    }
+
+    fn air() -> Self {
+        Self {
+            solid: false,
+            opaque: false,
+            transparent: true,
+            hardness: 0.0,
+            render_layer: RenderLayer::Invisible,
+            ..Self::new(AIR_NAME)
+        }
+    }
+
+    pub fn texture(&self, face: Face) -> Option<&str> {
+        self.textures[face.index()].as_deref()
+    }
+}
+
+/// Textures of a block in a definition file, the most specific one given wins for each face.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TextureDesc {
+    all: Option<String>,
+    /// All faces but top and bottom.
+    side: Option<String>,
+    top: Option<String>,
+    bottom: Option<String>,
+    pos_x: Option<String>,
+    neg_x: Option<String>,
+    pos_z: Option<String>,
+    neg_z: Option<String>,
+}
+
+impl TextureDesc {
+    fn resolve(self) -> [Option<String>; 6] {
+        let side = self.side.or(self.all.clone());
+        Face::ALL.map(|face| match face {
+            Face::PosX => self.pos_x.clone().or(side.clone()),
+            Face::NegX => self.neg_x.clone().or(side.clone()),
+            Face::PosZ => self.pos_z.clone().or(side.clone()),
+            Face::NegZ => self.neg_z.clone().or(side.clone()),
+            Face::PosY => self.top.clone().or(self.all.clone()),
+            Face::NegY => self.bottom.clone().or(self.all.clone()),
+        })
+    }
+}
+
+/// One block in a definition file, see [`BlockRegistry#load_json`](BlockRegistry::load_json).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockDesc {
+    id: String,
+    #[serde(default = "default_true")]
+    solid: bool,
+    #[serde(default = "default_true")]
+    opaque: bool,
+    #[serde(default)]
+    transparent: bool,
+    #[serde(default)]
+    light_emission: u8,
+    #[serde(default = "default_hardness")]
+    hardness: f32,
+    #[serde(default)]
+    textures: TextureDesc,
+    #[serde(default)]
+    render_layer: RenderLayer,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BlockFile {
+    blocks: Vec<BlockDesc>,
+}
+
+impl BlockDesc {
+    fn into_properties(self) -> anyhow::Result<BlockProperties> {
+        if self.light_emission > MAX_LIGHT {
+            anyhow::bail!("Light emission of '{}' is above {}", self.id, MAX_LIGHT);
+        }
+        Ok(BlockProperties {
+            name: self.id,
+            solid: self.solid,
+            opaque: self.opaque,
+            transparent: self.transparent,
+            light_emission: self.light_emission,
+            hardness: self.hardness,
+            textures: self.textures.resolve(),
+            render_layer: self.render_layer,
+        })
+    }
+}
+
+/// All types of blocks, giving each name a compact [`BlockId`], as a resource.
+/// # Usage
+/// Register blocks in code with [`#register`](BlockRegistry::register) or from a file with
+/// [`#load_json`](BlockRegistry::load_json), then [`#freeze`](BlockRegistry::freeze) the registry.
+/// Store [`#id_table`](BlockRegistry::id_table) with a save, and hand it to
+/// [`#reserve_ids`](BlockRegistry::reserve_ids) before registering when loading it,
+/// so blocks keep their ids even if definitions were added or reordered in between.
+/// # Definition file
+/// ```json
+/// { "blocks": [
+///     { "id": "terre:stone", "hardness": 1.5, "textures": { "all": "stone" } },
+///     { "id": "terre:glass", "opaque": false, "transparent": true, "render_layer": "cutout", "textures": { "all": "glass" } }
+/// ] }
+/// ```
+/// Omitted properties default to a solid opaque block, see [`BlockProperties::new`].
+#[derive(Resource)]
+pub struct BlockRegistry {
+    blocks: Vec<BlockProperties>,
+    ids: HashMap<String, BlockId>,
+    /// Ids reserved for a saved name that was not registered yet.
+    undefined: Vec<BlockId>,
+    frozen: bool,
+}
+
+impl BlockRegistry {
+    /// A registry with only air, at [`BlockId::AIR`].
+    pub fn new() -> Self {
+        let mut registry = Self {
+            blocks: vec![],
+            ids: HashMap::new(),
+            undefined: vec![],
+            frozen: false,
+        };
+        registry.push(BlockProperties::air()).unwrap();
+        registry
+    }
+
+    fn push(&mut self, properties: BlockProperties) -> anyhow::Result<BlockId> {
+        let id = u16::try_from(self.blocks.len()).map(BlockId)
+            .map_err(|_| anyhow!("Too many blocks to register '{}'", properties.name))?;
+        self.ids.insert(properties.name.clone(), id);
+        self.blocks.push(properties);
+        Ok(id)
+    }
+
+    /// Give the names of a saved [`#id_table`](BlockRegistry::id_table) their saved ids,
+    /// their properties are filled in as they are registered.
+    pub fn reserve_ids(&mut self, table: &[String]) -> anyhow::Result<()> {
+        if self.frozen || self.blocks.len() > 1 {
+            anyhow::bail!("Block ids can only be reserved before registering blocks");
+        }
+        match table.first() {
+            Some(name) if name == AIR_NAME => {}
+            _ => anyhow::bail!("Saved block ids do not start with '{}'", AIR_NAME),
+        }
+        for name in table.iter().skip(1) {
+            if self.ids.contains_key(name) {
+                anyhow::bail!("Block '{}' is saved twice", name);
+            }
+            let id = self.push(BlockProperties::new(name))?;
+            self.undefined.push(id);
+        }
+        Ok(())
+    }
+
+    /// Add a block, keeping its reserved id if it has one.
+    pub fn register(&mut self, properties: BlockProperties) -> anyhow::Result<BlockId> {
+        if self.frozen {
+            anyhow::bail!("Can not register '{}', the block registry is frozen", properties.name);
+        }
+        match self.ids.get(&properties.name).copied() {
+            Some(id) if self.undefined.contains(&id) => {
+                self.undefined.retain(|it| *it != id);
+                self.blocks[id.0 as usize] = properties;
+                Ok(id)
+            }
+            Some(_) => Err(anyhow!("Block '{}' is already registered", properties.name)),
+            None => self.push(properties),
+        }
+    }
+
+    /// Register every block of a definition file, in order, or none of them if one is invalid.
+    pub fn load_json(&mut self, json: &str) -> anyhow::Result<Vec<BlockId>> {
+        let file: BlockFile = serde_json::from_str(json)?;
+        let blocks = file.blocks.into_iter()
+            .map(|it| it.into_properties())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut names = HashSet::new();
+        for block in &blocks {
+            let reserved = self.ids.get(&block.name).is_some_and(|it| self.undefined.contains(it));
+            if !names.insert(&block.name) || (self.ids.contains_key(&block.name) && !reserved) {
+                anyhow::bail!("Block '{}' is already registered", block.name);
+            }
+        }
+        if self.blocks.len() + blocks.len() > u16::MAX as usize + 1 {
+            anyhow::bail!("Too many blocks to register");
+        }
+        blocks.into_iter().map(|it| self.register(it)).collect()
+    }
+
+    /// Stop registering, so ids stay what they are until the game exits.
+    /// Reserved blocks that were never registered stay as textureless solid blocks, and are logged.
+    pub fn freeze(&mut self) {
+        for id in self.undefined.iter() {
+            log::warn!("Saved block '{}' is not defined anymore", self.blocks[id.0 as usize].name);
+        }
+        self.frozen = true;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&BlockProperties> {
+        self.blocks.get(id.0 as usize)
+    }
+
+    /// Properties of `id`, falling back to air for ids not registered, e.g. from a corrupted save.
+    pub fn properties(&self, id: BlockId) -> &BlockProperties {
+        self.get(id).unwrap_or(&self.blocks[0])
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockProperties)> {
+        self.blocks.iter().enumerate().map(|(i, it)| (BlockId(i as u16), it))
+    }
+
+    /// Names of all blocks by id, to store with a save.
+    pub fn id_table(&self) -> Vec<String> {
+        self.blocks.iter().map(|it| it.name.clone()).collect()
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Default definition file of [`BlockRegistryPlugin`], under the asset root.
+pub const BLOCKS_PATH: &str = "blocks.json";
+
+/// Adds a [`BlockRegistry`] loaded from a definition file through the [`AssetServer`] on start, then frozen.
+/// Needs [`AssetPlugin`](crate::asset::AssetPlugin).
+pub struct BlockRegistryPlugin {
+    pub path: String,
+    /// Id table of the save being played, empty for a new world.
+    pub saved_ids: Vec<String>,
+    /// Directory of a [`DirChunkStore`] to restore the id table from when `saved_ids` is empty,
+    /// the same as [`ChunkStreamingPlugin::save_dir`](crate::voxel::streaming::ChunkStreamingPlugin::save_dir).
+    pub save_dir: Option<String>,
+}
+
+impl Default for BlockRegistryPlugin {
+    fn default() -> Self {
+        Self { path: BLOCKS_PATH.to_string(), saved_ids: vec![], save_dir: None }
+    }
+}
+
+impl Plugin for BlockRegistryPlugin {
+    fn build(&self, app: App) -> App {
+        let mut registry = BlockRegistry::new();
+        let saved_ids = match (&self.save_dir, self.saved_ids.is_empty()) {
+            (Some(dir), true) => DirChunkStore::new(dir).and_then(|it| it.load_id_table()).unwrap_or_else(|err| {
+                log::error!("Failed to read saved block ids: {:?}", err);
+                None
+            }),
+            _ => Some(self.saved_ids.clone()).filter(|it| !it.is_empty()),
+        };
+        if let Some(saved_ids) = saved_ids {
+            if let Err(err) = registry.reserve_ids(&saved_ids) {
+                log::error!("Failed to restore saved block ids: {}", err);
+            }
+        }
+        let path = self.path.clone();
+        app.add_resource(registry)
+            .add_system(Stage::Start, move |_: &mut World, res_manager: &mut ResManager| load_blocks(&path, res_manager))
+    }
+}
+
+fn load_blocks(path: &str, res_manager: &ResManager) {
+    let (Some(server), Some(mut registry)) = (res_manager.borrow_res::<AssetServer>(), res_manager.borrow_res_mut::<BlockRegistry>()) else {
+        log::error!("Failed to load blocks from '{}', there is no asset server", path);
+        return;
+    };
+    let result = server.io().read_to_string(path).and_then(|it| registry.load_json(&it));
+    match result {
+        Ok(ids) => log::info!("Registered {} blocks from '{}'", ids.len(), path),
+        Err(err) => log::error!("Failed to load blocks from '{}': {}", path, err),
+    }
+    registry.freeze();
+}
+
+#[cfg(test)]
+mod test {
+    use crate::voxel::block::{BlockId, Face};
+    use crate::voxel::registry::{AIR_NAME, BlockProperties, BlockRegistry, RenderLayer};
+
+    const BLOCKS: &str = r#"{ "blocks": [
+        { "id": "terre:stone", "hardness": 1.5, "textures": { "all": "stone" } },
+        { "id": "terre:grass", "textures": { "all": "dirt", "side": "grass_side", "top": "grass" } },
+        { "id": "terre:glass", "opaque": false, "transparent": true, "render_layer": "cutout" }
+    ] }"#;
+
+    #[test]
+    fn test_load_blocks() {
+        let mut registry = BlockRegistry::new();
+        let ids = registry.load_json(BLOCKS).unwrap();
+        assert_eq!(ids, vec![BlockId(1), BlockId(2), BlockId(3)]);
+        assert!(!registry.properties(BlockId::AIR).solid);
+
+        let grass = registry.properties(registry.id("terre:grass").unwrap());
+        assert_eq!(grass.texture(Face::PosY), Some("grass"));
+        assert_eq!(grass.texture(Face::NegY), Some("dirt"));
+        assert_eq!(grass.texture(Face::NegZ), Some("grass_side"));
+        let glass = registry.properties(BlockId(3));
+        assert_eq!((glass.opaque, glass.solid, glass.render_layer), (false, true, RenderLayer::Cutout));
+
+        assert!(registry.load_json(r#"{ "blocks": [{ "id": "terre:stone" }] }"#).is_err());
+        assert!(registry.load_json(r#"{ "blocks": [{ "id": "terre:lamp", "light_emission": 16 }] }"#).is_err());
+        // Files with an invalid block register none of them
+        assert!(registry.load_json(r#"{ "blocks": [{ "id": "terre:dirt" }, { "id": "terre:dirt" }] }"#).is_err());
+        assert!(registry.load_json(r#"{ "blocks": [{ "id": "terre:sand" }, { "id": "terre:torch", "light_emission": 16 }] }"#).is_err());
+        assert_eq!((registry.id("terre:dirt"), registry.id("terre:sand"), registry.len()), (None, None, 4));
+        registry.freeze();
+        assert!(registry.register(BlockProperties::new("terre:dirt")).is_err());
+    }
+
+    #[test]
+    fn test_saved_ids_are_kept() {
+        let saved = vec![AIR_NAME.to_string(), "terre:glass".to_string(), "terre:removed".to_string(), "terre:stone".to_string()];
+        let mut registry = BlockRegistry::new();
+        registry.reserve_ids(&saved).unwrap();
+        registry.load_json(BLOCKS).unwrap();
+        registry.freeze();
+
+        assert_eq!(registry.id("terre:glass"), Some(BlockId(1)));
+        assert_eq!(registry.id("terre:stone"), Some(BlockId(3)));
+        assert_eq!(registry.properties(BlockId(3)).hardness, 1.5);
+        // New blocks come after the saved ones
+        assert_eq!(registry.id("terre:grass"), Some(BlockId(4)));
+        assert_eq!(registry.id_table()[..4], saved[..]);
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use anyhow::{bail, Context};
+use crate::voxel::block::BlockId;
+use crate::voxel::chunk::{Chunk, CHUNK_VOLUME, ChunkPos, local_position};
+
+/// First bytes of an encoded chunk, with the version of the format.
+const CHUNK_MAGIC: &[u8; 4] = b"TRC1";
+
+/// File of [`DirChunkStore`] with the id table of the blocks, one name per line.
+pub const ID_TABLE_FILE: &str = "blocks.ids";
+
+/// Where modified chunks are kept while unloaded, e.g. by [`ChunkManager`](crate::voxel::streaming::ChunkManager).
+/// # Explanation
+/// Chunks store [`BlockId`]s, which only keep their meaning with the
+/// [`id_table`](crate::voxel::registry::BlockRegistry::id_table) of the registry they were saved with.
+pub trait ChunkStore: Send + Sync {
+    /// The chunk saved at `pos`, `None` if there is none.
+    fn load(&self, pos: ChunkPos) -> anyhow::Result<Option<Chunk>>;
+
+    fn save(&self, pos: ChunkPos, chunk: &Chunk) -> anyhow::Result<()>;
+}
+
+/// [`ChunkStore`] in memory, lost when the game exits.
+#[derive(Default)]
+pub struct MemoryChunkStore {
+    chunks: Mutex<HashMap<ChunkPos, Chunk>>,
+}
+
+impl MemoryChunkStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ChunkStore for MemoryChunkStore {
+    fn load(&self, pos: ChunkPos) -> anyhow::Result<Option<Chunk>> {
+        Ok(self.chunks.lock().unwrap().get(&pos).cloned())
+    }
+
+    fn save(&self, pos: ChunkPos, chunk: &Chunk) -> anyhow::Result<()> {
+        self.chunks.lock().unwrap().insert(pos, chunk.clone());
+        Ok(())
+    }
+}
+
+/// [`ChunkStore`] with a file per chunk in a directory, named after the chunk position, e.g. `1_-2_3.chunk`,
+/// and the [`id_table`](crate::voxel::registry::BlockRegistry::id_table) the chunks were saved with in [`ID_TABLE_FILE`].
+/// # Usage
+/// Hand the saved id table to [`BlockRegistry::reserve_ids`](crate::voxel::registry::BlockRegistry::reserve_ids)
+/// before registering blocks, as [`BlockRegistryPlugin::save_dir`](crate::voxel::registry::BlockRegistryPlugin::save_dir) does,
+/// then save the id table of the frozen registry.
+pub struct DirChunkStore {
+    dir: PathBuf,
+}
+
+impl DirChunkStore {
+    /// Store chunks in `dir`, which is created if needed.
+    pub fn new(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create chunk directory {:?}", dir))?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, pos: ChunkPos) -> PathBuf {
+        self.dir.join(format!("{}_{}_{}.chunk", pos.x, pos.y, pos.z))
+    }
+
+    /// The id table chunks were saved with, `None` if nothing was saved yet.
+    pub fn load_id_table(&self) -> anyhow::Result<Option<Vec<String>>> {
+        let path = self.dir.join(ID_TABLE_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Ok(Some(text.lines().map(|it| it.to_string()).collect())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("Failed to read block ids {:?}", path)),
+        }
+    }
+
+    pub fn save_id_table(&self, table: &[String]) -> anyhow::Result<()> {
+        let path = self.dir.join(ID_TABLE_FILE);
+        let text = table.iter().map(|it| format!("{}\n", it)).collect::<String>();
+        std::fs::write(&path, text).with_context(|| format!("Failed to write block ids {:?}", path))
+    }
+}
+
+impl ChunkStore for DirChunkStore {
+    fn load(&self, pos: ChunkPos) -> anyhow::Result<Option<Chunk>> {
+        let path = self.path(pos);
+        match std::fs::read(&path) {
+            Ok(bytes) => decode_chunk(&bytes).map(Some).with_context(|| format!("Invalid chunk file {:?}", path)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("Failed to read chunk file {:?}", path)),
+        }
+    }
+
+    fn save(&self, pos: ChunkPos, chunk: &Chunk) -> anyhow::Result<()> {
+        let path = self.path(pos);
+        std::fs::write(&path, encode_chunk(chunk)).with_context(|| format!("Failed to write chunk file {:?}", path))
+    }
+}
+
+/// Runs of blocks in storage order, each a little endian `u32` length followed by a `u16` id, after [`CHUNK_MAGIC`].
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let mut bytes = CHUNK_MAGIC.to_vec();
+    let mut run: Option<(BlockId, u32)> = None;
+    for index in 0..CHUNK_VOLUME {
+        let block = chunk.blocks().get(index);
+        run = match run {
+            Some((current, len)) if current == block => Some((current, len + 1)),
+            Some((current, len)) => {
+                bytes.extend(len.to_le_bytes());
+                bytes.extend(current.0.to_le_bytes());
+                Some((block, 1))
+            }
+            None => Some((block, 1)),
+        };
+    }
+    if let Some((current, len)) = run {
+        bytes.extend(len.to_le_bytes());
+        bytes.extend(current.0.to_le_bytes());
+    }
+    bytes
+}
+
+pub fn decode_chunk(bytes: &[u8]) -> anyhow::Result<Chunk> {
+    let Some(runs) = bytes.strip_prefix(CHUNK_MAGIC) else {
+        bail!("Not a chunk, or a chunk of another version");
+    };
+    if runs.len() % 6 != 0 {
+        bail!("Truncated chunk of {} bytes", bytes.len());
+    }
+    let mut chunk = Chunk::new();
+    let mut index = 0;
+    for run in runs.chunks_exact(6) {
+        let len = u32::from_le_bytes([run[0], run[1], run[2], run[3]]) as usize;
+        let block = BlockId(u16::from_le_bytes([run[4], run[5]]));
+        if index + len > CHUNK_VOLUME {
+            bail!("Chunk has more than {} blocks", CHUNK_VOLUME);
+        }
+        if !block.is_air() {
+            (index..index + len).for_each(|it| { chunk.set(local_position(it), block); });
+        }
+        index += len;
+    }
+    if index != CHUNK_VOLUME {
+        bail!("Chunk has {} blocks instead of {}", index, CHUNK_VOLUME);
+    }
+    chunk.compact();
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::voxel::block::BlockId;
+    use crate::voxel::chunk::{Chunk, CHUNK_VOLUME, ChunkPos};
+    use crate::voxel::IVec3;
+    use crate::voxel::storage::{ChunkStore, decode_chunk, DirChunkStore, encode_chunk};
+
+    #[test]
+    fn test_chunk_files() {
+        let mut chunk = Chunk::filled(BlockId(1));
+        chunk.set(IVec3::new(0, 0, 0), BlockId::AIR);
+        chunk.set(IVec3::new(5, 31, 7), BlockId(300));
+        let bytes = encode_chunk(&chunk);
+        // Runs of air, stone, the other block and stone again
+        assert_eq!(bytes.len(), 4 + 6 * 4);
+        let decoded = decode_chunk(&bytes).unwrap();
+        assert!((0..CHUNK_VOLUME).all(|it| decoded.blocks().get(it) == chunk.blocks().get(it)));
+        assert!(decode_chunk(&bytes[..bytes.len() - 6]).is_err());
+        assert!(decode_chunk(b"TRC0").is_err());
+
+        let dir = std::env::temp_dir().join(format!("terre_chunks_{}", std::process::id()));
+        let store = DirChunkStore::new(&dir).unwrap();
+        let pos = ChunkPos::new(1, -2, 3);
+        assert!(store.load(pos).unwrap().is_none());
+        store.save(pos, &chunk).unwrap();
+        assert_eq!(store.load(pos).unwrap().unwrap().get(IVec3::new(5, 31, 7)), BlockId(300));
+
+        assert_eq!(store.load_id_table().unwrap(), None);
+        let table = vec!["terre:air".to_string(), "terre:stone".to_string()];
+        store.save_id_table(&table).unwrap();
+        assert_eq!(DirChunkStore::new(&dir).unwrap().load_id_table().unwrap(), Some(table));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,761 @@
+use std::collections::{HashMap, VecDeque};
+use std::mem::size_of;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use cgmath::{InnerSpace, Vector3, Zero};
+use hecs::{Entity, World};
+use terre_core_macros::Resource;
+use crate::app::{App, Plugin};
+use crate::asset::server::AssetServer;
+use crate::ecs::removal::Removals;
+use crate::ecs::resource::ResManager;
+use crate::render::camera::{active_camera, Camera};
+use crate::render::RenderDevice;
+use crate::schedule::Stage;
+use crate::task::TaskPool;
+use crate::transform::{GlobalTransform, Transform};
+use crate::voxel::chunk::{Chunk, CHUNK_SIZE, ChunkPos};
+use crate::voxel::decoration::{BlockWrite, Decorator, SpilledWrites};
+use crate::voxel::IVec3;
+use crate::voxel::mesher::{ChunkMesh, ChunkMeshData, ChunkNeighborhood, mesh_chunk, MeshMode, MeshTable, VoxelVertex};
+use crate::voxel::registry::BlockRegistry;
+use crate::voxel::storage::{ChunkStore, DirChunkStore, MemoryChunkStore};
+use crate::voxel::terrain::{NoiseTerrain, TerrainBlocks, TerrainConfig, TerrainGenerator};
+use crate::voxel::textures::BlockTextures;
+use crate::voxel::world::VoxelWorld;
+
+/// Where a chunk tracked by [`ChunkManager`] is on its way to being drawn.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ChunkState {
+    /// Waiting for a worker to load or generate it.
+    Generating,
+    /// In the [`VoxelWorld`], waiting for its neighbors before it is meshed.
+    Loaded,
+    /// Being meshed, or waiting for its mesh to be uploaded; a previous mesh is drawn meanwhile.
+    Meshing,
+    /// Drawn with an up to date mesh.
+    Ready,
+}
+
+/// How far [`ChunkManager`] loads chunks, and how much work it does per frame.
+#[derive(Clone, Debug)]
+pub struct StreamingConfig {
+    /// Chunks within this many chunks around the viewer horizontally are loaded.
+    pub horizontal_radius: i32,
+    /// Chunks within this many chunks above and below the viewer are loaded.
+    pub vertical_radius: i32,
+    /// Generation and meshing jobs queued on the workers at once,
+    /// small enough that jobs are picked again as the viewer moves.
+    pub max_jobs: usize,
+    pub max_uploads_per_frame: usize,
+    /// Vertex and index bytes uploaded per frame, at least one mesh is uploaded per frame whatever its size.
+    pub max_upload_bytes_per_frame: usize,
+    pub mesh_mode: MeshMode,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            horizontal_radius: 8,
+            vertical_radius: 4,
+            max_jobs: 16,
+            max_uploads_per_frame: 8,
+            max_upload_bytes_per_frame: 4 << 20,
+            mesh_mode: MeshMode::Greedy,
+        }
+    }
+}
+
+/// Where chunks are streamed around, and which way it looks.
+#[derive(Copy, Clone, Debug)]
+pub struct Viewer {
+    pub position: Vector3<f32>,
+    /// Normalized, or zero to prioritize by distance only.
+    pub forward: Vector3<f32>,
+}
+
+impl Viewer {
+    pub fn new(position: Vector3<f32>, forward: Vector3<f32>) -> Self {
+        let forward = if forward.magnitude2() > f32::EPSILON { forward.normalize() } else { Vector3::zero() };
+        Self { position, forward }
+    }
+
+    /// The [`active_camera`], or the [`Camera`] resource without one.
+    pub fn find(world: &World, res_manager: &ResManager) -> Option<Self> {
+        if let Some(transform) = active_camera(world).and_then(|it| world.get::<&Transform>(it).ok()) {
+            return Some(Self::new(transform.position, transform.forward()));
+        }
+        let camera = res_manager.borrow_res::<Camera>()?;
+        Some(Self::new(Vector3::new(camera.eye.x, camera.eye.y, camera.eye.z), camera.forward()))
+    }
+
+    pub fn chunk(&self) -> ChunkPos {
+        ChunkPos::of_block(self.position.map(|it| it.floor() as i32))
+    }
+
+    /// Order in which work on the chunk at `pos` is scheduled, lowest first:
+    /// its distance, halved straight ahead of the viewer and half as long again straight behind.
+    pub fn priority(&self, pos: ChunkPos) -> f32 {
+        let center = (pos.origin() + IVec3::new(1, 1, 1) * (CHUNK_SIZE / 2)).cast::<f32>().unwrap();
+        let offset = center - self.position;
+        let distance = offset.magnitude();
+        if distance < f32::EPSILON {
+            return 0.0;
+        }
+        distance * (1.0 - 0.5 * offset.dot(self.forward) / distance)
+    }
+}
+
+enum JobResult {
+    /// `spills` are the writes of its features to other chunks, also for chunks from the store.
+    Generated { pos: ChunkPos, chunk: Chunk, stored: bool, spills: Vec<BlockWrite> },
+    Meshed { pos: ChunkPos, version: u32, mesh: ChunkMeshData },
+    /// The job panicked, reported so the job count stays right.
+    Failed { pos: ChunkPos },
+}
+
+struct ChunkEntry {
+    state: ChunkState,
+    /// Bumped on every change of the blocks, meshes of older versions are dropped.
+    version: u32,
+    /// Whether the chunk changed since it was last meshed.
+    dirty: bool,
+    /// Whether a mesh job of the chunk is running.
+    meshing: bool,
+    /// Whether the chunk was edited since it was last meshed, so it is meshed on the main thread
+    /// and uploaded regardless of the budgets, showing the edit the next frame.
+    urgent: bool,
+    /// Whether the chunk differs from what the generator and store would give, so it is saved when unloaded.
+    modified: bool,
+    /// Whether the chunk came from the store or was modified, so it keeps its blocks
+    /// instead of getting the writes of features of neighbors generated after it.
+    persistent: bool,
+    entity: Option<Entity>,
+}
+
+/// Keeps the chunks around a [`Viewer`] loaded in the [`VoxelWorld`] and meshed, as a resource.
+/// # Usage
+/// Added by [`ChunkStreamingPlugin`]. After changing blocks of a loaded chunk,
+/// call [`#mark_modified`](ChunkManager::mark_modified) so it is remeshed and saved when unloaded,
+/// and [`#remesh`](ChunkManager::remesh) for the neighbors whose meshes touch the changed blocks,
+/// see [`BlockEditPlugin`](crate::voxel::edit::BlockEditPlugin).
+/// [`#state`](ChunkManager::state) tells how far each chunk got, for debugging.
+/// # Explanation
+/// Each frame, chunks within the radii of [`StreamingConfig`] which are not loaded yet get a generation job,
+/// and loaded chunks whose 26 neighbors are all loaded get a mesh job, so the outermost chunks are only loaded
+/// to mesh the faces and ambient occlusion at the border of the inner ones.
+/// Jobs run on a [`TaskPool`], at most [`max_jobs`](StreamingConfig::max_jobs) at once,
+/// the next ones picked by [`Viewer::priority`] when a job finishes.
+///
+/// Chunks more than a chunk outside of the radii are unloaded, modified ones saved to the [`ChunkStore`],
+/// which generation jobs read before generating; [`#save_all`](ChunkManager::save_all) saves the loaded ones.
+/// Finished meshes are uploaded in the order they finish, within the budgets of [`StreamingConfig`].
+/// Edited chunks skip the workers and budgets instead, so edits show up the frame after them.
+///
+/// Writes of features to neighboring chunks are kept in [`SpilledWrites`] while the source or the target is loaded,
+/// so a chunk gets them again when it is regenerated, the same whichever chunk was generated first.
+/// Chunks from the store are decorated again for the writes to their neighbors, but keep their own blocks.
+#[derive(Resource)]
+pub struct ChunkManager {
+    config: StreamingConfig,
+    generator: Arc<dyn TerrainGenerator>,
+    decorator: Arc<Decorator>,
+    table: Arc<MeshTable>,
+    store: Arc<dyn ChunkStore>,
+    pool: TaskPool,
+    sender: Sender<JobResult>,
+    receiver: Receiver<JobResult>,
+    chunks: HashMap<ChunkPos, ChunkEntry>,
+    spills: SpilledWrites,
+    uploads: VecDeque<(ChunkPos, u32, ChunkMeshData)>,
+    /// Meshes of edited chunks, uploaded before and regardless of the others.
+    urgent_uploads: Vec<(ChunkPos, u32, ChunkMeshData)>,
+    jobs: usize,
+}
+
+impl ChunkManager {
+    pub fn new(
+        config: StreamingConfig,
+        generator: Arc<dyn TerrainGenerator>,
+        decorator: Decorator,
+        table: MeshTable,
+        store: Arc<dyn ChunkStore>,
+    ) -> Self {
+        Self::with_pool(config, generator, decorator, table, store, TaskPool::with_default_threads("Chunk Worker"))
+    }
+
+    pub fn with_pool(
+        config: StreamingConfig,
+        generator: Arc<dyn TerrainGenerator>,
+        decorator: Decorator,
+        table: MeshTable,
+        store: Arc<dyn ChunkStore>,
+        pool: TaskPool,
+    ) -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            config,
+            generator,
+            decorator: Arc::new(decorator),
+            table: Arc::new(table),
+            store,
+            pool,
+            sender,
+            receiver,
+            chunks: HashMap::new(),
+            spills: SpilledWrites::new(),
+            uploads: VecDeque::new(),
+            urgent_uploads: vec![],
+            jobs: 0,
+        }
+    }
+
+    pub fn config(&self) -> &StreamingConfig {
+        &self.config
+    }
+
+    /// `None` for chunks which are not tracked, i.e. out of range.
+    pub fn state(&self, pos: ChunkPos) -> Option<ChunkState> {
+        self.chunks.get(&pos).map(|it| it.state)
+    }
+
+    pub fn states(&self) -> impl Iterator<Item = (ChunkPos, ChunkState)> + '_ {
+        self.chunks.iter().map(|(pos, entry)| (*pos, entry.state))
+    }
+
+    /// Jobs queued or running on the workers.
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Remesh the loaded chunk at `pos` during the next update, and save it when it is unloaded.
+    /// Returns whether it is loaded.
+    pub fn mark_modified(&mut self, pos: ChunkPos) -> bool {
+        match self.chunks.get_mut(&pos) {
+            Some(entry) if entry.state != ChunkState::Generating => {
+                entry.modified = true;
+                entry.persistent = true;
+                self.mark_dirty(pos, true);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remesh the loaded chunk at `pos` during the next update, e.g. after a block on its border changed in a neighbor.
+    /// Returns whether it is loaded.
+    pub fn remesh(&mut self, pos: ChunkPos) -> bool {
+        match self.chunks.get(&pos) {
+            Some(entry) if entry.state != ChunkState::Generating => {
+                self.mark_dirty(pos, true);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Mesh chunks with `table` from now on, e.g. after the block textures were reloaded,
+    /// and remesh every loaded chunk within the upload budgets.
+    pub fn set_mesh_table(&mut self, table: MeshTable) {
+        self.table = Arc::new(table);
+        let loaded = self.chunks.iter()
+            .filter(|(_, entry)| entry.state != ChunkState::Generating)
+            .map(|(pos, _)| *pos)
+            .collect::<Vec<_>>();
+        loaded.into_iter().for_each(|pos| self.mark_dirty(pos, false));
+    }
+
+    fn mark_dirty(&mut self, pos: ChunkPos, urgent: bool) {
+        if let Some(entry) = self.chunks.get_mut(&pos) {
+            entry.version = entry.version.wrapping_add(1);
+            entry.dirty = true;
+            entry.urgent |= urgent;
+        }
+    }
+
+    /// Collect finished jobs, unload chunks out of range and schedule new jobs around `viewer`.
+    pub fn update(&mut self, world: &mut World, removals: &mut Removals, voxels: &mut VoxelWorld, viewer: &Viewer) {
+        while let Ok(result) = self.receiver.try_recv() {
+            self.jobs -= 1;
+            match result {
+                JobResult::Generated { pos, chunk, stored, spills } => self.finish_generation(voxels, pos, chunk, stored, spills),
+                JobResult::Meshed { pos, version, mesh } => {
+                    let Some(entry) = self.chunks.get_mut(&pos) else { continue };
+                    entry.meshing = false;
+                    // Changed while meshing, it stays dirty and is meshed again
+                    if entry.version == version {
+                        self.uploads.push_back((pos, version, mesh));
+                    }
+                }
+                JobResult::Failed { pos } => self.fail_job(voxels, pos),
+            }
+        }
+        self.unload(world, removals, voxels, viewer.chunk());
+        self.mesh_urgent(voxels);
+        self.schedule(voxels, viewer);
+    }
+
+    /// Mesh edited chunks right away, a few chunks per edit are quicker than waiting for the workers.
+    fn mesh_urgent(&mut self, voxels: &VoxelWorld) {
+        let urgent = self.chunks.iter()
+            .filter(|(pos, entry)| entry.urgent && entry.state != ChunkState::Generating && neighbors_loaded(voxels, **pos))
+            .map(|(pos, _)| *pos)
+            .collect::<Vec<_>>();
+        for pos in urgent {
+            let Some(chunk) = voxels.chunk(pos) else { continue };
+            let neighborhood = neighbor_offsets()
+                .filter_map(|offset| voxels.chunk(offset_pos(pos, offset)).map(|it| (offset, it)))
+                .fold(ChunkNeighborhood::new(chunk), |it, (offset, neighbor)| it.with_neighbor_at(offset, neighbor));
+            let mesh = mesh_chunk(&neighborhood, &self.table, self.config.mesh_mode);
+            let entry = self.chunks.get_mut(&pos).unwrap();
+            entry.urgent = false;
+            entry.dirty = false;
+            entry.state = ChunkState::Meshing;
+            self.urgent_uploads.push((pos, entry.version, mesh));
+        }
+    }
+
+    fn finish_generation(&mut self, voxels: &mut VoxelWorld, pos: ChunkPos, mut chunk: Chunk, stored: bool, spills: Vec<BlockWrite>) {
+        // Unloaded while generating, or generated twice after unloading and coming back into range
+        if self.chunks.get(&pos).map(|it| it.state) != Some(ChunkState::Generating) {
+            return;
+        }
+        // Stored chunks already have the writes of their neighbors
+        if !stored {
+            self.spills.apply(pos, &mut chunk);
+        }
+        for target in self.spills.record(pos, spills) {
+            if self.chunks.get(&target).is_some_and(|it| it.persistent) {
+                continue;
+            }
+            if voxels.chunk_mut(target).is_some_and(|chunk| self.spills.apply(target, chunk)) {
+                self.mark_dirty(target, false);
+            }
+        }
+        voxels.insert_chunk(pos, chunk);
+        let entry = self.chunks.get_mut(&pos).unwrap();
+        entry.state = ChunkState::Loaded;
+        entry.persistent = stored;
+        self.mark_dirty(pos, false);
+    }
+
+    /// Leave a chunk whose generation panicked empty, and one whose meshing panicked without a mesh until it changes,
+    /// rather than panicking again every frame.
+    fn fail_job(&mut self, voxels: &mut VoxelWorld, pos: ChunkPos) {
+        let Some(entry) = self.chunks.get_mut(&pos) else { return };
+        match entry.state {
+            ChunkState::Generating => {
+                log::error!("Failed to generate chunk {:?}, it is left empty", pos);
+                self.finish_generation(voxels, pos, Chunk::new(), false, vec![]);
+            }
+            _ => {
+                log::error!("Failed to mesh chunk {:?}", pos);
+                entry.meshing = false;
+            }
+        }
+    }
+
+    /// Save the modified chunks which are loaded to the [`ChunkStore`], e.g. before the game exits,
+    /// returning how many were saved.
+    pub fn save_all(&mut self, voxels: &VoxelWorld) -> usize {
+        let mut saved = 0;
+        for (pos, entry) in self.chunks.iter_mut().filter(|(_, it)| it.modified) {
+            let Some(chunk) = voxels.chunk(*pos) else { continue };
+            match self.store.save(*pos, chunk) {
+                Ok(()) => {
+                    entry.modified = false;
+                    saved += 1;
+                }
+                Err(err) => log::error!("Failed to save chunk {:?}: {:?}", pos, err),
+            }
+        }
+        saved
+    }
+
+    fn in_range(&self, pos: ChunkPos, center: ChunkPos, margin: i32) -> bool {
+        let horizontal = self.config.horizontal_radius + margin;
+        let (dx, dz) = (pos.x - center.x, pos.z - center.z);
+        dx * dx + dz * dz <= horizontal * horizontal && (pos.y - center.y).abs() <= self.config.vertical_radius + margin
+    }
+
+    fn unload(&mut self, world: &mut World, removals: &mut Removals, voxels: &mut VoxelWorld, center: ChunkPos) {
+        let far = self.chunks.keys().filter(|it| !self.in_range(**it, center, 1)).copied().collect::<Vec<_>>();
+        for pos in far {
+            let entry = self.chunks.remove(&pos).unwrap();
+            if let Some(chunk) = voxels.remove_chunk(pos) {
+                if entry.modified {
+                    if let Err(err) = self.store.save(pos, &chunk) {
+                        log::error!("Failed to save chunk {:?}: {:?}", pos, err);
+                    }
+                }
+            }
+            if let Some(entity) = entry.entity {
+                let _ = removals.despawn(world, entity);
+            }
+        }
+        let chunks = &self.chunks;
+        self.spills.retain(|it| chunks.contains_key(&it));
+    }
+
+    fn schedule(&mut self, voxels: &VoxelWorld, viewer: &Viewer) {
+        let free = self.config.max_jobs.saturating_sub(self.jobs);
+        if free == 0 {
+            return;
+        }
+        let center = viewer.chunk();
+        let (horizontal, vertical) = (self.config.horizontal_radius, self.config.vertical_radius);
+        let mut candidates = vec![];
+        for y in -vertical..=vertical {
+            for z in -horizontal..=horizontal {
+                for x in -horizontal..=horizontal {
+                    let pos = ChunkPos::new(center.x + x, center.y + y, center.z + z);
+                    if self.in_range(pos, center, 0) && !self.chunks.contains_key(&pos) {
+                        candidates.push((viewer.priority(pos), pos, true));
+                    }
+                }
+            }
+        }
+        for (pos, entry) in &self.chunks {
+            if entry.dirty && !entry.meshing && entry.state != ChunkState::Generating && neighbors_loaded(voxels, *pos) {
+                candidates.push((viewer.priority(*pos), *pos, false));
+            }
+        }
+        candidates.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        for (_, pos, generate) in candidates.into_iter().take(free) {
+            match generate {
+                true => self.spawn_generation(pos),
+                false => self.spawn_meshing(voxels, pos),
+            }
+        }
+    }
+
+    fn spawn_generation(&mut self, pos: ChunkPos) {
+        self.chunks.insert(pos, ChunkEntry {
+            state: ChunkState::Generating,
+            version: 0,
+            dirty: false,
+            meshing: false,
+            urgent: false,
+            modified: false,
+            persistent: false,
+            entity: None,
+        });
+        let (generator, decorator, store, sender) = (self.generator.clone(), self.decorator.clone(), self.store.clone(), self.sender.clone());
+        self.jobs += 1;
+        self.pool.spawn(move || {
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                // Features of stored chunks still reach their neighbors, which may not be stored
+                let (generated, spills) = decorator.generate(pos, generator.as_ref());
+                let stored = store.load(pos).unwrap_or_else(|err| {
+                    log::error!("Failed to load chunk {:?}, generating it instead: {:?}", pos, err);
+                    None
+                });
+                match stored {
+                    Some(chunk) => JobResult::Generated { pos, chunk, stored: true, spills },
+                    None => JobResult::Generated { pos, chunk: generated, stored: false, spills },
+                }
+            }));
+            let _ = sender.send(result.unwrap_or(JobResult::Failed { pos }));
+        });
+    }
+
+    fn spawn_meshing(&mut self, voxels: &VoxelWorld, pos: ChunkPos) {
+        let entry = self.chunks.get_mut(&pos).unwrap();
+        entry.dirty = false;
+        entry.meshing = true;
+        entry.state = ChunkState::Meshing;
+        let version = entry.version;
+        // Copies, so blocks can change while the worker meshes
+        let chunks = neighbor_offsets()
+            .chain([IVec3::zero()])
+            .filter_map(|offset| voxels.chunk(offset_pos(pos, offset)).map(|it| (offset, it.clone())))
+            .collect::<Vec<_>>();
+        let (table, mode, sender) = (self.table.clone(), self.config.mesh_mode, self.sender.clone());
+        self.jobs += 1;
+        self.pool.spawn(move || {
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                let (_, chunk) = chunks.iter().find(|(offset, _)| offset.is_zero()).unwrap();
+                let neighborhood = chunks.iter()
+                    .filter(|(offset, _)| !offset.is_zero())
+                    .fold(ChunkNeighborhood::new(chunk), |it, (offset, neighbor)| it.with_neighbor_at(*offset, neighbor));
+                JobResult::Meshed { pos, version, mesh: mesh_chunk(&neighborhood, &table, mode) }
+            }));
+            let _ = sender.send(result.unwrap_or(JobResult::Failed { pos }));
+        });
+    }
+
+    /// Turn finished meshes into [`ChunkMesh`] entities with `upload`, within the budgets of [`StreamingConfig`],
+    /// except for meshes of edited chunks which are all uploaded first.
+    pub fn upload_meshes(&mut self, world: &mut World, removals: &mut Removals, mut upload: impl FnMut(&ChunkMeshData) -> ChunkMesh) {
+        for (pos, version, data) in std::mem::take(&mut self.urgent_uploads) {
+            self.upload_mesh(world, removals, pos, version, &data, &mut upload);
+        }
+        let (mut count, mut bytes) = (0, 0);
+        while count < self.config.max_uploads_per_frame && (count == 0 || bytes < self.config.max_upload_bytes_per_frame) {
+            let Some((pos, version, data)) = self.uploads.pop_front() else { break };
+            if let Some(uploaded) = self.upload_mesh(world, removals, pos, version, &data, &mut upload) {
+                count += 1;
+                bytes += uploaded;
+            }
+        }
+    }
+
+    /// Returns the bytes uploaded, `None` if nothing was.
+    fn upload_mesh(
+        &mut self,
+        world: &mut World,
+        removals: &mut Removals,
+        pos: ChunkPos,
+        version: u32,
+        data: &ChunkMeshData,
+        upload: &mut impl FnMut(&ChunkMeshData) -> ChunkMesh,
+    ) -> Option<usize> {
+        let entry = self.chunks.get_mut(&pos).filter(|it| it.version == version)?;
+        entry.state = ChunkState::Ready;
+        if data.is_empty() {
+            if let Some(entity) = entry.entity.take() {
+                let _ = removals.despawn(world, entity);
+            }
+            return None;
+        }
+        let mesh = upload(data);
+        match entry.entity.filter(|it| world.contains(*it)) {
+            Some(entity) => { let _ = world.insert_one(entity, mesh); }
+            None => {
+                let transform = GlobalTransform::new(&Transform::from_position(pos.origin().cast::<f32>().unwrap()));
+                entry.entity = Some(world.spawn((mesh, transform)));
+            }
+        }
+        Some([&data.opaque, &data.cutout, &data.translucent].iter()
+            .map(|it| it.vertices.len() * size_of::<VoxelVertex>() + it.indices.len() * size_of::<u32>())
+            .sum())
+    }
+}
+
+/// Offsets of the 26 chunks around a chunk.
+fn neighbor_offsets() -> impl Iterator<Item = IVec3> {
+    (-1..=1).flat_map(|y| (-1..=1).flat_map(move |z| (-1..=1).map(move |x| IVec3::new(x, y, z))))
+        .filter(|it| !it.is_zero())
+}
+
+fn offset_pos(pos: ChunkPos, offset: IVec3) -> ChunkPos {
+    ChunkPos::new(pos.x + offset.x, pos.y + offset.y, pos.z + offset.z)
+}
+
+fn neighbors_loaded(voxels: &VoxelWorld, pos: ChunkPos) -> bool {
+    neighbor_offsets().all(|it| voxels.contains_chunk(offset_pos(pos, it)))
+}
+
+/// Streams a [`NoiseTerrain`] world, decorated by [`Decorator::standard`], around the active camera
+/// with a [`ChunkManager`], uploads its meshes in [`Stage::PostUpdate`] and saves it in [`Stage::Exit`].
+/// Needs [`AssetPlugin`](crate::asset::AssetPlugin), and to be added after
+/// [`BlockTexturesPlugin`](crate::voxel::textures::BlockTexturesPlugin); draw the chunks with
+/// [`VoxelPlugin`](crate::render::pass::voxel::VoxelPlugin).
+#[derive(Default)]
+pub struct ChunkStreamingPlugin {
+    pub config: StreamingConfig,
+    pub terrain: TerrainConfig,
+    /// Directory modified chunks are saved to, kept in memory if `None`.
+    /// Set [`BlockRegistryPlugin::save_dir`](crate::voxel::registry::BlockRegistryPlugin::save_dir) to the same directory,
+    /// so blocks keep the ids they were saved with.
+    pub save_dir: Option<String>,
+}
+
+impl Plugin for ChunkStreamingPlugin {
+    fn build(&self, app: App) -> App {
+        let (config, terrain, save_dir) = (self.config.clone(), self.terrain.clone(), self.save_dir.clone());
+        app.add_system(Stage::Start, move |_: &mut World, res_manager: &mut ResManager| {
+            match create_chunk_manager(&config, &terrain, save_dir.as_deref(), res_manager) {
+                Ok(manager) => res_manager.push_res(manager).unwrap(),
+                Err(err) => log::error!("Failed to start chunk streaming: {:?}", err),
+            }
+            if !res_manager.contains_res::<VoxelWorld>() {
+                res_manager.push_res(VoxelWorld::new()).unwrap();
+            }
+        })
+            .add_system(Stage::PostUpdate, update_chunk_streaming)
+            .add_system(Stage::Exit, save_chunks)
+    }
+}
+
+fn create_chunk_manager(
+    config: &StreamingConfig,
+    terrain: &TerrainConfig,
+    save_dir: Option<&str>,
+    res_manager: &ResManager,
+) -> anyhow::Result<ChunkManager> {
+    let (Some(registry), Some(textures), Some(server)) = (
+        res_manager.borrow_res::<BlockRegistry>(),
+        res_manager.borrow_res::<BlockTextures>(),
+        res_manager.borrow_res::<AssetServer>(),
+    ) else {
+        anyhow::bail!("There is no block registry, block textures or asset server");
+    };
+    let generator = NoiseTerrain::new(terrain.clone(), TerrainBlocks::from_registry(&registry)?);
+    let decorator = Decorator::standard(terrain.seed, &registry, server.io()).unwrap_or_else(|err| {
+        log::error!("Failed to set up terrain features, the terrain is left bare: {:?}", err);
+        Decorator::new(terrain.seed)
+    });
+    let store: Arc<dyn ChunkStore> = match save_dir {
+        Some(dir) => {
+            let store = DirChunkStore::new(dir)?;
+            let ids = registry.id_table();
+            match store.load_id_table()? {
+                Some(saved) if !ids.starts_with(&saved) => anyhow::bail!(
+                    "Chunks in '{}' were saved with other block ids, restore them with BlockRegistryPlugin::save_dir", dir
+                ),
+                _ => store.save_id_table(&ids)?,
+            }
+            Arc::new(store)
+        }
+        None => Arc::new(MemoryChunkStore::new()),
+    };
+    Ok(ChunkManager::new(config.clone(), Arc::new(generator), decorator, textures.mesh_table(&registry), store))
+}
+
+fn save_chunks(_: &mut World, res_manager: &mut ResManager) {
+    let (Some(mut manager), Some(voxels)) = (res_manager.borrow_res_mut::<ChunkManager>(), res_manager.borrow_res::<VoxelWorld>()) else {
+        return;
+    };
+    log::info!("Saved {} modified chunks", manager.save_all(&voxels));
+}
+
+fn update_chunk_streaming(world: &mut World, res_manager: &mut ResManager) {
+    let Some(viewer) = Viewer::find(world, res_manager) else { return };
+    let (Some(mut manager), Some(mut voxels), Some(mut removals)) = (
+        res_manager.borrow_res_mut::<ChunkManager>(),
+        res_manager.borrow_res_mut::<VoxelWorld>(),
+        res_manager.borrow_res_mut::<Removals>(),
+    ) else {
+        return;
+    };
+    manager.update(world, &mut removals, &mut voxels, &viewer);
+    if let Some(device) = res_manager.borrow_res::<RenderDevice>() {
+        manager.upload_meshes(world, &mut removals, |it| it.upload(&device.device));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use cgmath::Vector3;
+    use hecs::World;
+    use crate::ecs::removal::Removals;
+    use crate::task::TaskPool;
+    use crate::voxel::block::BlockId;
+    use crate::voxel::chunk::{Chunk, CHUNK_SIZE, CHUNK_VOLUME, ChunkPos, local_position};
+    use crate::voxel::decoration::{Decorator, Feature, FeatureContext, Replace, Rng};
+    use crate::voxel::IVec3;
+    use crate::voxel::mesher::{BlockMeshInfo, ChunkMesh, MeshTable};
+    use crate::voxel::registry::RenderLayer;
+    use crate::voxel::storage::MemoryChunkStore;
+    use crate::voxel::streaming::{ChunkManager, ChunkState, StreamingConfig, Viewer};
+    use crate::voxel::terrain::TerrainGenerator;
+    use crate::voxel::world::VoxelWorld;
+
+    const STONE: BlockId = BlockId(1);
+
+    /// Stone below height `8`.
+    struct Flat;
+
+    impl TerrainGenerator for Flat {
+        fn generate(&self, pos: ChunkPos) -> Chunk {
+            let mut chunk = Chunk::new();
+            for index in 0..CHUNK_VOLUME {
+                if pos.block(local_position(index)).y < 8 {
+                    chunk.set(local_position(index), STONE);
+                }
+            }
+            chunk
+        }
+    }
+
+    /// Stone in the air of the next chunk along x.
+    struct Marker;
+
+    impl Feature for Marker {
+        fn place(&self, context: &mut FeatureContext, _: &mut Rng) {
+            context.set(context.pos.origin() + IVec3::new(CHUNK_SIZE, 20, 0), STONE, Replace::Air);
+        }
+    }
+
+    fn table() -> MeshTable {
+        let stone = BlockMeshInfo { render_layer: RenderLayer::Opaque, opaque: true, layers: [0; 6] };
+        MeshTable::from_blocks(vec![BlockMeshInfo::INVISIBLE, stone])
+    }
+
+    /// Update until `done`, uploading one mesh per frame.
+    fn run_until(manager: &mut ChunkManager, world: &mut World, voxels: &mut VoxelWorld, viewer: &Viewer, done: impl Fn(&ChunkManager) -> bool) {
+        let mut removals = Removals::new();
+        let start = Instant::now();
+        while !done(manager) {
+            assert!(start.elapsed() < Duration::from_secs(10), "Chunks did not finish streaming");
+            manager.update(world, &mut removals, voxels, viewer);
+            manager.upload_meshes(world, &mut removals, |_| ChunkMesh::default());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_stream_chunks_around_viewer() {
+        let config = StreamingConfig { horizontal_radius: 2, vertical_radius: 1, max_jobs: 4, max_uploads_per_frame: 1, ..Default::default() };
+        let store = Arc::new(MemoryChunkStore::new());
+        let mut manager = ChunkManager::with_pool(
+            config, Arc::new(Flat), Decorator::new(0).with_feature(Marker), table(), store.clone(), TaskPool::new("Test Chunk Worker", 2),
+        );
+        let (mut world, mut voxels) = (World::new(), VoxelWorld::new());
+        let here = Viewer::new(Vector3::new(16.0, 16.0, 16.0), Vector3::new(0.0, 0.0, -1.0));
+
+        // The center chunk is meshed once its neighbors are loaded, the others only load
+        let center = ChunkPos::new(0, 0, 0);
+        run_until(&mut manager, &mut world, &mut voxels, &here, |it| it.state(center) == Some(ChunkState::Ready));
+        run_until(&mut manager, &mut world, &mut voxels, &here, |it| it.jobs() == 0);
+        // Columns within two chunks horizontally, one chunk above and below
+        assert_eq!(voxels.chunks().count(), 13 * 3);
+        assert_eq!(manager.states().filter(|(_, state)| *state == ChunkState::Loaded).count(), 13 * 3 - 1);
+        assert_eq!(world.len(), 1);
+        let marker = IVec3::new(CHUNK_SIZE, 20, 0);
+        assert_eq!(voxels.get_block(marker), Some(STONE));
+
+        // Chunks remeshed after edits are meshed and uploaded by the next update
+        assert!(manager.remesh(center));
+        let mut removals = Removals::new();
+        manager.update(&mut world, &mut removals, &mut voxels, &here);
+        assert_eq!(manager.state(center), Some(ChunkState::Meshing));
+        manager.upload_meshes(&mut world, &mut removals, |_| ChunkMesh::default());
+        assert_eq!(manager.state(center), Some(ChunkState::Ready));
+        assert!(!manager.remesh(ChunkPos::new(0, 5, 0)));
+
+        // A new mesh table remeshes the loaded chunks
+        manager.set_mesh_table(table());
+        manager.update(&mut world, &mut removals, &mut voxels, &here);
+        assert_eq!(manager.state(center), Some(ChunkState::Meshing));
+        run_until(&mut manager, &mut world, &mut voxels, &here, |it| it.state(center) == Some(ChunkState::Ready));
+
+        // Modified chunks are saved on demand, and when the viewer leaves, then loaded back when it returns
+        let below = ChunkPos::new(0, -1, 0);
+        voxels.set_block(below.block(IVec3::new(3, CHUNK_SIZE - 1, 3)), BlockId::AIR);
+        assert!(manager.mark_modified(below));
+        assert!(manager.mark_modified(center));
+        assert_eq!(manager.save_all(&voxels), 2);
+        assert_eq!(manager.save_all(&voxels), 0);
+        voxels.set_block(below.block(IVec3::new(5, CHUNK_SIZE - 1, 3)), BlockId::AIR);
+        assert!(manager.mark_modified(below));
+        let away = Viewer::new(Vector3::new(16.0, 16.0, 16.0 + 8.0 * CHUNK_SIZE as f32), Vector3::new(0.0, 0.0, 1.0));
+        manager.update(&mut world, &mut removals, &mut voxels, &away);
+        assert_eq!(manager.state(below), None);
+        assert_eq!(store.len(), 2);
+        assert_eq!(world.len(), 0);
+        // Writes between chunks which both unloaded are forgotten
+        assert!(manager.spills.is_empty());
+
+        run_until(&mut manager, &mut world, &mut voxels, &here, |it| it.state(center) == Some(ChunkState::Ready));
+        assert_eq!(voxels.get_block(below.block(IVec3::new(3, CHUNK_SIZE - 1, 3))), Some(BlockId::AIR));
+        assert_eq!(voxels.get_block(below.block(IVec3::new(5, CHUNK_SIZE - 1, 3))), Some(BlockId::AIR));
+        assert_eq!(voxels.get_block(below.block(IVec3::new(4, CHUNK_SIZE - 1, 3))), Some(STONE));
+        // The stored center still writes to its neighbor, which was generated again
+        assert_eq!(voxels.get_block(marker), Some(STONE));
+    }
+}
@@ -0,0 +1,372 @@
+use anyhow::anyhow;
+use noise::{Fbm, MultiFractal, NoiseFn, OpenSimplex, Perlin, RidgedMulti};
+use crate::voxel::block::BlockId;
+use crate::voxel::chunk::{Chunk, CHUNK_SIZE, ChunkPos};
+use crate::voxel::IVec3;
+use crate::voxel::registry::BlockRegistry;
+
+/// Blocks of dirt, or sand, below the surface block before stone.
+const SUBSURFACE_DEPTH: i32 = 3;
+
+/// Generates the blocks of chunks.
+/// # Explanation
+/// Generation must be pure: the chunk at a [`ChunkPos`] only depends on the generator and the position,
+/// never on which chunks were generated before or on which thread, so chunks can be generated in any order
+/// and regenerated instead of saved while unmodified.
+pub trait TerrainGenerator: Send + Sync {
+    fn generate(&self, pos: ChunkPos) -> Chunk;
+
+    /// Generated block at the world position `position`, the same as in its chunk.
+    /// Generates the whole chunk unless the generator has a quicker way.
+    fn block(&self, position: IVec3) -> BlockId {
+        let (pos, local) = ChunkPos::split(position);
+        self.generate(pos).get(local)
+    }
+
+    /// Biome of the column at `(x, z)`, `None` for terrain without biomes.
+    fn biome(&self, x: i32, z: i32) -> Option<Biome> {
+        let _ = (x, z);
+        None
+    }
+}
+
+/// Climate of a column, choosing its surface blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Forest,
+    Desert,
+    Tundra,
+}
+
+/// Blocks placed by [`NoiseTerrain`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TerrainBlocks {
+    pub stone: BlockId,
+    pub dirt: BlockId,
+    pub grass: BlockId,
+    pub sand: BlockId,
+    pub gravel: BlockId,
+    pub snow: BlockId,
+    pub water: BlockId,
+    pub bedrock: BlockId,
+}
+
+impl TerrainBlocks {
+    /// Ids of the built-in blocks, failing if one of them is not registered.
+    pub fn from_registry(registry: &BlockRegistry) -> anyhow::Result<Self> {
+        let id = |name: &str| registry.id(name).ok_or_else(|| anyhow!("Block '{}' is not registered", name));
+        Ok(Self {
+            stone: id("terre:stone")?,
+            dirt: id("terre:dirt")?,
+            grass: id("terre:grass")?,
+            sand: id("terre:sand")?,
+            gravel: id("terre:gravel")?,
+            snow: id("terre:snow")?,
+            water: id("terre:water")?,
+            bedrock: id("terre:bedrock")?,
+        })
+    }
+}
+
+/// Shape of the terrain of [`NoiseTerrain`], heights in blocks.
+#[derive(Clone, Debug)]
+pub struct TerrainConfig {
+    pub seed: u32,
+    /// Air at or below this height is water.
+    pub sea_level: i32,
+    /// Height of continents above, and of ocean floors below, the sea level.
+    pub continent_amplitude: f64,
+    pub hill_amplitude: f64,
+    /// Height of mountain ridges above the continents.
+    pub mountain_amplitude: f64,
+    /// How far the 3D density moves the surface, carving overhangs and floating bits near it.
+    pub overhang_amplitude: f64,
+    /// Width of cave tunnels, `0` for no caves.
+    pub cave_size: f64,
+    /// Every block at or below this height is bedrock.
+    pub bedrock_level: i32,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            sea_level: 0,
+            continent_amplitude: 24.0,
+            hill_amplitude: 8.0,
+            mountain_amplitude: 64.0,
+            overhang_amplitude: 6.0,
+            cave_size: 0.06,
+            bedrock_level: -64,
+        }
+    }
+}
+
+/// Seed of one of the noises of a world, so they differ from each other.
+/// Kept below `2³¹` since fractal noises seed their octaves with the following seeds.
+fn derive_seed(seed: u32, salt: u32) -> u32 {
+    (seed ^ salt).wrapping_mul(0x9E37_79B1) >> 1
+}
+
+/// Height and biome of a column of blocks.
+#[derive(Copy, Clone, Debug)]
+struct Column {
+    height: f64,
+    biome: Biome,
+}
+
+/// Default [`TerrainGenerator`]: continents, hills and mountain ridges with overhangs, caves, oceans and biomes.
+/// # Explanation
+/// A column starts from a 2D height, the sum of fractal Perlin continents, OpenSimplex hills,
+/// and ridged mountains rising only on land.
+/// A block is solid when its height below the surface, moved by up to
+/// [`overhang_amplitude`](TerrainConfig::overhang_amplitude) of 3D noise, is positive;
+/// blocks further from the surface skip sampling the 3D noise.
+/// Caves carve tunnels where two 3D noises are both close to zero.
+///
+/// Temperature and humidity noises choose the [`Biome`] of each column, mountains getting colder with height.
+/// Every noise is seeded from [`TerrainConfig::seed`], so a seed always yields the same world.
+pub struct NoiseTerrain {
+    config: TerrainConfig,
+    blocks: TerrainBlocks,
+    continent: Fbm<Perlin>,
+    hills: Fbm<OpenSimplex>,
+    mountains: RidgedMulti<Perlin>,
+    density: Fbm<Perlin>,
+    caves: [Fbm<Perlin>; 2],
+    temperature: Fbm<Perlin>,
+    humidity: Fbm<Perlin>,
+}
+
+impl NoiseTerrain {
+    pub fn new(config: TerrainConfig, blocks: TerrainBlocks) -> Self {
+        let seed = config.seed;
+        let fbm = |salt, octaves, frequency| Fbm::<Perlin>::new(derive_seed(seed, salt))
+            .set_octaves(octaves)
+            .set_frequency(frequency)
+            .set_lacunarity(2.0);
+        Self {
+            continent: fbm(1, 5, 1.0 / 512.0),
+            hills: Fbm::<OpenSimplex>::new(derive_seed(seed, 2))
+                .set_octaves(4)
+                .set_frequency(1.0 / 96.0)
+                .set_lacunarity(2.0),
+            mountains: RidgedMulti::<Perlin>::new(derive_seed(seed, 3))
+                .set_octaves(5)
+                .set_frequency(1.0 / 384.0)
+                .set_lacunarity(2.0)
+                .set_persistence(0.5),
+            density: fbm(4, 3, 1.0 / 48.0),
+            caves: [fbm(5, 2, 1.0 / 64.0), fbm(6, 2, 1.0 / 64.0)],
+            temperature: fbm(7, 3, 1.0 / 1024.0),
+            humidity: fbm(8, 3, 1.0 / 1024.0),
+            config,
+            blocks,
+        }
+    }
+
+    pub fn config(&self) -> &TerrainConfig {
+        &self.config
+    }
+
+    /// Height of the surface at column `(x, z)` before overhangs and caves.
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        self.column(x, z).height.floor() as i32
+    }
+
+    fn column(&self, x: i32, z: i32) -> Column {
+        let point = [x as f64, z as f64];
+        let continent = self.continent.get(point);
+        let hills = self.hills.get(point);
+        // Ridges only rise on land, fading in from the coast
+        let land = ((continent - 0.05) / 0.3).clamp(0.0, 1.0);
+        let ridges = self.mountains.get(point).max(0.0);
+        let height = self.config.sea_level as f64
+            + continent * self.config.continent_amplitude
+            + hills * self.config.hill_amplitude
+            + ridges * land * land * self.config.mountain_amplitude;
+
+        let altitude = (height - self.config.sea_level as f64 - 32.0).max(0.0);
+        let temperature = self.temperature.get(point) - altitude / 64.0;
+        let humidity = self.humidity.get(point);
+        let biome = match (temperature, humidity) {
+            (t, _) if t < -0.25 => Biome::Tundra,
+            (t, h) if t > 0.2 && h < 0.0 => Biome::Desert,
+            (_, h) if h > 0.1 => Biome::Forest,
+            _ => Biome::Plains,
+        };
+        Column { height, biome }
+    }
+
+    /// Highest surface any column can have, overhangs included.
+    fn max_height(&self) -> f64 {
+        let config = &self.config;
+        config.sea_level as f64 + config.continent_amplitude + config.hill_amplitude
+            + config.mountain_amplitude + config.overhang_amplitude
+    }
+
+    fn is_solid(&self, position: IVec3, column: &Column) -> bool {
+        let depth = column.height - position.y as f64;
+        let amplitude = self.config.overhang_amplitude;
+        if depth.abs() >= amplitude {
+            return depth > 0.0;
+        }
+        depth + self.density.get(position.cast::<f64>().unwrap().into()) * amplitude > 0.0
+    }
+
+    fn is_cave(&self, position: IVec3, column: &Column) -> bool {
+        // Ocean floors stay sealed, so the sea does not hang over caves
+        if column.height < self.config.sea_level as f64 && position.y as f64 > column.height - 4.0 {
+            return false;
+        }
+        let point: [f64; 3] = position.cast::<f64>().unwrap().into();
+        self.caves.iter().all(|it| it.get(point).abs() < self.config.cave_size)
+    }
+
+    /// Pass the blocks of the column at `(x, z)` from height `bottom` up to `bottom + height`,
+    /// from the top down and skipping the air above ground, to `set` with their height.
+    fn generate_column(&self, x: i32, z: i32, bottom: i32, height: i32, mut set: impl FnMut(i32, BlockId)) {
+        let column = self.column(x, z);
+        let sea_level = self.config.sea_level;
+        // Start above the blocks, so the depth below the surface is known at the top
+        let mut depth = 0;
+        let mut surface = i32::MAX;
+        for y in (bottom..bottom + height + SUBSURFACE_DEPTH + 1).rev() {
+            let position = IVec3::new(x, y, z);
+            let solid = y <= self.config.bedrock_level || self.is_solid(position, &column);
+            if !solid {
+                depth = 0;
+                if y < bottom + height && y <= sea_level {
+                    set(y, self.blocks.water);
+                }
+                continue;
+            }
+            depth += 1;
+            if depth == 1 {
+                surface = y;
+            }
+            if y >= bottom + height {
+                continue;
+            }
+
+            let block = if y <= self.config.bedrock_level {
+                self.blocks.bedrock
+            } else if self.config.cave_size > 0.0 && self.is_cave(position, &column) {
+                BlockId::AIR
+            } else if depth == 1 {
+                self.surface(&column, surface).0
+            } else if depth <= SUBSURFACE_DEPTH + 1 {
+                self.surface(&column, surface).1
+            } else {
+                self.blocks.stone
+            };
+            set(y, block);
+        }
+    }
+
+    /// Surface block of a column, and the blocks below it.
+    fn surface(&self, column: &Column, surface: i32) -> (BlockId, BlockId) {
+        let blocks = &self.blocks;
+        let sea_level = self.config.sea_level;
+        match column.biome {
+            Biome::Tundra => (blocks.snow, blocks.dirt),
+            _ if surface < sea_level - 4 => (blocks.gravel, blocks.gravel),
+            _ if surface <= sea_level + 1 => (blocks.sand, blocks.sand),
+            Biome::Desert => (blocks.sand, blocks.sand),
+            Biome::Plains | Biome::Forest => (blocks.grass, blocks.dirt),
+        }
+    }
+}
+
+impl TerrainGenerator for NoiseTerrain {
+    fn generate(&self, pos: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new();
+        let origin = pos.origin();
+        let sea_level = self.config.sea_level;
+        if origin.y as f64 > self.max_height() && origin.y > sea_level {
+            return chunk;
+        }
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                self.generate_column(origin.x + x, origin.z + z, origin.y, CHUNK_SIZE, |y, block| {
+                    chunk.set(IVec3::new(x, y - origin.y, z), block);
+                });
+            }
+        }
+        chunk.compact();
+        chunk
+    }
+
+    fn block(&self, position: IVec3) -> BlockId {
+        if position.y as f64 > self.max_height() && position.y > self.config.sea_level {
+            return BlockId::AIR;
+        }
+        let mut block = BlockId::AIR;
+        self.generate_column(position.x, position.z, position.y, 1, |_, it| block = it);
+        block
+    }
+
+    fn biome(&self, x: i32, z: i32) -> Option<Biome> {
+        Some(self.column(x, z).biome)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use crate::voxel::block::BlockId;
+    use crate::voxel::chunk::{Chunk, CHUNK_VOLUME, ChunkPos};
+    use crate::voxel::IVec3;
+    use crate::voxel::terrain::{NoiseTerrain, TerrainBlocks, TerrainConfig, TerrainGenerator};
+
+    const BLOCKS: TerrainBlocks = TerrainBlocks {
+        stone: BlockId(1),
+        dirt: BlockId(2),
+        grass: BlockId(3),
+        sand: BlockId(4),
+        gravel: BlockId(5),
+        snow: BlockId(6),
+        water: BlockId(7),
+        bedrock: BlockId(8),
+    };
+
+    /// FNV-1a of the blocks of `chunk`.
+    fn digest(chunk: &Chunk) -> u64 {
+        (0..CHUNK_VOLUME).fold(0xcbf2_9ce4_8422_2325, |hash, it| {
+            (hash ^ chunk.blocks().get(it).0 as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    fn terrain(seed: u32) -> NoiseTerrain {
+        NoiseTerrain::new(TerrainConfig { seed, ..Default::default() }, BLOCKS)
+    }
+
+    #[test]
+    fn test_generation_is_deterministic() {
+        let positions = [ChunkPos::new(0, 0, 0), ChunkPos::new(3, -1, -2), ChunkPos::new(-5, 0, 7)];
+        let first = positions.map(|it| digest(&terrain(42).generate(it)));
+        // Another generator with the same seed, generating in another order
+        let generator = terrain(42);
+        let second = positions.into_iter().rev().map(|it| digest(&generator.generate(it))).collect::<Vec<_>>();
+        assert_eq!(first.iter().rev().copied().collect::<Vec<_>>(), second);
+        assert_eq!(first, SNAPSHOT);
+        assert_ne!(first, positions.map(|it| digest(&terrain(43).generate(it))));
+
+        // Far above is air, far below is stone over bedrock
+        assert!(generator.generate(ChunkPos::new(0, 10, 0)).is_air());
+        let deep = generator.generate(ChunkPos::new(0, -2, 0));
+        let blocks = (0..CHUNK_VOLUME).map(|it| deep.blocks().get(it)).collect::<HashSet<_>>();
+        assert!(blocks.contains(&BLOCKS.stone) && blocks.contains(&BLOCKS.bedrock));
+
+        // Single blocks match their chunk
+        let chunk = generator.generate(positions[1]);
+        for local in [IVec3::new(0, 0, 0), IVec3::new(7, 31, 19), IVec3::new(31, 12, 4)] {
+            assert_eq!(generator.block(positions[1].block(local)), chunk.get(local));
+        }
+    }
+
+    /// Digests of the chunks of the test with seed 42, to update only when generation changes on purpose.
+    const SNAPSHOT: [u64; 3] = [1234427343357710938, 16167196890064786655, 11148637596659861285];
+}
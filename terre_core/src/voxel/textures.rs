@@ -0,0 +1,237 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Arc;
+use hecs::World;
+use terre_core_macros::Resource;
+use crate::app::{App, Plugin};
+use crate::asset::io::AssetIo;
+use crate::asset::server::AssetServer;
+use crate::ecs::resource::ResManager;
+use crate::render::extract::extract_resource;
+use crate::render::texture::TextureArrayBuilder;
+use crate::schedule::Stage;
+use crate::voxel::block::Face;
+use crate::voxel::mesher::{MeshTable, MISSING_TEXTURE_LAYER};
+use crate::voxel::registry::BlockRegistry;
+use crate::voxel::streaming::ChunkManager;
+
+/// Default directory of block textures under the asset root, a texture named `stone` is `stone.png` in it.
+pub const BLOCK_TEXTURE_DIR: &str = "textures/blocks";
+/// Name of the generated texture at [`MISSING_TEXTURE_LAYER`].
+pub const MISSING_TEXTURE: &str = "terre:missing";
+/// Size of the missing texture when no block texture could be loaded.
+const DEFAULT_TEXTURE_SIZE: u32 = 16;
+
+/// Face textures of every registered block, as layers of one texture array drawn by
+/// [`VoxelPass`](crate::render::pass::voxel::VoxelPass).
+/// # Usage
+/// Mesh chunks with the [`MeshTable`] of [`#mesh_table`](BlockTextures::mesh_table), so faces refer to the layers of
+/// their textures.
+/// # Explanation
+/// Images are shared by clones, so the resource is cheap to extract for rendering.
+#[derive(Resource, Clone)]
+pub struct BlockTextures {
+    array: Arc<TextureArrayBuilder>,
+}
+
+impl BlockTextures {
+    /// Load the textures named by the blocks of `registry` from `dir`.
+    /// Textures failing to load, or not the size most textures have, are logged and drawn as the missing texture.
+    pub fn load(registry: &BlockRegistry, io: &dyn AssetIo, dir: &str) -> Self {
+        let mut names = registry.iter()
+            .flat_map(|(_, properties)| Face::ALL.map(|face| properties.texture(face)))
+            .flatten()
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+
+        let images = names.into_iter()
+            .filter_map(|name| {
+                let path = format!("{}/{}.png", dir, name);
+                match io.read(&path).and_then(|it| Ok(image::load_from_memory(&it)?)) {
+                    Ok(image) => Some((name, image)),
+                    Err(err) => {
+                        log::warn!("Failed to load block texture '{}': {}", path, err);
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // The most common size wins, the smallest one among equally common sizes
+        let mut sizes: HashMap<(u32, u32), usize> = HashMap::new();
+        images.iter().for_each(|(_, image)| *sizes.entry((image.width(), image.height())).or_default() += 1);
+        let (width, height) = sizes.into_iter()
+            .max_by_key(|(size, count)| (*count, Reverse(*size)))
+            .map_or((DEFAULT_TEXTURE_SIZE, DEFAULT_TEXTURE_SIZE), |(size, _)| size);
+        let mut array = TextureArrayBuilder::new();
+        let missing = array.add(MISSING_TEXTURE, &missing_texture(width, height)).unwrap();
+        debug_assert_eq!(missing, MISSING_TEXTURE_LAYER);
+        for (name, image) in images {
+            if let Err(err) = array.add(name, &image) {
+                log::warn!("Skipped block texture: {}", err);
+            }
+        }
+        Self { array: Arc::new(array) }
+    }
+
+    /// Layer of the texture `name`, `None` if it was not loaded.
+    pub fn layer(&self, name: &str) -> Option<u32> {
+        self.array.layer(name)
+    }
+
+    pub fn array(&self) -> &TextureArrayBuilder {
+        &self.array
+    }
+
+    pub fn mesh_table(&self, registry: &BlockRegistry) -> MeshTable {
+        MeshTable::new(registry, |it| self.layer(it))
+    }
+
+    /// Whether both have every texture at the same layer, so meshes of one are valid for the other.
+    pub fn same_layers(&self, other: &BlockTextures) -> bool {
+        self.array.len() == other.array.len() && self.array.layers().all(|(name, layer)| other.layer(name) == Some(layer))
+    }
+
+    /// Whether both are clones of the same load.
+    pub fn ptr_eq(&self, other: &BlockTextures) -> bool {
+        Arc::ptr_eq(&self.array, &other.array)
+    }
+}
+
+/// Magenta and black checkerboard of two by two squares.
+fn missing_texture(width: u32, height: u32) -> image::DynamicImage {
+    let image = image::RgbaImage::from_fn(width, height, |x, y| match (x * 2 / width + y * 2 / height) % 2 {
+        0 => image::Rgba([255, 0, 255, 255]),
+        _ => image::Rgba([0, 0, 0, 255]),
+    });
+    image::DynamicImage::ImageRgba8(image)
+}
+
+/// Adds [`BlockTextures`] loaded through the [`AssetServer`] on start, and extracts them for rendering.
+/// They are loaded again when a file of `dir` changes while the server watches, and the chunks of [`ChunkManager`]
+/// are remeshed if layers moved.
+/// Needs [`AssetPlugin`](crate::asset::AssetPlugin), and to be added after
+/// [`BlockRegistryPlugin`](crate::voxel::registry::BlockRegistryPlugin) so every block is registered.
+pub struct BlockTexturesPlugin {
+    pub dir: String,
+}
+
+impl Default for BlockTexturesPlugin {
+    fn default() -> Self {
+        Self { dir: BLOCK_TEXTURE_DIR.to_string() }
+    }
+}
+
+impl Plugin for BlockTexturesPlugin {
+    fn build(&self, app: App) -> App {
+        let dir = self.dir.clone();
+        app.add_system(Stage::Start, move |_: &mut World, res_manager: &mut ResManager| load_block_textures(&dir, res_manager))
+            .add_system(Stage::AssetUpload, {
+                let dir = self.dir.clone();
+                move |_: &mut World, res_manager: &mut ResManager| reload_block_textures(&dir, res_manager)
+            })
+            .add_extract(extract_resource::<BlockTextures>)
+    }
+}
+
+fn load_block_textures(dir: &str, res_manager: &mut ResManager) {
+    let textures = {
+        let (Some(server), Some(registry)) = (res_manager.borrow_res::<AssetServer>(), res_manager.borrow_res::<BlockRegistry>()) else {
+            log::error!("Failed to load block textures from '{}', there is no asset server or block registry", dir);
+            return;
+        };
+        BlockTextures::load(&registry, server.io(), dir)
+    };
+    log::info!("Loaded {} block textures from '{}'", textures.array().len() - 1, dir);
+    res_manager.push_res(textures).unwrap();
+}
+
+fn reload_block_textures(dir: &str, res_manager: &mut ResManager) {
+    let textures = {
+        let (Some(server), Some(registry)) = (res_manager.borrow_res::<AssetServer>(), res_manager.borrow_res::<BlockRegistry>()) else {
+            return;
+        };
+        let prefix = format!("{}/", dir);
+        if !server.changed_paths().iter().any(|it| it.starts_with(&prefix)) {
+            return;
+        }
+        let textures = BlockTextures::load(&registry, server.io(), dir);
+        let moved = res_manager.borrow_res::<BlockTextures>().is_some_and(|it| !it.same_layers(&textures));
+        if moved {
+            if let Some(mut manager) = res_manager.borrow_res_mut::<ChunkManager>() {
+                manager.set_mesh_table(textures.mesh_table(&registry));
+            }
+        }
+        textures
+    };
+    log::info!("Reloaded {} block textures from '{}'", textures.array().len() - 1, dir);
+    if let Some(mut it) = res_manager.borrow_res_mut::<BlockTextures>() {
+        **it = textures;
+        return;
+    }
+    res_manager.push_res(textures).unwrap();
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+    use crate::asset::io::MemoryAssetIo;
+    use crate::voxel::block::Face;
+    use crate::voxel::mesher::MISSING_TEXTURE_LAYER;
+    use crate::voxel::registry::BlockRegistry;
+    use crate::voxel::textures::BlockTextures;
+
+    fn png(size: u32) -> Vec<u8> {
+        let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(size, size, image::Rgba([90, 90, 90, 255])));
+        let mut bytes = Cursor::new(vec![]);
+        image.write_to(&mut bytes, image::ImageOutputFormat::Png).unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    fn test_block_texture_layers() {
+        let mut registry = BlockRegistry::new();
+        registry.load_json(r#"{ "blocks": [
+            { "id": "terre:grass", "textures": { "all": "dirt", "side": "grass_side", "top": "grass_top" } },
+            { "id": "terre:dirt", "textures": { "all": "dirt" } },
+            { "id": "terre:big", "textures": { "all": "big" } }
+        ] }"#).unwrap();
+        let io = MemoryAssetIo::new()
+            .with("blocks/dirt.png", png(16))
+            .with("blocks/grass_side.png", png(16))
+            .with("blocks/big.png", png(32));
+        let textures = BlockTextures::load(&registry, &io, "blocks");
+
+        // The missing texture, then loaded textures by name
+        assert_eq!(textures.array().len(), 3);
+        assert_eq!(textures.array().size(), Some((16, 16)));
+        let table = textures.mesh_table(&registry);
+        let grass = table.get(registry.id("terre:grass").unwrap());
+        let dirt = textures.layer("dirt").unwrap();
+        assert_eq!(grass.layers[Face::NegY.index()], dirt);
+        assert_eq!(grass.layers[Face::PosX.index()], textures.layer("grass_side").unwrap());
+        assert_eq!(grass.layers[Face::PosY.index()], MISSING_TEXTURE_LAYER);
+        assert_eq!(table.get(registry.id("terre:big").unwrap()).layers, [MISSING_TEXTURE_LAYER; 6]);
+    }
+
+    #[test]
+    fn test_reloaded_block_texture_layers() {
+        let mut registry = BlockRegistry::new();
+        registry.load_json(r#"{ "blocks": [
+            { "id": "terre:dirt", "textures": { "all": "dirt" } },
+            { "id": "terre:stone", "textures": { "all": "stone" } }
+        ] }"#).unwrap();
+        let io = MemoryAssetIo::new().with("blocks/stone.png", png(16));
+        let textures = BlockTextures::load(&registry, &io, "blocks");
+
+        // Editing a texture keeps the layers, adding one moves them
+        let edited = BlockTextures::load(&registry, &io, "blocks");
+        assert!(textures.same_layers(&edited));
+        assert!(!textures.ptr_eq(&edited));
+        assert!(textures.ptr_eq(&textures.clone()));
+        let io = io.with("blocks/dirt.png", png(16));
+        let added = BlockTextures::load(&registry, &io, "blocks");
+        assert!(!textures.same_layers(&added));
+    }
+}
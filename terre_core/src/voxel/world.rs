@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use terre_core_macros::Resource;
+use crate::voxel::block::BlockId;
+use crate::voxel::chunk::{Chunk, ChunkPos};
+use crate::voxel::IVec3;
+
+/// Loaded chunks of the voxel world, as a resource.
+/// # Usage
+/// Read and write blocks by their world position with [`#get_block`](VoxelWorld::get_block)
+/// and [`#set_block`](VoxelWorld::set_block), both of which only reach loaded chunks.
+#[derive(Resource, Default)]
+pub struct VoxelWorld {
+    chunks: HashMap<ChunkPos, Chunk>,
+}
+
+impl VoxelWorld {
+    pub fn new() -> Self {
+        Self { chunks: HashMap::new() }
+    }
+
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub fn chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&pos)
+    }
+
+    /// Load `chunk` at `pos`, returning the chunk it replaces.
+    pub fn insert_chunk(&mut self, pos: ChunkPos, chunk: Chunk) -> Option<Chunk> {
+        self.chunks.insert(pos, chunk)
+    }
+
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        self.chunks.remove(&pos)
+    }
+
+    pub fn contains_chunk(&self, pos: ChunkPos) -> bool {
+        self.chunks.contains_key(&pos)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (&ChunkPos, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    /// Block at `block`, `None` if its chunk is not loaded.
+    pub fn get_block(&self, block: IVec3) -> Option<BlockId> {
+        let (pos, local) = ChunkPos::split(block);
+        self.chunks.get(&pos).map(|it| it.get(local))
+    }
+
+    /// Set the block at `block` and return the one replaced, `None` without change if its chunk is not loaded.
+    pub fn set_block(&mut self, block: IVec3, id: BlockId) -> Option<BlockId> {
+        let (pos, local) = ChunkPos::split(block);
+        self.chunks.get_mut(&pos).map(|it| it.set(local, id))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::voxel::block::BlockId;
+    use crate::voxel::chunk::{Chunk, ChunkPos};
+    use crate::voxel::IVec3;
+    use crate::voxel::world::VoxelWorld;
+
+    #[test]
+    fn test_world_blocks() {
+        let mut world = VoxelWorld::new();
+        let block = IVec3::new(-1, -1, -1);
+        assert_eq!(world.set_block(block, BlockId(1)), None);
+        assert_eq!(world.get_block(block), None);
+
+        world.insert_chunk(ChunkPos::new(-1, -1, -1), Chunk::new());
+        assert_eq!(world.set_block(block, BlockId(1)), Some(BlockId::AIR));
+        assert_eq!(world.get_block(block), Some(BlockId(1)));
+        assert_eq!(world.chunk(ChunkPos::new(-1, -1, -1)).unwrap().get(IVec3::new(31, 31, 31)), BlockId(1));
+        assert_eq!(world.get_block(IVec3::new(0, 0, 0)), None);
+    }
+}